@@ -5,5 +5,5 @@ set -x
 
 cargo +nightly fmt
 cargo test
-cargo run
+cargo run -- run samples/basic.lil
 cargo clippy
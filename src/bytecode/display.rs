@@ -0,0 +1,156 @@
+use std::fmt::Display;
+
+use super::{
+    ByteCode, ConstValue, Expression, FunctionDefinition, Identifier, Import, ModuleDefinition,
+    Value,
+};
+
+// The output of these impls is the textual format accepted by `parser::parse`, so a dump can
+// always be fed back into the compiler.
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "${}", self.0)
+    }
+}
+
+impl Display for ConstValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::U64(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Literal(value) => write!(f, "{value}"),
+            Self::Local(identifier) => write!(f, "{identifier}"),
+            Self::Computed(expression) => write!(f, "{expression}"),
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Assignment(binding, value) => write!(f, "(let {binding} {value})"),
+            Self::Add(left, right) => write!(f, "(add {left} {right})"),
+            Self::Call(name, arguments) => {
+                write!(f, "(call {name}")?;
+                for argument in arguments {
+                    write!(f, " {argument}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl Display for FunctionDefinition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(fn {} (", self.name)?;
+        for (index, argument) in self.arguments.iter().enumerate() {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{argument}")?;
+        }
+        write!(f, ")")?;
+
+        for instruction in &self.instructions {
+            write!(f, "\n    {instruction}")?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+impl Display for Import {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "(import {} {} {})",
+            self.module, self.function, self.arity
+        )
+    }
+}
+
+impl Display for ModuleDefinition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(module {}", self.name)?;
+
+        for import in &self.imports {
+            write!(f, "\n    {import}")?;
+        }
+
+        if !self.exports.is_empty() {
+            write!(f, "\n    (export {})", self.exports.join(" "))?;
+        }
+
+        for function in &self.functions {
+            for line in function.to_string().lines() {
+                write!(f, "\n    {line}")?;
+            }
+        }
+
+        write!(f, ")")
+    }
+}
+
+impl ModuleDefinition {
+    fn line_count(&self) -> usize {
+        1 + self.imports.len()
+            + usize::from(!self.exports.is_empty())
+            + self
+                .functions
+                .iter()
+                .map(|function| 1 + function.instructions.len())
+                .sum::<usize>()
+    }
+}
+
+impl Display for ByteCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for import in &self.imports {
+            writeln!(f, "{import}")?;
+        }
+
+        for module in &self.modules {
+            writeln!(f, "{module}")?;
+        }
+
+        for function in &self.functions {
+            writeln!(f, "{function}")?;
+        }
+
+        for instruction in &self.instructions {
+            writeln!(f, "{instruction}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ByteCode {
+    /// The lines of the listing where the functions of each of the modules start, followed by
+    /// the line where the functions outside of the modules start. The code generator needs them
+    /// for the debug info, as every module is compiled separately.
+    pub(crate) fn function_lines(&self) -> (Vec<u32>, u32) {
+        let mut line = 1 + self.imports.len();
+        let modules = self
+            .modules
+            .iter()
+            .map(|module| {
+                let first =
+                    line + 1 + module.imports.len() + usize::from(!module.exports.is_empty());
+                line += module.line_count();
+
+                u32::try_from(first).unwrap()
+            })
+            .collect();
+
+        (modules, u32::try_from(line).unwrap())
+    }
+}
@@ -0,0 +1,308 @@
+use super::{
+    ByteCode, ConstValue, Expression, FunctionDefinition, Identifier, Import, ModuleDefinition,
+    Value,
+};
+
+/// A small xorshift64* generator.
+///
+/// It's not suitable for anything but producing test inputs, but the same seed always produces
+/// the same sequence, on any platform, which is what makes the failures reproducible.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        // xorshift gets stuck on a zero state, so the seed gets scrambled with a splitmix64 step
+        // first, that way any seed (including 0) is fine
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+
+        Self {
+            state: if state == 0 { 1 } else { state },
+        }
+    }
+
+    pub const fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a number in `0..bound`.
+    ///
+    /// # Panics
+    ///
+    /// If `bound` is zero.
+    pub fn below(&mut self, bound: usize) -> usize {
+        // The modulo bias is irrelevant for the bounds used here
+        usize::try_from(self.next_u64() % bound as u64).unwrap()
+    }
+
+    /// Returns a number in `minimum..=maximum`.
+    pub fn between(&mut self, minimum: usize, maximum: usize) -> usize {
+        minimum + self.below(maximum - minimum + 1)
+    }
+
+    /// Returns true with the probability of `numerator / denominator`.
+    pub fn chance(&mut self, numerator: usize, denominator: usize) -> bool {
+        self.below(denominator) < numerator
+    }
+}
+
+/// The upper bounds on the size of the generated programs.
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratorOptions {
+    /// The number of modules, besides the functions outside of them
+    pub modules: usize,
+    /// The number of functions in each of the modules, and outside of them
+    pub functions: usize,
+    pub arguments: usize,
+    /// The number of instructions at the top level and in each of the function bodies, there's
+    /// always at least one, as they can't be empty
+    pub instructions: usize,
+    /// How deep can the values be nested in each other
+    pub depth: usize,
+}
+
+impl GeneratorOptions {
+    /// Options that scale all the bounds together, bigger sizes make bigger programs.
+    #[must_use]
+    pub fn sized(size: usize) -> Self {
+        Self {
+            modules: size.div_ceil(3),
+            functions: size,
+            arguments: size.min(4),
+            instructions: size.max(1),
+            depth: size.div_ceil(2),
+        }
+    }
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        Self::sized(6)
+    }
+}
+
+// How many calls can a single run of a function (or the top level) make, including the nested
+// ones, so that the programs finish quickly even with big sizes
+const CALL_BUDGET: usize = 10_000;
+
+/// Generates a random program that passes the validation.
+///
+/// Functions only ever call the functions defined before them, and the modules only import from
+/// the modules before them, so there's no recursion and all the programs terminate.
+pub fn generate(rng: &mut Rng, options: &GeneratorOptions) -> ByteCode {
+    let mut generator = Generator {
+        rng,
+        options,
+        functions: vec![],
+        exported: vec![],
+        scope: vec![],
+        calls: 0,
+    };
+
+    let module_count = generator.rng.between(0, options.modules);
+    let modules = (0..module_count)
+        .map(|index| generator.module(index))
+        .collect();
+
+    generator.functions.clear();
+    let imports = generator.imports();
+    let function_count = generator.rng.between(0, options.functions);
+    let functions = (0..function_count)
+        .map(|index| generator.function(format!("f{index}")))
+        .collect();
+
+    generator.scope.clear();
+    generator.calls = 0;
+    let instructions = generator.instructions();
+
+    ByteCode {
+        imports,
+        modules,
+        functions,
+        instructions,
+    }
+}
+
+struct Generator<'a> {
+    rng: &'a mut Rng,
+    options: &'a GeneratorOptions,
+    /// The functions the code that's being generated can call
+    functions: Vec<Callable>,
+    /// The functions exported by the modules generated so far, along with their module
+    exported: Vec<(String, Callable)>,
+    scope: Vec<Identifier>,
+    /// The number of calls a run of the code generated so far makes
+    calls: usize,
+}
+
+#[derive(Clone)]
+struct Callable {
+    name: String,
+    arity: usize,
+    /// The number of calls a single call to the function makes, including itself
+    calls: usize,
+}
+
+impl Generator<'_> {
+    fn module(&mut self, index: usize) -> ModuleDefinition {
+        let name = format!("m{index}");
+
+        self.functions.clear();
+        let imports = self.imports();
+        let function_count = self.rng.between(0, self.options.functions);
+        let functions: Vec<_> = (0..function_count)
+            .map(|function| self.function(format!("{name}_f{function}")))
+            .collect();
+
+        let exports: Vec<_> = functions
+            .iter()
+            .filter(|_| self.rng.chance(1, 2))
+            .map(|function| function.name.clone())
+            .collect();
+        // The functions of the module come after the imported ones
+        for callable in &self.functions[imports.len()..] {
+            if exports.contains(&callable.name) {
+                self.exported.push((name.clone(), callable.clone()));
+            }
+        }
+
+        ModuleDefinition {
+            name,
+            imports,
+            exports,
+            functions,
+        }
+    }
+
+    /// Imports some of the functions the modules so far export, making them callable.
+    fn imports(&mut self) -> Vec<Import> {
+        let mut imports = vec![];
+
+        for (module, callable) in &self.exported {
+            if self.rng.chance(1, 2) {
+                imports.push(Import {
+                    module: module.clone(),
+                    function: callable.name.clone(),
+                    arity: callable.arity,
+                });
+                self.functions.push(callable.clone());
+            }
+        }
+
+        imports
+    }
+
+    fn function(&mut self, name: String) -> FunctionDefinition {
+        let arity = self.rng.between(0, self.options.arguments);
+        let arguments: Vec<_> = (0..arity)
+            .map(|argument| Identifier::new(u32::try_from(argument).unwrap()))
+            .collect();
+
+        self.scope.clone_from(&arguments);
+        self.calls = 0;
+        let instructions = self.instructions();
+
+        // Added only after the body is generated, so the function can't call itself
+        self.functions.push(Callable {
+            name: name.clone(),
+            arity,
+            calls: self.calls + 1,
+        });
+
+        FunctionDefinition {
+            name,
+            arguments,
+            instructions,
+        }
+    }
+
+    fn instructions(&mut self) -> Vec<Expression> {
+        // Empty bodies are not valid, and empty programs have no result to compare
+        let count = self.rng.between(1, self.options.instructions.max(1));
+
+        (0..count)
+            .map(|_| self.expression(self.options.depth))
+            .collect()
+    }
+
+    fn expression(&mut self, depth: usize) -> Expression {
+        let kind = self
+            .rng
+            .below(if self.functions.is_empty() { 2 } else { 3 });
+
+        if kind == 0 {
+            let value = self.value(depth);
+            let binding = self.binding();
+            if !self.scope.contains(&binding) {
+                self.scope.push(binding);
+            }
+
+            return Expression::Assignment(binding, value);
+        }
+
+        if kind == 2 {
+            let callee = &self.functions[self.rng.below(self.functions.len())];
+
+            if self.calls + callee.calls <= CALL_BUDGET {
+                let name = callee.name.clone();
+                let arity = callee.arity;
+                self.calls += callee.calls;
+
+                let arguments = (0..arity).map(|_| self.value(depth)).collect();
+
+                return Expression::Call(name, arguments);
+            }
+        }
+
+        let left = self.value(depth);
+        let right = self.value(depth);
+
+        Expression::Add(left, right)
+    }
+
+    fn value(&mut self, depth: usize) -> Value {
+        match self.rng.below(if depth == 0 { 2 } else { 3 }) {
+            0 if !self.scope.is_empty() => {
+                Value::Local(self.scope[self.rng.below(self.scope.len())])
+            }
+            0 | 1 => Value::Literal(ConstValue::U64(self.literal())),
+            _ => Value::Computed(Box::new(self.expression(depth - 1))),
+        }
+    }
+
+    /// Either reassigns a local that's already in scope, or introduces a new one.
+    fn binding(&mut self) -> Identifier {
+        if !self.scope.is_empty() && self.rng.chance(1, 3) {
+            return self.scope[self.rng.below(self.scope.len())];
+        }
+
+        let next = self
+            .scope
+            .iter()
+            .map(|identifier| identifier.as_u32() + 1)
+            .max()
+            .unwrap_or(0);
+
+        Identifier::new(next)
+    }
+
+    fn literal(&mut self) -> u64 {
+        // Mostly small numbers, with a bias towards the ones around the overflow boundary
+        match self.rng.below(8) {
+            0 => 0,
+            1 => u64::MAX - self.rng.next_u64() % 16,
+            2 => self.rng.next_u64(),
+            _ => self.rng.next_u64() % 1000,
+        }
+    }
+}
@@ -1,23 +1,37 @@
+mod display;
+mod generator;
+mod parser;
+mod validation;
+
 use std::fmt::Debug;
 
+// Only for the fuzzer of the command line driver, not a part of the API
+#[doc(hidden)]
+pub use generator::{GeneratorOptions, Rng, generate};
+pub use parser::{ParseError, parse};
+pub use validation::{ValidationError, validate_functions, validate_modules};
+pub(crate) use validation::{import_arities, validate_functions_with_externals};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct Identifier(u32);
 
 impl Identifier {
-    // TODO this function shouldn't be so public, but we first need to really have interning to be
-    // able to avoid it
-    pub(crate) const fn new(id: u32) -> Self {
+    // TODO this function shouldn't be public, but we first need to really have interning to be able
+    // to avoid it, until then it's the only way for embedders to construct bytecode
+    #[must_use]
+    pub const fn new(id: u32) -> Self {
         Self(id)
     }
 
+    #[must_use]
     pub const fn as_u32(self) -> u32 {
         self.0
     }
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TypeTag {
     Primitive = 0,
 
@@ -43,14 +57,35 @@ impl TypeTag {
     }
 }
 
+/// The id of a type in the type store. The ids below `TypeId::RESERVED` are the ones of the
+/// `TypeTag`s, the rest are handed out by the type store as the types get registered.
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(transparent)]
-// TODO ensure this can only be constructed so that first 256 values represent TypeTag
 // TODO this might need to be converted into some more opaque concept, as some types have IDs that
 // only get created at runtime (otoh we can just convert the value, so no biggie?)
 pub struct TypeId(u32);
 
 impl TypeId {
+    /// The range of the ids that mirror the `TypeTag`s, whether they're used or not.
+    pub const RESERVED: u32 = 1 << u8::BITS;
+
+    /// The id of a type that's not one of the `TypeTag`s, `None` if it's in their range. The ids
+    /// of the tags come from converting them instead.
+    #[must_use]
+    pub const fn new(id: u32) -> Option<Self> {
+        if id < Self::RESERVED {
+            None
+        } else {
+            Some(Self(id))
+        }
+    }
+
+    /// For the ids read back from the runtime, which only ever stores valid ones.
+    pub(crate) const fn from_raw(id: u32) -> Self {
+        Self(id)
+    }
+
+    #[must_use]
     pub const fn as_u32(self) -> u32 {
         self.0
     }
@@ -58,50 +93,65 @@ impl TypeId {
 
 impl Debug for TypeId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Ok(tag) = u8::try_from(self.0) {
-            write!(f, "TypeId({:?})", TypeTag::from_value(tag).unwrap())
-        } else {
-            write!(f, "TypeTag({})", self.0)
+        match u8::try_from(self.0).ok().and_then(TypeTag::from_value) {
+            Some(tag) => write!(f, "TypeId({tag:?})"),
+            None => write!(f, "TypeId({})", self.0),
         }
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConstValue {
     U64(u64),
 }
 
+#[derive(Debug, Clone)]
 pub enum Value {
     Literal(ConstValue),
     Local(Identifier),
     Computed(Box<Expression>),
 }
 
+#[derive(Debug, Clone)]
 pub enum Expression {
     Assignment(Identifier, Value),
     Add(Value, Value),
+    Call(String, Vec<Value>),
 }
 
-// TODO this probably be at like function granularity or something
-pub struct ByteCode {
-    // TODO this probably shouldn't be pub
+// TODO the name should be interned, same as the identifiers
+#[derive(Debug, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub arguments: Vec<Identifier>,
     pub instructions: Vec<Expression>,
 }
 
-impl ByteCode {
-    pub fn new() -> Self {
-        Self {
-            instructions: vec![
-                Expression::Assignment(Identifier(1), Value::Literal(ConstValue::U64(100))),
-                Expression::Assignment(Identifier(2), Value::Literal(ConstValue::U64(10))),
-                Expression::Add(
-                    Value::Literal(ConstValue::U64(1)),
-                    Value::Computed(Box::new(Expression::Add(
-                        Value::Local(Identifier(1)),
-                        Value::Local(Identifier(2)),
-                    ))),
-                ),
-            ],
-        }
-    }
+/// Makes a function exported by another module callable under its own name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub module: String,
+    pub function: String,
+    /// The number of arguments the importing module expects the function to take, it has to
+    /// match the definition
+    pub arity: usize,
+}
+
+/// A named group of functions, compiled separately from the rest of the program. Only the
+/// exported functions can be imported by the other modules.
+#[derive(Debug, Clone)]
+pub struct ModuleDefinition {
+    pub name: String,
+    pub imports: Vec<Import>,
+    pub exports: Vec<String>,
+    pub functions: Vec<FunctionDefinition>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ByteCode {
+    // TODO these probably shouldn't be pub
+    pub imports: Vec<Import>,
+    pub modules: Vec<ModuleDefinition>,
+    pub functions: Vec<FunctionDefinition>,
+    pub instructions: Vec<Expression>,
 }
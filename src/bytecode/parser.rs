@@ -0,0 +1,431 @@
+use std::{fmt::Display, iter::Peekable, str::CharIndices};
+
+use super::{
+    ByteCode, ConstValue, Expression, FunctionDefinition, Identifier, Import, ModuleDefinition,
+    Value,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'source> {
+    Open,
+    Close,
+    Atom(&'source str),
+}
+
+struct Lexer<'source> {
+    source: &'source str,
+    characters: Peekable<CharIndices<'source>>,
+    position: Position,
+}
+
+impl<'source> Lexer<'source> {
+    fn new(source: &'source str) -> Self {
+        Self {
+            source,
+            characters: source.char_indices().peekable(),
+            position: Position { line: 1, column: 1 },
+        }
+    }
+
+    fn advance(&mut self) -> Option<(usize, char)> {
+        let (offset, character) = self.characters.next()?;
+
+        if character == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+
+        Some((offset, character))
+    }
+
+    fn skip_trivia(&mut self) {
+        while let Some(&(_, character)) = self.characters.peek() {
+            if character == ';' {
+                while self.advance().is_some_and(|(_, c)| c != '\n') {}
+            } else if character.is_whitespace() {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Option<(Position, Token<'source>)> {
+        self.skip_trivia();
+
+        let position = self.position;
+        let (start, character) = self.advance()?;
+
+        let token = match character {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            _ => {
+                let mut end = start + character.len_utf8();
+                while let Some(&(offset, character)) = self.characters.peek() {
+                    if character.is_whitespace() || matches!(character, '(' | ')' | ';') {
+                        break;
+                    }
+                    end = offset + character.len_utf8();
+                    self.advance();
+                }
+
+                Token::Atom(&self.source[start..end])
+            }
+        };
+
+        Some((position, token))
+    }
+}
+
+struct Parser<'source> {
+    lexer: Lexer<'source>,
+    peeked: Option<(Position, Token<'source>)>,
+}
+
+impl<'source> Parser<'source> {
+    fn error(position: Position, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: position.line,
+            column: position.column,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<&(Position, Token<'source>)> {
+        if self.peeked.is_none() {
+            self.peeked = self.lexer.next_token();
+        }
+
+        self.peeked.as_ref()
+    }
+
+    fn next(&mut self) -> Result<(Position, Token<'source>), ParseError> {
+        self.peek();
+
+        self.peeked
+            .take()
+            .ok_or_else(|| Self::error(self.lexer.position, "unexpected end of input"))
+    }
+
+    fn expect_close(&mut self) -> Result<(), ParseError> {
+        match self.next()? {
+            (_, Token::Close) => Ok(()),
+            (position, token) => Err(Self::error(
+                position,
+                format!("expected `)`, found {token}"),
+            )),
+        }
+    }
+
+    fn program(&mut self) -> Result<ByteCode, ParseError> {
+        let mut imports = vec![];
+        let mut modules = vec![];
+        let mut functions = vec![];
+        let mut instructions = vec![];
+
+        while self.peek().is_some() {
+            match self.next()? {
+                (_, Token::Open) => match self.peek() {
+                    Some((_, Token::Atom("fn"))) => {
+                        self.next()?;
+                        functions.push(self.function()?);
+                    }
+                    Some((_, Token::Atom("module"))) => {
+                        self.next()?;
+                        modules.push(self.module()?);
+                    }
+                    Some((_, Token::Atom("import"))) => {
+                        self.next()?;
+                        imports.push(self.import()?);
+                    }
+                    Some((position, Token::Atom("export"))) => {
+                        return Err(Self::error(*position, "only modules can export functions"));
+                    }
+                    _ => instructions.push(self.expression()?),
+                },
+                (position, token) => {
+                    return Err(Self::error(
+                        position,
+                        format!("expected an expression, found {token}"),
+                    ));
+                }
+            }
+        }
+
+        Ok(ByteCode {
+            imports,
+            modules,
+            functions,
+            instructions,
+        })
+    }
+
+    /// Parses the remainder of a module, the opening parenthesis and the `module` keyword must
+    /// already be consumed.
+    fn module(&mut self) -> Result<ModuleDefinition, ParseError> {
+        let (position, token) = self.next()?;
+        let name = Self::name(position, &token, "a module name")?;
+
+        let mut module = ModuleDefinition {
+            name,
+            imports: vec![],
+            exports: vec![],
+            functions: vec![],
+        };
+
+        loop {
+            match self.next()? {
+                (_, Token::Close) => break,
+                (_, Token::Open) => match self.next()? {
+                    (_, Token::Atom("fn")) => module.functions.push(self.function()?),
+                    (_, Token::Atom("import")) => module.imports.push(self.import()?),
+                    (_, Token::Atom("export")) => {
+                        while !matches!(self.peek(), Some((_, Token::Close))) {
+                            let (position, token) = self.next()?;
+                            module
+                                .exports
+                                .push(Self::name(position, &token, "a function name")?);
+                        }
+                        self.expect_close()?;
+                    }
+                    (position, token) => {
+                        return Err(Self::error(
+                            position,
+                            format!("expected a function, an import or an export, found {token}"),
+                        ));
+                    }
+                },
+                (position, token) => {
+                    return Err(Self::error(
+                        position,
+                        format!("expected a function, an import or an export, found {token}"),
+                    ));
+                }
+            }
+        }
+
+        Ok(module)
+    }
+
+    /// Parses the remainder of an import, the opening parenthesis and the `import` keyword must
+    /// already be consumed.
+    fn import(&mut self) -> Result<Import, ParseError> {
+        let (position, token) = self.next()?;
+        let module = Self::name(position, &token, "a module name")?;
+
+        let (position, token) = self.next()?;
+        let function = Self::name(position, &token, "a function name")?;
+
+        let arity = match self.next()? {
+            (position, Token::Atom(atom)) => atom
+                .parse()
+                .map_err(|_| Self::error(position, format!("invalid arity `{atom}`")))?,
+            (position, token) => {
+                return Err(Self::error(
+                    position,
+                    format!("expected an arity, found {token}"),
+                ));
+            }
+        };
+
+        self.expect_close()?;
+
+        Ok(Import {
+            module,
+            function,
+            arity,
+        })
+    }
+
+    /// Parses the remainder of a function definition, the opening parenthesis and the `fn`
+    /// keyword must already be consumed.
+    fn function(&mut self) -> Result<FunctionDefinition, ParseError> {
+        let (name_position, token) = self.next()?;
+        let name = Self::name(name_position, &token, "a function name")?;
+
+        match self.next()? {
+            (_, Token::Open) => {}
+            (position, token) => {
+                return Err(Self::error(
+                    position,
+                    format!("expected an argument list, found {token}"),
+                ));
+            }
+        }
+
+        let mut arguments = vec![];
+        loop {
+            match self.next()? {
+                (_, Token::Close) => break,
+                (position, Token::Atom(atom)) => arguments.push(Self::local(position, atom)?),
+                (position, token @ Token::Open) => {
+                    return Err(Self::error(
+                        position,
+                        format!("expected an argument, found {token}"),
+                    ));
+                }
+            }
+        }
+
+        let mut instructions = vec![];
+        loop {
+            match self.next()? {
+                (_, Token::Close) => break,
+                (_, Token::Open) => instructions.push(self.expression()?),
+                (position, token) => {
+                    return Err(Self::error(
+                        position,
+                        format!("expected an expression, found {token}"),
+                    ));
+                }
+            }
+        }
+
+        if instructions.is_empty() {
+            return Err(Self::error(
+                name_position,
+                format!("function `{name}` has an empty body"),
+            ));
+        }
+
+        Ok(FunctionDefinition {
+            name,
+            arguments,
+            instructions,
+        })
+    }
+
+    /// Parses the remainder of an expression, the opening parenthesis must already be consumed.
+    fn expression(&mut self) -> Result<Expression, ParseError> {
+        let (position, token) = self.next()?;
+        let Token::Atom(operator) = token else {
+            return Err(Self::error(
+                position,
+                format!("expected an operator, found {token}"),
+            ));
+        };
+
+        let expression = match operator {
+            "let" => {
+                let (position, token) = self.next()?;
+                let Token::Atom(atom) = token else {
+                    return Err(Self::error(
+                        position,
+                        format!("expected a local, found {token}"),
+                    ));
+                };
+
+                let binding = Self::local(position, atom)?;
+                Expression::Assignment(binding, self.value()?)
+            }
+            "add" => Expression::Add(self.value()?, self.value()?),
+            "call" => {
+                let (position, token) = self.next()?;
+                let name = Self::name(position, &token, "a function name")?;
+
+                let mut arguments = vec![];
+                while !matches!(self.peek(), Some((_, Token::Close))) {
+                    arguments.push(self.value()?);
+                }
+
+                Expression::Call(name, arguments)
+            }
+            _ => {
+                return Err(Self::error(
+                    position,
+                    format!("unknown operator `{operator}`"),
+                ));
+            }
+        };
+
+        self.expect_close()?;
+
+        Ok(expression)
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.next()? {
+            (_, Token::Open) => Ok(Value::Computed(Box::new(self.expression()?))),
+            (position, Token::Atom(atom)) if atom.starts_with('$') => {
+                Ok(Value::Local(Self::local(position, atom)?))
+            }
+            (position, Token::Atom(atom)) => atom
+                .parse()
+                .map(|value| Value::Literal(ConstValue::U64(value)))
+                .map_err(|_| Self::error(position, format!("invalid literal `{atom}`"))),
+            (position, token @ Token::Close) => Err(Self::error(
+                position,
+                format!("expected a value, found {token}"),
+            )),
+        }
+    }
+
+    fn name(position: Position, token: &Token, expected: &str) -> Result<String, ParseError> {
+        match token {
+            Token::Atom(atom)
+                if atom.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && atom.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+            {
+                Ok((*atom).to_string())
+            }
+            _ => Err(Self::error(
+                position,
+                format!("expected {expected}, found {token}"),
+            )),
+        }
+    }
+
+    fn local(position: Position, atom: &str) -> Result<Identifier, ParseError> {
+        atom.strip_prefix('$')
+            .and_then(|id| id.parse().ok())
+            .map(Identifier::new)
+            .ok_or_else(|| Self::error(position, format!("invalid local `{atom}`")))
+    }
+}
+
+impl Display for Token<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::Atom(atom) => write!(f, "`{atom}`"),
+        }
+    }
+}
+
+/// Parses the textual form of the bytecode, as printed by its `Display` impl.
+///
+/// # Errors
+///
+/// If the source is not well-formed, pointing at where the problem is. The result is not
+/// validated beyond its syntax.
+pub fn parse(source: &str) -> Result<ByteCode, ParseError> {
+    Parser {
+        lexer: Lexer::new(source),
+        peeked: None,
+    }
+    .program()
+}
@@ -0,0 +1,258 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    hash::BuildHasher,
+};
+
+use super::{ByteCode, Expression, FunctionDefinition, Identifier, Import, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    UndefinedLocal(Identifier),
+    UndefinedFunction(String),
+    DuplicateFunction(String),
+    EmptyFunction(String),
+    /// A standalone program has no instructions to compute its result with
+    EmptyProgram,
+    ArityMismatch {
+        function: String,
+        expected: usize,
+        actual: usize,
+    },
+    DuplicateModule(String),
+    UndefinedModule(String),
+    /// The export list of the module names a function it doesn't define
+    UndefinedExport {
+        module: String,
+        function: String,
+    },
+    /// The import names a function the module doesn't export
+    MissingExport {
+        module: String,
+        function: String,
+    },
+    /// The import expects the function to take a different number of arguments than it does
+    // Boxed to keep the errors small, they're carried around in pairs when comparing the
+    // interpreter and the code generator
+    ImportArityMismatch {
+        import: Box<Import>,
+        exported: usize,
+    },
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UndefinedLocal(identifier) => {
+                write!(f, "local {identifier} is used before being assigned")
+            }
+            Self::UndefinedFunction(name) => write!(f, "function `{name}` is not defined"),
+            Self::DuplicateFunction(name) => write!(f, "function `{name}` is defined twice"),
+            Self::EmptyFunction(name) => write!(f, "function `{name}` has an empty body"),
+            Self::EmptyProgram => write!(f, "the program has no instructions"),
+            Self::ArityMismatch {
+                function,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "function `{function}` takes {expected} arguments, but {actual} were passed"
+            ),
+            Self::DuplicateModule(name) => write!(f, "module `{name}` is defined twice"),
+            Self::UndefinedModule(name) => write!(f, "module `{name}` is not defined"),
+            Self::UndefinedExport { module, function } => write!(
+                f,
+                "module `{module}` exports function `{function}`, which it doesn't define"
+            ),
+            Self::MissingExport { module, function } => {
+                write!(f, "module `{module}` doesn't export function `{function}`")
+            }
+            Self::ImportArityMismatch { import, exported } => write!(
+                f,
+                "function `{}` exported by module `{}` takes {exported} arguments, but it's \
+                 imported as taking {}",
+                import.function, import.module, import.arity
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Checks that the modules of the program fit together.
+///
+/// Every exported function has to be defined, and every import has to name a function another
+/// module exports, with the arity it was defined with. The functions themselves are not checked.
+///
+/// # Errors
+///
+/// The first import or export that doesn't fit.
+pub fn validate_modules(bytecode: &ByteCode) -> Result<(), ValidationError> {
+    let mut exports = HashMap::new();
+    for module in &bytecode.modules {
+        let arities: HashMap<_, _> = module
+            .functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.arguments.len()))
+            .collect();
+
+        let mut exported = HashMap::new();
+        for function in &module.exports {
+            let Some(&arity) = arities.get(function.as_str()) else {
+                return Err(ValidationError::UndefinedExport {
+                    module: module.name.clone(),
+                    function: function.clone(),
+                });
+            };
+
+            exported.insert(function.as_str(), arity);
+        }
+
+        if exports.insert(module.name.as_str(), exported).is_some() {
+            return Err(ValidationError::DuplicateModule(module.name.clone()));
+        }
+    }
+
+    let imports = bytecode
+        .modules
+        .iter()
+        .map(|module| &module.imports)
+        .chain([&bytecode.imports]);
+
+    for imports in imports {
+        let mut imported = HashSet::new();
+        for import in imports {
+            let Some(exported) = exports.get(import.module.as_str()) else {
+                return Err(ValidationError::UndefinedModule(import.module.clone()));
+            };
+
+            let Some(&arity) = exported.get(import.function.as_str()) else {
+                return Err(ValidationError::MissingExport {
+                    module: import.module.clone(),
+                    function: import.function.clone(),
+                });
+            };
+
+            if arity != import.arity {
+                return Err(ValidationError::ImportArityMismatch {
+                    import: Box::new(import.clone()),
+                    exported: arity,
+                });
+            }
+
+            // The functions are called by their names alone, so two modules can't provide the
+            // same one
+            if !imported.insert(import.function.as_str()) {
+                return Err(ValidationError::DuplicateFunction(import.function.clone()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The arities of the imported functions, under the names they're called by, to be passed as
+/// the externals to `validate_functions_with_externals`.
+#[must_use]
+pub fn import_arities(imports: &[Import]) -> HashMap<String, usize> {
+    imports
+        .iter()
+        .map(|import| (import.function.clone(), import.arity))
+        .collect()
+}
+
+/// Checks that the functions can be compiled, without compiling them. The errors are reported in
+/// the same order the code generator would encounter them.
+///
+/// # Errors
+///
+/// The first problem that would make the code generator reject the functions.
+pub fn validate_functions(functions: &[FunctionDefinition]) -> Result<(), ValidationError> {
+    validate_functions_with_externals(functions, &HashMap::new())
+}
+
+/// Same as `validate_functions`, but the functions can also call the `externals` (given with
+/// their arities), which they must not redefine.
+///
+/// # Errors
+///
+/// The first problem that would make the code generator reject the functions.
+pub fn validate_functions_with_externals<S: BuildHasher>(
+    functions: &[FunctionDefinition],
+    externals: &HashMap<String, usize, S>,
+) -> Result<(), ValidationError> {
+    let mut arities: HashMap<_, _> = externals
+        .iter()
+        .map(|(name, arity)| (name.as_str(), *arity))
+        .collect();
+    for function in functions {
+        if arities
+            .insert(function.name.as_str(), function.arguments.len())
+            .is_some()
+        {
+            return Err(ValidationError::DuplicateFunction(function.name.clone()));
+        }
+    }
+
+    for function in functions {
+        let mut scope: HashSet<_> = function.arguments.iter().copied().collect();
+        for instruction in &function.instructions {
+            validate_expression(instruction, &mut scope, &arities)?;
+        }
+
+        if function.instructions.is_empty() {
+            return Err(ValidationError::EmptyFunction(function.name.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_expression(
+    expression: &Expression,
+    scope: &mut HashSet<Identifier>,
+    arities: &HashMap<&str, usize>,
+) -> Result<(), ValidationError> {
+    match expression {
+        Expression::Assignment(binding, value) => {
+            validate_value(value, scope, arities)?;
+            scope.insert(*binding);
+        }
+        Expression::Add(left, right) => {
+            validate_value(left, scope, arities)?;
+            validate_value(right, scope, arities)?;
+        }
+        Expression::Call(name, arguments) => {
+            let Some(&arity) = arities.get(name.as_str()) else {
+                return Err(ValidationError::UndefinedFunction(name.clone()));
+            };
+
+            if arity != arguments.len() {
+                return Err(ValidationError::ArityMismatch {
+                    function: name.clone(),
+                    expected: arity,
+                    actual: arguments.len(),
+                });
+            }
+
+            for argument in arguments {
+                validate_value(argument, scope, arities)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_value(
+    value: &Value,
+    scope: &mut HashSet<Identifier>,
+    arities: &HashMap<&str, usize>,
+) -> Result<(), ValidationError> {
+    match value {
+        Value::Literal(_) => Ok(()),
+        Value::Local(identifier) if scope.contains(identifier) => Ok(()),
+        Value::Local(identifier) => Err(ValidationError::UndefinedLocal(*identifier)),
+        Value::Computed(expression) => validate_expression(expression, scope, arities),
+    }
+}
@@ -0,0 +1,397 @@
+mod repl;
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use lilith::{
+    CodeGen, CodeGenError, CompilationMode, Context, Session,
+    bytecode::{self, ByteCode, ConstValue, GeneratorOptions, ParseError},
+    interpreter::{self, Comparison, Divergence, InterpreterError},
+};
+
+use crate::fuzz::{self, Failure, FuzzOptions};
+
+pub const USAGE: &str = "\
+usage:
+    lilith run [--lazy] [--interpret | --differential] [--listing <directory>]
+        [--cache <directory>] <file>
+    lilith compile <file> -o <output>
+    lilith check <file>
+    lilith dump [--ir [--demangle]] [--bytecode] <file>
+    lilith repl
+    lilith fuzz [--seed <n>] [--iterations <n>] [--size <n>]";
+
+#[derive(Debug)]
+pub enum Command {
+    Run {
+        path: PathBuf,
+        compilation_mode: CompilationMode,
+        execution: Execution,
+        /// Where to write the listings that the debug info of the generated code refers to
+        listing: Option<PathBuf>,
+        /// Where to keep the compiled modules between the runs
+        cache: Option<PathBuf>,
+    },
+    Compile {
+        path: PathBuf,
+        output: PathBuf,
+    },
+    Check {
+        path: PathBuf,
+    },
+    Dump {
+        path: PathBuf,
+        ir: bool,
+        /// Replaces the mangled symbols in the IR with their readable names
+        demangle: bool,
+        bytecode: bool,
+    },
+    Repl,
+    Fuzz(FuzzOptions),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Execution {
+    Jit,
+    Interpreter,
+    /// Runs both in the interpreter and in the JIT and fails if the results differ
+    Differential,
+}
+
+#[derive(Debug)]
+pub struct UsageError(String);
+
+impl Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+#[derive(Debug)]
+pub enum CliError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: ParseError,
+    },
+    CodeGen(CodeGenError),
+    Interpreter(InterpreterError),
+    Divergence(Divergence),
+    Fuzz(Box<Failure>),
+    Console(std::io::Error),
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "{}: {error}", path.display()),
+            Self::Parse { path, error } => write!(f, "{}:{error}", path.display()),
+            Self::CodeGen(error) => write!(f, "{error}"),
+            Self::Interpreter(error) => write!(f, "{error}"),
+            Self::Divergence(divergence) => write!(f, "results diverged: {divergence}"),
+            Self::Fuzz(failure) => write!(f, "{failure}"),
+            Self::Console(error) => write!(f, "console: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<CodeGenError> for CliError {
+    fn from(value: CodeGenError) -> Self {
+        Self::CodeGen(value)
+    }
+}
+
+impl Command {
+    pub fn parse(arguments: &[String]) -> Result<Self, UsageError> {
+        let Some((subcommand, rest)) = arguments.split_first() else {
+            return Err(UsageError("missing subcommand".to_string()));
+        };
+
+        match subcommand.as_str() {
+            "run" => parse_run(subcommand, rest),
+            "check" => Ok(Self::Check {
+                path: single_path(subcommand, rest)?,
+            }),
+            "compile" => {
+                let mut path = None;
+                let mut output = None;
+                let mut rest = rest.iter();
+
+                while let Some(argument) = rest.next() {
+                    if argument == "-o" {
+                        let Some(value) = rest.next() else {
+                            return Err(UsageError("`-o` requires a value".to_string()));
+                        };
+                        output = Some(PathBuf::from(value));
+                    } else {
+                        set_path(&mut path, subcommand, argument)?;
+                    }
+                }
+
+                Ok(Self::Compile {
+                    path: path.ok_or_else(|| missing_file(subcommand))?,
+                    output: output.ok_or_else(|| {
+                        UsageError("`compile` requires an output file (`-o`)".to_string())
+                    })?,
+                })
+            }
+            "dump" => {
+                let mut path = None;
+                let mut ir = false;
+                let mut demangle = false;
+                let mut bytecode = false;
+
+                for argument in rest {
+                    match argument.as_str() {
+                        "--ir" => ir = true,
+                        "--demangle" => demangle = true,
+                        "--bytecode" => bytecode = true,
+                        _ => set_path(&mut path, subcommand, argument)?,
+                    }
+                }
+
+                if !ir && !bytecode {
+                    return Err(UsageError(
+                        "`dump` requires at least one of `--ir` or `--bytecode`".to_string(),
+                    ));
+                }
+
+                if demangle && !ir {
+                    return Err(UsageError("`--demangle` requires `--ir`".to_string()));
+                }
+
+                Ok(Self::Dump {
+                    path: path.ok_or_else(|| missing_file(subcommand))?,
+                    ir,
+                    demangle,
+                    bytecode,
+                })
+            }
+            "repl" => rest.first().map_or(Ok(Self::Repl), |argument| {
+                Err(UsageError(format!(
+                    "unexpected argument `{argument}` for `repl`"
+                )))
+            }),
+            "fuzz" => parse_fuzz(rest).map(Self::Fuzz),
+            _ => Err(UsageError(format!("unknown subcommand `{subcommand}`"))),
+        }
+    }
+}
+
+fn parse_run(subcommand: &str, rest: &[String]) -> Result<Command, UsageError> {
+    let mut path = None;
+    let mut compilation_mode = CompilationMode::Eager;
+    let mut execution = Execution::Jit;
+    let mut listing = None;
+    let mut cache = None;
+    let mut rest = rest.iter();
+
+    while let Some(argument) = rest.next() {
+        match argument.as_str() {
+            "--lazy" => compilation_mode = CompilationMode::Lazy,
+            "--listing" => listing = Some(directory(argument, rest.next())?),
+            "--cache" => cache = Some(directory(argument, rest.next())?),
+            "--interpret" | "--differential" if execution != Execution::Jit => {
+                return Err(UsageError(
+                    "`--interpret` and `--differential` are mutually exclusive".to_string(),
+                ));
+            }
+            "--interpret" => execution = Execution::Interpreter,
+            "--differential" => execution = Execution::Differential,
+            _ => set_path(&mut path, subcommand, argument)?,
+        }
+    }
+
+    Ok(Command::Run {
+        path: path.ok_or_else(|| missing_file(subcommand))?,
+        compilation_mode,
+        execution,
+        listing,
+        cache,
+    })
+}
+
+fn parse_fuzz(rest: &[String]) -> Result<FuzzOptions, UsageError> {
+    let mut options = FuzzOptions {
+        seed: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs()),
+        iterations: 100,
+        generator: GeneratorOptions::default(),
+    };
+    let mut rest = rest.iter();
+
+    while let Some(argument) = rest.next() {
+        match argument.as_str() {
+            "--seed" => options.seed = number(argument, rest.next())?,
+            "--iterations" => options.iterations = number(argument, rest.next())?,
+            "--size" => options.generator = GeneratorOptions::sized(number(argument, rest.next())?),
+            _ => {
+                return Err(UsageError(format!(
+                    "unexpected argument `{argument}` for `fuzz`"
+                )));
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+fn number<T: FromStr>(option: &str, value: Option<&String>) -> Result<T, UsageError> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| UsageError(format!("`{option}` requires a number")))
+}
+
+fn directory(option: &str, value: Option<&String>) -> Result<PathBuf, UsageError> {
+    value
+        .map(PathBuf::from)
+        .ok_or_else(|| UsageError(format!("`{option}` requires a directory")))
+}
+
+fn missing_file(subcommand: &str) -> UsageError {
+    UsageError(format!("`{subcommand}` requires a file"))
+}
+
+fn set_path(
+    path: &mut Option<PathBuf>,
+    subcommand: &str,
+    argument: &str,
+) -> Result<(), UsageError> {
+    if argument.starts_with('-') {
+        return Err(UsageError(format!(
+            "unknown option `{argument}` for `{subcommand}`"
+        )));
+    }
+
+    if path.replace(PathBuf::from(argument)).is_some() {
+        return Err(UsageError(format!("`{subcommand}` accepts only one file")));
+    }
+
+    Ok(())
+}
+
+fn single_path(subcommand: &str, rest: &[String]) -> Result<PathBuf, UsageError> {
+    let mut path = None;
+    for argument in rest {
+        set_path(&mut path, subcommand, argument)?;
+    }
+
+    path.ok_or_else(|| missing_file(subcommand))
+}
+
+fn load(path: &Path) -> Result<ByteCode, CliError> {
+    let source = std::fs::read_to_string(path).map_err(|error| CliError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    bytecode::parse(&source).map_err(|error| CliError::Parse {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+pub fn run(command: Command) -> Result<(), CliError> {
+    let context = Context::create();
+
+    match command {
+        Command::Run {
+            path,
+            compilation_mode,
+            execution,
+            listing,
+            cache,
+        } => {
+            let bytecode = load(&path)?;
+            let session = || -> Result<Session, CodeGenError> {
+                let session = Session::new(&context)?.with_compilation_mode(compilation_mode);
+                let session = match listing {
+                    Some(directory) => session.with_listing_directory(directory),
+                    None => session,
+                };
+
+                match cache {
+                    Some(directory) => session.with_module_cache(directory),
+                    None => Ok(session),
+                }
+            };
+
+            let result = match execution {
+                Execution::Jit => session()?.execute(bytecode)?,
+                Execution::Interpreter => match interpreter::interpret(&bytecode) {
+                    Ok(ConstValue::U64(value)) => value,
+                    Err(error) => return Err(CliError::Interpreter(error)),
+                },
+                Execution::Differential => match interpreter::compare(&mut session()?, bytecode) {
+                    Comparison::Agreed(value) => value,
+                    Comparison::Rejected(error) => {
+                        return Err(CliError::CodeGen(error.into()));
+                    }
+                    Comparison::Skipped(error) => return Err(CliError::Interpreter(error)),
+                    Comparison::Diverged(divergence) => {
+                        return Err(CliError::Divergence(divergence));
+                    }
+                },
+            };
+
+            println!("result: {result}");
+        }
+        Command::Compile { path, output } => {
+            let bytecode = load(&path)?;
+            let module = CodeGen::new(&context).compile(bytecode)?;
+
+            lilith::write_object_file(&module, &output)?;
+        }
+        Command::Check { path } => {
+            let bytecode = load(&path)?;
+            CodeGen::new(&context).compile(bytecode)?;
+        }
+        Command::Dump {
+            path,
+            ir,
+            demangle,
+            bytecode,
+        } => {
+            let loaded = load(&path)?;
+
+            if bytecode {
+                print!("{loaded}");
+            }
+
+            if ir {
+                let module = CodeGen::new(&context).compile(loaded)?;
+                let ir = module.print_to_string().to_string();
+
+                if demangle {
+                    print!("{}", lilith::demangle_symbols(&ir));
+                } else {
+                    print!("{ir}");
+                }
+            }
+        }
+        Command::Repl => repl::run()?,
+        Command::Fuzz(options) => {
+            println!(
+                "fuzzing {} programs, starting with seed {}",
+                options.iterations, options.seed
+            );
+
+            fuzz::run(&options).map_err(CliError::Fuzz)?;
+
+            println!("all programs passed");
+        }
+    }
+
+    Ok(())
+}
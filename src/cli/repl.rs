@@ -0,0 +1,68 @@
+use std::io::Write;
+
+use lilith::{Context, Session, bytecode};
+
+use super::CliError;
+
+pub(super) fn run() -> Result<(), CliError> {
+    let context = Context::create();
+    let mut session = Session::new(&context)?;
+
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    let mut input = String::new();
+
+    loop {
+        write!(stdout, "{}", if input.is_empty() { "> " } else { ". " })
+            .map_err(CliError::Console)?;
+        stdout.flush().map_err(CliError::Console)?;
+
+        if stdin.read_line(&mut input).map_err(CliError::Console)? == 0 {
+            writeln!(stdout).map_err(CliError::Console)?;
+            return Ok(());
+        }
+
+        if !is_complete(&input) {
+            continue;
+        }
+
+        let source = std::mem::take(&mut input);
+        if source.trim().is_empty() {
+            continue;
+        }
+
+        let result = bytecode::parse(&source)
+            .map_err(|error| error.to_string())
+            .and_then(|bytecode| {
+                session
+                    .evaluate(bytecode)
+                    .map_err(|error| error.to_string())
+            });
+
+        match result {
+            Ok(Some(value)) => println!("{value}"),
+            Ok(None) => {}
+            Err(error) => eprintln!("error: {error}"),
+        }
+    }
+}
+
+/// An input is complete once all the parentheses opened in it are closed, until then the REPL
+/// keeps reading more lines.
+fn is_complete(input: &str) -> bool {
+    let mut depth = 0i64;
+
+    for line in input.lines() {
+        let code = line.split_once(';').map_or(line, |(code, _comment)| code);
+
+        for character in code.chars() {
+            match character {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    depth <= 0
+}
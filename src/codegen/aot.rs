@@ -0,0 +1,50 @@
+use std::path::Path;
+
+use inkwell::{
+    OptimizationLevel,
+    module::Module,
+    targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine},
+};
+
+use super::CodeGenError;
+
+// TODO the builtins (like debug_type_definition) are only provided by the host when running in
+// the JIT, so the object file will have undefined references to them until we ship a runtime
+// library that can be linked against
+/// Writes the module as an object file for the host machine.
+///
+/// # Errors
+///
+/// If LLVM doesn't support the host machine, or the file can't be written.
+pub fn write_object_file(module: &Module<'_>, path: &Path) -> Result<(), CodeGenError> {
+    let target_machine = host_target_machine(OptimizationLevel::Aggressive)?;
+
+    module.set_triple(&target_machine.get_triple());
+    module.set_data_layout(&target_machine.get_target_data().get_data_layout());
+
+    target_machine
+        .write_to_file(module, FileType::Object, path)
+        .map_err(|error| CodeGenError::Target(error.to_string()))
+}
+
+/// Creates a target machine for the machine the compiler runs on.
+pub(in crate::codegen) fn host_target_machine(
+    optimization_level: OptimizationLevel,
+) -> Result<TargetMachine, CodeGenError> {
+    Target::initialize_native(&InitializationConfig::default()).map_err(CodeGenError::Target)?;
+
+    let triple = TargetMachine::get_default_triple();
+    let target =
+        Target::from_triple(&triple).map_err(|error| CodeGenError::Target(error.to_string()))?;
+
+    target
+        .create_target_machine(
+            &triple,
+            &TargetMachine::get_host_cpu_name().to_string(),
+            &TargetMachine::get_host_cpu_features().to_string(),
+            optimization_level,
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| CodeGenError::Target(format!("no target machine for {triple}")))
+}
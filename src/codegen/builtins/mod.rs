@@ -1,27 +1,81 @@
 mod debug;
+mod runtime_error;
 
 use debug::debug_type_definition_impl;
 use inkwell::{context::Context, execution_engine::ExecutionEngine, module::Module};
+pub(in crate::codegen) use runtime_error::{report_runtime_error, take_runtime_error};
+use runtime_error::{report_unexpected_type_impl, report_uninitialized_runtime_impl};
 
 use super::{context::Procedure, types::values::Value};
-use crate::make_function_type;
+use crate::{bytecode::TypeTag, make_function_type};
 
 make_function_type!(DebugTypeDefinition, (value: *const Value));
+make_function_type!(ReportUnexpectedType, (expected: TypeTag, value: *const Value));
+make_function_type!(ReportUninitializedRuntime, ());
+
+pub(in crate::codegen) fn declare<'ctx>(
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+) -> DebugTypeDefinition<'ctx> {
+    let symbol = DebugTypeDefinition::symbol();
+
+    DebugTypeDefinition::new(module.get_function(&symbol).unwrap_or_else(|| {
+        module.add_function(
+            &symbol,
+            // this should really be a type argument, and not a value argument
+            DebugTypeDefinition::llvm_type(context),
+            None,
+        )
+    }))
+}
+
+/// Declares the function the generated code calls when a value doesn't have the type it should,
+/// the error then gets picked up with `take_runtime_error`.
+pub(in crate::codegen) fn report_unexpected_type<'ctx>(
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+) -> ReportUnexpectedType<'ctx> {
+    let symbol = ReportUnexpectedType::symbol();
+
+    ReportUnexpectedType::new(module.get_function(&symbol).unwrap_or_else(|| {
+        module.add_function(&symbol, ReportUnexpectedType::llvm_type(context), None)
+    }))
+}
+
+/// Declares the function the generated code calls when it finds the runtime modules haven't been
+/// initialized, the error then gets picked up with `take_runtime_error`.
+pub(in crate::codegen) fn report_uninitialized_runtime<'ctx>(
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+) -> ReportUninitializedRuntime<'ctx> {
+    let symbol = ReportUninitializedRuntime::symbol();
+
+    ReportUninitializedRuntime::new(module.get_function(&symbol).unwrap_or_else(|| {
+        module.add_function(
+            &symbol,
+            ReportUninitializedRuntime::llvm_type(context),
+            None,
+        )
+    }))
+}
 
 pub(in crate::codegen) fn register<'ctx>(
     execution_engine: &ExecutionEngine<'ctx>,
     module: &Module<'ctx>,
     context: &'ctx Context,
 ) {
-    let debug_type_definition = module.add_function(
-        "debug_type_definition",
-        // this should really be a type argument, and not a value argument
-        DebugTypeDefinition::llvm_type(context),
-        None,
+    execution_engine.add_global_mapping(
+        &declare(module, context).as_global_value(),
+        debug_type_definition_impl as extern "C" fn(*const Value) as usize,
     );
 
     execution_engine.add_global_mapping(
-        &debug_type_definition,
-        debug_type_definition_impl as extern "C" fn(*const Value) as usize,
+        &report_unexpected_type(module, context).as_global_value(),
+        report_unexpected_type_impl as extern "C" fn(TypeTag, *const Value) as usize,
+    );
+
+    execution_engine.add_global_mapping(
+        &report_uninitialized_runtime(module, context).as_global_value(),
+        report_uninitialized_runtime_impl as extern "C" fn() as usize,
     );
 }
@@ -0,0 +1,37 @@
+use std::cell::Cell;
+
+use crate::{
+    bytecode::TypeTag,
+    codegen::{error::RuntimeError, types::values::Value},
+};
+
+thread_local! {
+    // The generated code runs on the thread that called into it, so that's where the host picks
+    // the error up once the call returns
+    static RUNTIME_ERROR: Cell<Option<RuntimeError>> = const { Cell::new(None) };
+}
+
+pub(super) extern "C" fn report_unexpected_type_impl(expected: TypeTag, value: *const Value) {
+    // The tag can be anything at this point, so it has to be read as a plain byte, not a TypeTag
+    let actual = unsafe { *value.cast::<u8>() };
+
+    report_runtime_error(RuntimeError::UnexpectedType { expected, actual });
+}
+
+pub(super) extern "C" fn report_uninitialized_runtime_impl() {
+    report_runtime_error(RuntimeError::UninitializedRuntime);
+}
+
+/// Records the error for the host to pick up, unless there already is one, as the first error is
+/// the one that caused any that follow.
+pub(in crate::codegen) fn report_runtime_error(error: RuntimeError) {
+    RUNTIME_ERROR.with(|runtime_error| {
+        let previous = runtime_error.take();
+        runtime_error.set(previous.or(Some(error)));
+    });
+}
+
+/// Returns the error the generated code reported on this thread, if there was one.
+pub(in crate::codegen) fn take_runtime_error() -> Option<RuntimeError> {
+    RUNTIME_ERROR.take()
+}
@@ -0,0 +1,322 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use inkwell::{
+    OptimizationLevel, context::Context, module::Module, passes::PassBuilderOptions,
+    support::get_llvm_version, targets::TargetMachine,
+};
+
+use super::{CodeGenError, aot};
+use crate::bytecode::ByteCode;
+
+/// Keeps the optimized bitcode of the compiled units on disk, so that running the same unit again
+/// doesn't have to compile it again.
+///
+/// The entries are keyed by a hash of everything the generated code depends on: the bytecode of
+/// the unit, its name (which the symbols are derived from), the build of the compiler, the version
+/// of LLVM, the target machine and the optimization settings. Any change to them makes for a
+/// different key, so the stale entries are simply never loaded again.
+///
+/// Each entry is a directory with a bitcode file for every module of the unit, named after the
+/// module, and a `key` file with everything the key was hashed from. The entries whose `key` file
+/// doesn't match, like the ones of another unit with a colliding hash, are never loaded.
+pub(in crate::codegen) struct ModuleCache {
+    directory: PathBuf,
+    optimization_level: OptimizationLevel,
+    target_machine: TargetMachine,
+    // The parts of the key that are the same for all the units
+    identity: String,
+}
+
+/// Identifies the compiled modules of an unit in the cache, by the hash that names its entry and
+/// the text it was hashed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(in crate::codegen) struct CacheKey {
+    hash: u64,
+    text: String,
+}
+
+impl ModuleCache {
+    /// # Errors
+    ///
+    /// If there's no target machine to optimize the modules for, or the executable of the
+    /// compiler can't be read.
+    pub(in crate::codegen) fn new(
+        directory: PathBuf,
+        optimization_level: OptimizationLevel,
+    ) -> Result<Self, CodeGenError> {
+        let target_machine = aot::host_target_machine(optimization_level)?;
+        let (major, minor, patch) = get_llvm_version();
+        let identity = format!(
+            "{:016x} {major}.{minor}.{patch} {} {} {} {optimization_level:?}",
+            build_identity()?,
+            target_machine.get_triple().as_str().to_string_lossy(),
+            target_machine.get_cpu(),
+            target_machine.get_feature_string().to_string_lossy(),
+        );
+
+        Ok(Self {
+            directory,
+            optimization_level,
+            target_machine,
+            identity,
+        })
+    }
+
+    /// The key of the unit, `source_directory` being where its debug info expects the listing to
+    /// be.
+    pub(in crate::codegen) fn key(
+        &self,
+        unit: &str,
+        bytecode: &ByteCode,
+        source_directory: &Path,
+    ) -> CacheKey {
+        // The textual form round-trips through the parser, so it's as good as the bytecode itself.
+        // The parts are kept apart by a zero byte, so they can't shift into each other
+        let text = [
+            self.identity.as_str(),
+            unit,
+            &source_directory.to_string_lossy(),
+            &bytecode.to_string(),
+        ]
+        .join("\0");
+        let mut hasher = Fnv1a::default();
+        hasher.write(text.as_bytes());
+
+        CacheKey {
+            hash: hasher.0,
+            text,
+        }
+    }
+
+    /// Loads the modules of the entry, in the order of the `names`. Anything that's missing or
+    /// fails to parse counts as a miss, so that the entry gets written anew, as does an entry
+    /// stored under another key with the same hash.
+    pub(in crate::codegen) fn load<'ctx>(
+        &self,
+        key: &CacheKey,
+        names: &[String],
+        context: &'ctx Context,
+    ) -> Option<Vec<Module<'ctx>>> {
+        let entry = self.entry_directory(key);
+        if std::fs::read(key_path(&entry)).ok()? != key.text.as_bytes() {
+            return None;
+        }
+
+        names
+            .iter()
+            .map(|name| Module::parse_bitcode_from_path(bitcode_path(&entry, name), context).ok())
+            .collect()
+    }
+
+    /// Optimizes the modules (in place, so the caller runs the same code the cache has) and
+    /// writes them into the entry.
+    ///
+    /// # Errors
+    ///
+    /// If the optimization fails, or the entry can't be written.
+    pub(in crate::codegen) fn store(
+        &self,
+        key: &CacheKey,
+        modules: &[&Module<'_>],
+    ) -> Result<(), CodeGenError> {
+        let entry = self.entry_directory(key);
+        std::fs::create_dir_all(&entry).map_err(|error| CodeGenError::Cache {
+            path: entry.clone(),
+            error,
+        })?;
+
+        for module in modules {
+            module
+                .run_passes(
+                    &passes(self.optimization_level),
+                    &self.target_machine,
+                    PassBuilderOptions::create(),
+                )
+                .map_err(|error| CodeGenError::Target(error.to_string()))?;
+
+            let name = module.get_name().to_string_lossy();
+            write_file(
+                &bitcode_path(&entry, &name),
+                module.write_bitcode_to_memory().as_slice(),
+            )?;
+        }
+
+        // Last, so that the entry is only ever loaded once all of its modules are there
+        write_file(&key_path(&entry), key.text.as_bytes())
+    }
+
+    fn entry_directory(&self, key: &CacheKey) -> PathBuf {
+        self.directory.join(format!("{:016x}", key.hash))
+    }
+}
+
+/// Writes the file under a temporary name first, so that a run that gets interrupted doesn't
+/// leave a truncated file behind for the later ones to load.
+fn write_file(path: &Path, contents: &[u8]) -> Result<(), CodeGenError> {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+
+    std::fs::write(&temporary_path, contents)
+        .and_then(|()| std::fs::rename(&temporary_path, path))
+        .map_err(|error| CodeGenError::Cache {
+            path: path.to_path_buf(),
+            error,
+        })
+}
+
+fn bitcode_path(entry: &Path, module: &str) -> PathBuf {
+    entry.join(format!("{module}.bc"))
+}
+
+fn key_path(entry: &Path) -> PathBuf {
+    entry.join("key")
+}
+
+/// The pipeline the modules get optimized with, same as `opt -O<n>` would use.
+fn passes(optimization_level: OptimizationLevel) -> String {
+    let level = match optimization_level {
+        OptimizationLevel::None => 0,
+        OptimizationLevel::Less => 1,
+        OptimizationLevel::Default => 2,
+        OptimizationLevel::Aggressive => 3,
+    };
+
+    format!("default<O{level}>")
+}
+
+/// Identifies the build of the compiler by a hash of its executable, as the generated code can
+/// change with any build, not just with the version. The executable is only hashed once per
+/// process, unless reading it fails.
+fn build_identity() -> Result<u64, CodeGenError> {
+    static BUILD_IDENTITY: OnceLock<u64> = OnceLock::new();
+
+    if let Some(identity) = BUILD_IDENTITY.get() {
+        return Ok(*identity);
+    }
+
+    let executable = std::env::current_exe()
+        .and_then(std::fs::read)
+        .map_err(CodeGenError::CompilerIdentity)?;
+
+    let mut hasher = Fnv1a::default();
+    hasher.write(&executable);
+
+    Ok(*BUILD_IDENTITY.get_or_init(|| hasher.0))
+}
+
+/// The keys have to stay the same across runs (and builds) of the compiler, which the hashers
+/// from the standard library don't promise, so it's FNV-1a.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use inkwell::{OptimizationLevel, context::Context};
+
+    use super::{CacheKey, ModuleCache};
+    use crate::bytecode::parse;
+
+    fn cache_directory(test: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("lilith-cache-{test}-{}", std::process::id()));
+        // Left behind by an earlier run that got interrupted
+        let _ = std::fs::remove_dir_all(&directory);
+
+        directory
+    }
+
+    #[test]
+    fn stored_units_are_loaded_by_their_key() {
+        let directory = cache_directory("hit");
+        let cache = ModuleCache::new(directory.clone(), OptimizationLevel::Default).unwrap();
+        let context = Context::create();
+        let module = context.create_module("unit");
+        module.add_function("answer", context.i64_type().fn_type(&[], false), None);
+
+        let bytecode = parse("(add 1 2)").unwrap();
+        let key = cache.key("unit", &bytecode, Path::new("listings"));
+        assert!(cache.load(&key, &["unit".to_string()], &context).is_none());
+
+        cache.store(&key, &[&module]).unwrap();
+        // The same unit gets the same key, even from another session
+        let other_cache = ModuleCache::new(directory.clone(), OptimizationLevel::Default).unwrap();
+        let key = other_cache.key("unit", &parse("(add 1 2)").unwrap(), Path::new("listings"));
+        let loaded = other_cache
+            .load(&key, &["unit".to_string()], &context)
+            .unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert!(loaded[0].get_function("answer").is_some());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn entries_of_colliding_keys_are_not_loaded() {
+        let directory = cache_directory("collision");
+        let cache = ModuleCache::new(directory.clone(), OptimizationLevel::Default).unwrap();
+        let context = Context::create();
+        let module = context.create_module("unit");
+
+        let key = cache.key("unit", &parse("(add 1 2)").unwrap(), Path::new("listings"));
+        cache.store(&key, &[&module]).unwrap();
+
+        // Another unit whose key happens to hash the same
+        let colliding = CacheKey {
+            hash: key.hash,
+            text: cache
+                .key("unit", &parse("(add 1 3)").unwrap(), Path::new("listings"))
+                .text,
+        };
+        assert!(
+            cache
+                .load(&colliding, &["unit".to_string()], &context)
+                .is_none()
+        );
+        assert!(cache.load(&key, &["unit".to_string()], &context).is_some());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn any_change_makes_for_another_key() {
+        let directory = cache_directory("invalidation");
+        let mut cache = ModuleCache::new(directory.clone(), OptimizationLevel::Default).unwrap();
+        let bytecode = parse("(add 1 2)").unwrap();
+        let key = cache.key("unit", &bytecode, Path::new("listings"));
+
+        let others = [
+            cache.key("unit", &parse("(add 1 3)").unwrap(), Path::new("listings")),
+            cache.key("other", &bytecode, Path::new("listings")),
+            cache.key("unit", &bytecode, Path::new("elsewhere")),
+            ModuleCache::new(directory, OptimizationLevel::Aggressive)
+                .unwrap()
+                .key("unit", &bytecode, Path::new("listings")),
+        ];
+        for other in others {
+            assert_ne!(other, key);
+        }
+
+        // As if the compiler was rebuilt, or run on another machine
+        cache.identity.push_str(" rebuilt");
+        assert_ne!(cache.key("unit", &bytecode, Path::new("listings")), key);
+    }
+}
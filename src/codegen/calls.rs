@@ -0,0 +1,180 @@
+use super::{
+    error::{CallError, RuntimeError},
+    host_functions::{HostValue, sealed::Sealed},
+    type_store::host::StoredType,
+    types::{classes::ClassId, functions::FunctionSignature, values::Value},
+};
+use crate::bytecode::{Identifier, TypeId, TypeTag};
+
+type ValuePointer = *const Value;
+
+/// The arguments of a call from the host into a bytecode function, implemented for tuples of up
+/// to 4 `HostValue`s. It's sealed, as the function gets called with exactly these values.
+pub trait BytecodeArguments: Sealed {
+    fn types() -> Vec<TypeTag>;
+    fn into_raw(self) -> Vec<u64>;
+}
+
+macro_rules! impl_bytecode_arguments {
+    ($($argument:ident),*) => {
+        impl<$($argument: HostValue),*> Sealed for ($($argument,)*) {}
+
+        impl<$($argument: HostValue),*> BytecodeArguments for ($($argument,)*) {
+            fn types() -> Vec<TypeTag> {
+                vec![$($argument::TYPE),*]
+            }
+
+            #[allow(non_snake_case)]
+            fn into_raw(self) -> Vec<u64> {
+                let ($($argument,)*) = self;
+
+                vec![$($argument.into_raw()),*]
+            }
+        }
+    };
+}
+
+impl_bytecode_arguments!();
+impl_bytecode_arguments!(A);
+impl_bytecode_arguments!(A, B);
+impl_bytecode_arguments!(A, B, C);
+impl_bytecode_arguments!(A, B, C, D);
+
+/// Builds the signature the bytecode function with these arguments gets registered with in the
+/// type store. Everything in the bytecode is an u64 for now, so that's what all the arguments
+/// and the result are.
+pub(in crate::codegen) fn bytecode_signature(arguments: &[Identifier]) -> *const Value {
+    StoredType::FunctionSignature {
+        class_id: ClassId::none(),
+        arguments: arguments
+            .iter()
+            .map(|name| (*name, TypeTag::U64.into()))
+            .collect(),
+        return_type: TypeTag::U64.into(),
+    }
+    .into_raw()
+}
+
+/// Checks that the function can be called with the arguments and return the result, according
+/// to its `signature` from the type store, and the types the `lookup` finds there.
+pub(in crate::codegen) fn check_signature(
+    function: &str,
+    signature: *const Value,
+    arguments: &[TypeTag],
+    result: TypeTag,
+    lookup: &impl Fn(TypeId) -> Option<StoredType>,
+) -> Result<(), CallError> {
+    // The type store returns null for the ids it doesn't know
+    if signature.is_null() || unsafe { *signature.cast::<u8>() } != TypeTag::FunctionSignature as u8
+    {
+        return Err(CallError::MissingSignature(function.to_string()));
+    }
+
+    let signature = unsafe { &*((*signature).raw as *const FunctionSignature) };
+    let declared: Vec<TypeId> = unsafe {
+        signature
+            .arguments
+            .iter(usize::from(signature.argument_count))
+    }
+    .map(|argument| argument.type_id)
+    .collect();
+
+    if declared.len() != arguments.len() {
+        return Err(CallError::ArityMismatch {
+            function: function.to_string(),
+            expected: declared.len(),
+            actual: arguments.len(),
+        });
+    }
+
+    for (position, (declared, passed)) in declared.into_iter().zip(arguments).enumerate() {
+        if !StoredType::is_assignable((*passed).into(), declared, lookup) {
+            return Err(CallError::ArgumentType {
+                function: function.to_string(),
+                position,
+                declared,
+                passed: (*passed).into(),
+            });
+        }
+    }
+
+    if !StoredType::is_assignable(signature.return_type_id, result.into(), lookup) {
+        return Err(CallError::ReturnType {
+            function: function.to_string(),
+            declared: signature.return_type_id,
+            requested: result.into(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Calls the bytecode function at the address, wrapping the arguments into values first, and
+/// unwraps its result.
+///
+/// # Safety
+///
+/// The function has to take as many arguments as there are `types`, at most 4.
+pub(in crate::codegen) unsafe fn call<T: HostValue>(
+    address: usize,
+    types: &[TypeTag],
+    arguments: Vec<u64>,
+) -> Result<T, RuntimeError> {
+    let values: Vec<_> = types
+        .iter()
+        .zip(arguments)
+        .map(|(tag, raw)| Value {
+            tag: *tag,
+            unused_0: 0,
+            class_id: ClassId::none(),
+            unused_1: 0,
+            raw,
+        })
+        .collect();
+    // The result can be one of the arguments, so the values have to outlive its decoding
+    let pointers: Vec<*const Value> = values.iter().map(std::ptr::from_ref).collect();
+
+    let result = unsafe {
+        match pointers[..] {
+            [] => std::mem::transmute::<usize, extern "C" fn() -> ValuePointer>(address)(),
+            [a] => std::mem::transmute::<usize, extern "C" fn(ValuePointer) -> ValuePointer>(
+                address,
+            )(a),
+            [a, b] => std::mem::transmute::<
+                usize,
+                extern "C" fn(ValuePointer, ValuePointer) -> ValuePointer,
+            >(address)(a, b),
+            [a, b, c] => std::mem::transmute::<
+                usize,
+                extern "C" fn(ValuePointer, ValuePointer, ValuePointer) -> ValuePointer,
+            >(address)(a, b, c),
+            [a, b, c, d] => std::mem::transmute::<
+                usize,
+                extern "C" fn(
+                    ValuePointer,
+                    ValuePointer,
+                    ValuePointer,
+                    ValuePointer,
+                ) -> ValuePointer,
+            >(address)(a, b, c, d),
+            _ => unreachable!("the arguments are only implemented for up to 4 values"),
+        }
+    };
+
+    decode(result)
+}
+
+/// Unwraps the result of a call, if it's of the expected type.
+fn decode<T: HostValue>(result: *const Value) -> Result<T, RuntimeError> {
+    // The tag can be anything at this point, so it has to be read as a plain byte, not a TypeTag
+    let actual = unsafe { *result.cast::<u8>() };
+
+    if actual != T::TYPE as u8 {
+        return Err(RuntimeError::UnexpectedType {
+            expected: T::TYPE,
+            actual,
+        });
+    }
+
+    Ok(T::from_raw(unsafe { &*result }.raw))
+}
@@ -12,7 +12,8 @@ use crate::codegen::llvm_struct::representations::LlvmRepresentation;
 // traits?
 // TODO There's a lot of repeated code all around here, clean it up
 pub(in crate::codegen) trait Procedure<'ctx, TArguments> {
-    const NAME: &'static str;
+    /// The mangled name of the procedure, see `mangling`
+    fn symbol() -> String;
 
     fn llvm_type(context: &'ctx Context) -> FunctionType<'ctx>;
 
@@ -24,7 +25,8 @@ pub(in crate::codegen) trait Procedure<'ctx, TArguments> {
 }
 
 pub(in crate::codegen) trait Function<'ctx, TReturn: LlvmRepresentation<'ctx>, TArguments> {
-    const NAME: &'static str;
+    /// The mangled name of the function, see `mangling`
+    fn symbol() -> String;
 
     fn llvm_type(context: &'ctx Context) -> FunctionType<'ctx>;
 
@@ -33,6 +35,7 @@ pub(in crate::codegen) trait Function<'ctx, TReturn: LlvmRepresentation<'ctx>, T
 }
 
 #[macro_export]
+#[doc(hidden)]
 macro_rules! make_llvm_type_instance {
     ($context:expr, $type:ty) => {
         <$type as $crate::codegen::llvm_struct::representations::LlvmRepresentation>::llvm_type(
@@ -42,6 +45,7 @@ macro_rules! make_llvm_type_instance {
 }
 
 #[macro_export]
+#[doc(hidden)]
 macro_rules! make_llvm_value_type {
     ($type:ty) => {
         <$type as $crate::codegen::llvm_struct::representations::LlvmRepresentation<'ctx>>::LlvmValue
@@ -49,8 +53,10 @@ macro_rules! make_llvm_value_type {
 }
 
 #[macro_export]
+#[doc(hidden)]
 macro_rules! make_function_type {
     ($name:ident, ($($argument_name:ident: $argument:ty),*)) => {
+        #[derive(Clone, Copy)]
         pub(in $crate::codegen) struct $name<'ctx> {
             value: inkwell::values::FunctionValue<'ctx>
         }
@@ -59,7 +65,14 @@ macro_rules! make_function_type {
         impl<'ctx> $crate::codegen::context::Procedure<
             'ctx, ($($crate::make_llvm_value_type!($argument)),*)
         > for $name<'ctx> {
-            const NAME: &'static str = stringify!($name);
+            fn symbol() -> String {
+                $crate::codegen::mangling::mangle_function(
+                    module_path!(),
+                    stringify!($name),
+                    &[$(stringify!($argument)),*],
+                    None,
+                )
+            }
 
             fn new(value: inkwell::values::FunctionValue<'ctx>) -> Self {
                 Self { value }
@@ -98,6 +111,7 @@ macro_rules! make_function_type {
     };
 
     ($name:ident, ($($argument_name:ident: $argument:ty),*): $return_type:ty) => {
+        #[derive(Clone, Copy)]
         pub(in $crate::codegen) struct $name<'ctx> {
             #[allow(unused)]
             value: inkwell::values::FunctionValue<'ctx>,
@@ -109,7 +123,14 @@ macro_rules! make_function_type {
             $return_type,
             ($($crate::make_llvm_value_type!($argument)),*)
         > for $name<'ctx> {
-            const NAME: &'static str = stringify!($name);
+            fn symbol() -> String {
+                $crate::codegen::mangling::mangle_function(
+                    module_path!(),
+                    stringify!($name),
+                    &[$(stringify!($argument)),*],
+                    Some(stringify!($return_type)),
+                )
+            }
 
             // TODO can CodegenContext implement Context(if it's a trait) maybeee? or maybe we can
             // have some common trait, to just avoid going deep into properties at call sites
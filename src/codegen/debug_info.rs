@@ -0,0 +1,402 @@
+use std::{collections::HashMap, path::Path};
+
+use inkwell::{
+    AddressSpace,
+    builder::Builder,
+    context::Context,
+    debug_info::{
+        AsDIScope, DICompileUnit, DIFlags, DIFlagsConstants, DIScope, DISubroutineType, DIType,
+        DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder, debug_metadata_version,
+    },
+    llvm_sys::debuginfo::LLVMInstructionSetDebugLoc,
+    module::{FlagBehavior, Module},
+    types::BasicTypeEnum,
+    values::{AsValueRef, FunctionValue, PointerValue},
+};
+
+use super::{context_ergonomics::ContextErgonomics, mangling};
+use crate::bytecode::Identifier;
+
+const DWARF_VERSION: u64 = 4;
+// The type encodings from the DWARF specification (`DW_ATE_*`)
+const ENCODING_UNSIGNED: u32 = 0x07;
+const ENCODING_UNSIGNED_CHAR: u32 = 0x08;
+
+/// Describes the code generated into a module to debuggers.
+///
+/// The source the debug info refers to is the textual form of the bytecode (as printed by its
+/// `Display` impl), where each instruction is on a line of its own, with the functions first and
+/// the top-level instructions after them. Nested values don't get locations of their own, they
+/// share the line of the instruction they're part of.
+pub(in crate::codegen) struct DebugInfo<'ctx> {
+    context: &'ctx Context,
+    builder: DebugInfoBuilder<'ctx>,
+    compile_unit: DICompileUnit<'ctx>,
+    u64_type: DIType<'ctx>,
+    value_pointer_type: DIType<'ctx>,
+    // The line of the listing where the next function (or the top-level instructions) starts
+    next_line: u32,
+}
+
+/// The debug info of the function that's currently being built.
+pub(in crate::codegen) struct DebugFunction<'ctx> {
+    scope: DIScope<'ctx>,
+    // The functions are indented in the listing, while the top-level instructions are not
+    column: u32,
+    // Every local gets a stack slot holding the pointer to its current value, so that it can be
+    // described to the debugger even after it gets reassigned
+    variables: HashMap<Identifier, PointerValue<'ctx>>,
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    pub(in crate::codegen) fn new(
+        module: &Module<'ctx>,
+        context: &'ctx Context,
+        file_name: &str,
+        directory: &Path,
+    ) -> Self {
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context.const_u32(debug_metadata_version()),
+        );
+        module.add_basic_value_flag(
+            "Dwarf Version",
+            FlagBehavior::Warning,
+            context.const_u64(DWARF_VERSION),
+        );
+
+        let (builder, compile_unit) = module.create_debug_info_builder(
+            true,
+            // There's no language code for us, C is the closest to how the values look
+            DWARFSourceLanguage::C,
+            file_name,
+            &directory.to_string_lossy(),
+            "lilith",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+
+        let u64_type = builder
+            .create_basic_type("u64", 64, ENCODING_UNSIGNED, DIFlags::ZERO)
+            .unwrap()
+            .as_type();
+        let value_pointer_type = Self::value_pointer_type(&builder, compile_unit);
+
+        Self {
+            context,
+            builder,
+            compile_unit,
+            u64_type,
+            value_pointer_type,
+            next_line: 1,
+        }
+    }
+
+    /// Returns the first of the next `count` lines of the listing.
+    pub(in crate::codegen) fn reserve_lines(&mut self, count: usize) -> u32 {
+        let line = self.next_line;
+        self.next_line += u32::try_from(count).unwrap();
+
+        line
+    }
+
+    /// Attaches a subprogram to the function, starting at the `line`. The locations of the code
+    /// built into the function afterwards are set with `set_location`.
+    pub(in crate::codegen) fn function(
+        &self,
+        function: FunctionValue<'ctx>,
+        line: u32,
+        column: u32,
+    ) -> DebugFunction<'ctx> {
+        let file = self.compile_unit.get_file();
+        let function_type = function.get_type();
+
+        let subroutine_type = self.builder.create_subroutine_type(
+            file,
+            function_type
+                .get_return_type()
+                .map(|return_type| self.describe_type(return_type)),
+            &function_type
+                .get_param_types()
+                .into_iter()
+                .map(|parameter_type| {
+                    self.describe_type(BasicTypeEnum::try_from(parameter_type).unwrap())
+                })
+                .collect::<Vec<_>>(),
+            DIFlags::ZERO,
+        );
+
+        DebugFunction {
+            scope: self.subprogram(function, line, subroutine_type, DIFlags::ZERO),
+            column,
+            variables: HashMap::new(),
+        }
+    }
+
+    fn subprogram(
+        &self,
+        function: FunctionValue<'ctx>,
+        line: u32,
+        subroutine_type: DISubroutineType<'ctx>,
+        flags: DIFlags,
+    ) -> DIScope<'ctx> {
+        // Debuggers show the demangled name, and use the symbol to find the function
+        let symbol = function.get_name().to_string_lossy();
+        let name = mangling::demangle(&symbol);
+        let subprogram = self.builder.create_function(
+            self.compile_unit.as_debug_info_scope(),
+            name.as_deref().unwrap_or(&symbol),
+            name.is_some().then_some(&*symbol),
+            self.compile_unit.get_file(),
+            line,
+            subroutine_type,
+            true,
+            true,
+            line,
+            flags,
+            false,
+        );
+        function.set_subprogram(subprogram);
+
+        subprogram.as_debug_info_scope()
+    }
+
+    /// Makes the instructions built afterwards point at the `line` of the listing.
+    pub(in crate::codegen) fn set_location(
+        &self,
+        function: &DebugFunction<'ctx>,
+        builder: &Builder<'ctx>,
+        line: u32,
+    ) {
+        builder.set_current_debug_location(self.builder.create_debug_location(
+            self.context,
+            line,
+            function.column,
+            function.scope,
+            None,
+        ));
+    }
+
+    /// Records that the local now holds the `value`. `argument` is the position of the argument
+    /// (counting from 1) if the local is one of the arguments of the function.
+    pub(in crate::codegen) fn assign_variable(
+        &self,
+        function: &mut DebugFunction<'ctx>,
+        builder: &Builder<'ctx>,
+        identifier: Identifier,
+        value: PointerValue<'ctx>,
+        argument: Option<u32>,
+    ) {
+        let location = builder
+            .get_current_debug_location()
+            .expect("the location is set before building any instructions");
+
+        let slot = *function.variables.entry(identifier).or_insert_with(|| {
+            let name = identifier.to_string();
+            let slot = builder
+                .build_alloca(self.context.ptr_type(AddressSpace::default()), &name)
+                .unwrap();
+
+            let file = self.compile_unit.get_file();
+            let variable = match argument {
+                Some(argument) => self.builder.create_parameter_variable(
+                    function.scope,
+                    &name,
+                    argument,
+                    file,
+                    location.get_line(),
+                    self.value_pointer_type,
+                    true,
+                    DIFlags::ZERO,
+                ),
+                None => self.builder.create_auto_variable(
+                    function.scope,
+                    &name,
+                    file,
+                    location.get_line(),
+                    self.value_pointer_type,
+                    true,
+                    DIFlags::ZERO,
+                    0,
+                ),
+            };
+
+            self.builder.insert_declare_at_end(
+                slot,
+                Some(variable),
+                None,
+                location,
+                builder.get_insert_block().unwrap(),
+            );
+
+            slot
+        });
+
+        builder.build_store(slot, value).unwrap();
+    }
+
+    /// Has to be called once all the code is built, before the module gets verified.
+    pub(in crate::codegen) fn finalize(&self) {
+        self.builder.finalize();
+    }
+
+    fn describe_type(&self, r#type: BasicTypeEnum<'ctx>) -> DIType<'ctx> {
+        match r#type {
+            BasicTypeEnum::PointerType(_) => self.value_pointer_type,
+            BasicTypeEnum::IntType(int_type) if int_type.get_bit_width() == 64 => self.u64_type,
+            _ => unreachable!("the generated functions only take and return values and u64s"),
+        }
+    }
+
+    // TODO this duplicates the layout of `types::values::Value`, it should be generated by the
+    // llvm_struct! macro instead
+    fn value_pointer_type(
+        builder: &DebugInfoBuilder<'ctx>,
+        compile_unit: DICompileUnit<'ctx>,
+    ) -> DIType<'ctx> {
+        let file = compile_unit.get_file();
+        let scope = compile_unit.as_debug_info_scope();
+
+        let members: Vec<_> = [
+            ("tag", 8, ENCODING_UNSIGNED_CHAR),
+            ("unused_0", 8, ENCODING_UNSIGNED_CHAR),
+            ("class_id", 16, ENCODING_UNSIGNED),
+            ("unused_1", 32, ENCODING_UNSIGNED),
+            ("raw", 64, ENCODING_UNSIGNED),
+        ]
+        .into_iter()
+        .scan(0, |offset, (name, size, encoding)| {
+            let member_type = builder
+                .create_basic_type(&format!("u{size}"), size, encoding, DIFlags::ZERO)
+                .unwrap()
+                .as_type();
+            let member = builder.create_member_type(
+                scope,
+                name,
+                file,
+                0,
+                size,
+                u32::try_from(size).unwrap(),
+                *offset,
+                DIFlags::ZERO,
+                member_type,
+            );
+            *offset += size;
+
+            Some(member.as_type())
+        })
+        .collect();
+
+        let value_type = builder.create_struct_type(
+            scope,
+            "Value",
+            file,
+            0,
+            128,
+            64,
+            DIFlags::ZERO,
+            None,
+            &members,
+            0,
+            None,
+            "Value",
+        );
+
+        builder
+            .create_pointer_type(
+                "Value*",
+                value_type.as_type(),
+                64,
+                64,
+                AddressSpace::default(),
+            )
+            .as_type()
+    }
+}
+
+/// Describes the functions of a module that wasn't generated from bytecode (like the type store),
+/// so that they at least show up with their names in the backtraces. There's no source for them,
+/// so all of their code is put on line 0.
+pub(in crate::codegen) fn describe_runtime_module<'ctx>(
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+) {
+    let name = module.get_name().to_string_lossy().into_owned();
+    let debug_info = DebugInfo::new(module, context, &name, Path::new(""));
+    // The signatures of the runtime functions are not described, as there's nothing but the
+    // values that the debug info knows how to describe
+    let subroutine_type = debug_info.builder.create_subroutine_type(
+        debug_info.compile_unit.get_file(),
+        None,
+        &[],
+        DIFlags::ZERO,
+    );
+
+    for function in module.get_functions() {
+        if function.count_basic_blocks() > 0 {
+            let scope = debug_info.subprogram(function, 0, subroutine_type, DIFlags::ARTIFICIAL);
+            // The verifier rejects calls between functions with debug info that have no location,
+            // as they couldn't be inlined, so every instruction gets one
+            let location = debug_info
+                .builder
+                .create_debug_location(context, 0, 0, scope, None);
+
+            for block in function.get_basic_block_iter() {
+                for instruction in block.get_instructions() {
+                    unsafe {
+                        LLVMInstructionSetDebugLoc(
+                            instruction.as_value_ref(),
+                            location.as_mut_ptr(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    debug_info.finalize();
+}
+
+#[cfg(test)]
+mod tests {
+    use inkwell::context::Context;
+
+    use super::describe_runtime_module;
+    use crate::codegen::ContextErgonomics;
+
+    #[test]
+    fn runtime_modules_calling_their_own_functions_verify() {
+        let context = Context::create();
+        let module = context.create_module("runtime");
+        let builder = context.create_builder();
+        let function_type = context.i64_type().fn_type(&[], false);
+
+        let called = module.add_function("called", function_type, None);
+        builder.position_at_end(context.append_basic_block(called, "entry"));
+        builder.build_return(Some(&context.const_u64(1))).unwrap();
+
+        let calling = module.add_function("calling", function_type, None);
+        builder.position_at_end(context.append_basic_block(calling, "entry"));
+        let result = builder
+            .build_call(called, &[], "result")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+        builder.build_return(Some(&result)).unwrap();
+
+        describe_runtime_module(&module, &context);
+
+        assert!(calling.get_subprogram().is_some());
+        module.verify().unwrap();
+    }
+}
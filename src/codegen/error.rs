@@ -0,0 +1,246 @@
+use std::{fmt::Display, path::PathBuf};
+
+use crate::bytecode::{TypeId, TypeTag, ValidationError};
+
+#[derive(Debug)]
+pub enum CodeGenError {
+    Invalid(ValidationError),
+    Verification {
+        module: String,
+        message: String,
+    },
+    Linking(String),
+    ExecutionEngine(String),
+    Target(String),
+    Listing {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Cache {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// The executable of the compiler can't be read, to key the module cache by
+    CompilerIdentity(std::io::Error),
+    /// The global constructors of the runtime modules depend on each other, listed from the
+    /// first one back to itself
+    InitializerCycle(Vec<String>),
+    UndefinedInitializer(String),
+    DuplicateInitializer(String),
+    /// There already is a type with the id in the type store
+    DuplicateType(TypeId),
+    UndefinedType(TypeId),
+    /// The id is one of the `TypeTag`s, which can't be stored, see `TypeId::RESERVED`
+    ReservedType(TypeId),
+    /// The type in the type store has a tag the host doesn't know about, which is the raw `tag`
+    UnknownTypeTag {
+        id: TypeId,
+        tag: u8,
+    },
+    Runtime(RuntimeError),
+    Call(CallError),
+}
+
+impl Display for CodeGenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(error) => write!(f, "{error}"),
+            Self::Verification { module, message } => {
+                write!(f, "module `{module}` failed verification: {message}")
+            }
+            Self::Linking(message) => write!(f, "failed to link modules: {message}"),
+            Self::ExecutionEngine(message) => write!(f, "execution engine: {message}"),
+            Self::Target(message) => write!(f, "failed to emit code for the target: {message}"),
+            Self::Listing { path, error } => {
+                write!(
+                    f,
+                    "failed to write the listing to {}: {error}",
+                    path.display()
+                )
+            }
+            Self::Cache { path, error } => {
+                write!(
+                    f,
+                    "failed to write the module cache to {}: {error}",
+                    path.display()
+                )
+            }
+            Self::CompilerIdentity(error) => {
+                write!(
+                    f,
+                    "failed to identify the compiler for the module cache: {error}"
+                )
+            }
+            Self::InitializerCycle(cycle) => write!(
+                f,
+                "global constructors depend on each other: {}",
+                cycle.join(" -> ")
+            ),
+            Self::UndefinedInitializer(name) => {
+                write!(f, "undefined global constructor `{name}`")
+            }
+            Self::DuplicateInitializer(name) => {
+                write!(f, "global constructor `{name}` is defined more than once")
+            }
+            Self::DuplicateType(id) => {
+                write!(f, "the type store already has a type with the id {id:?}")
+            }
+            Self::UndefinedType(id) => {
+                write!(f, "the type store has no type with the id {id:?}")
+            }
+            Self::ReservedType(id) => {
+                write!(f, "the id {id:?} is reserved for a type tag")
+            }
+            Self::UnknownTypeTag { id, tag } => {
+                write!(
+                    f,
+                    "the type with the id {id:?} has an unknown type tag {tag}"
+                )
+            }
+            Self::Runtime(error) => write!(f, "runtime error: {error}"),
+            Self::Call(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for CodeGenError {}
+
+impl From<ValidationError> for CodeGenError {
+    fn from(value: ValidationError) -> Self {
+        Self::Invalid(value)
+    }
+}
+
+impl From<RuntimeError> for CodeGenError {
+    fn from(value: RuntimeError) -> Self {
+        Self::Runtime(value)
+    }
+}
+
+impl From<CallError> for CodeGenError {
+    fn from(value: CallError) -> Self {
+        Self::Call(value)
+    }
+}
+
+/// An error the generated code ran into, reported back to the host.
+#[derive(Debug, Clone)]
+pub enum RuntimeError {
+    UnexpectedType {
+        expected: TypeTag,
+        /// The raw tag of the value, as it might not be a valid `TypeTag`
+        actual: u8,
+    },
+    /// A host function was passed an argument that doesn't match its signature
+    UnexpectedArgumentType {
+        function: String,
+        position: usize,
+        expected: TypeId,
+        actual: u8,
+    },
+    /// The program was run before the constructors of the runtime, or after its destructors
+    UninitializedRuntime,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let actual = match self {
+            Self::UnexpectedType { actual, .. } | Self::UnexpectedArgumentType { actual, .. } => {
+                *actual
+            }
+            Self::UninitializedRuntime => {
+                return write!(
+                    f,
+                    "the runtime isn't initialized, its constructors haven't run"
+                );
+            }
+        };
+
+        match self {
+            Self::UnexpectedType { expected, .. } => {
+                write!(f, "expected a value of type {expected:?}, got ")?;
+            }
+            Self::UnexpectedArgumentType {
+                function,
+                position,
+                expected,
+                ..
+            } => write!(
+                f,
+                "argument {position} of host function `{function}` should be of type \
+                 {expected:?}, got "
+            )?,
+            Self::UninitializedRuntime => unreachable!(),
+        }
+
+        match TypeTag::from_value(actual) {
+            Some(actual) => write!(f, "{actual:?}"),
+            None => write!(f, "an unknown type tag {actual}"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// A call from the host into a bytecode function that doesn't match the function's signature.
+#[derive(Debug, Clone)]
+pub enum CallError {
+    UndefinedFunction(String),
+    /// The type store has no signature under the id the function was registered with
+    MissingSignature(String),
+    ArityMismatch {
+        function: String,
+        expected: usize,
+        actual: usize,
+    },
+    ArgumentType {
+        function: String,
+        position: usize,
+        declared: TypeId,
+        passed: TypeId,
+    },
+    ReturnType {
+        function: String,
+        declared: TypeId,
+        requested: TypeId,
+    },
+}
+
+impl Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UndefinedFunction(name) => write!(f, "function `{name}` is not defined"),
+            Self::MissingSignature(name) => {
+                write!(f, "function `{name}` has no signature in the type store")
+            }
+            Self::ArityMismatch {
+                function,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "function `{function}` takes {expected} arguments, but {actual} were passed"
+            ),
+            Self::ArgumentType {
+                function,
+                position,
+                declared,
+                passed,
+            } => write!(
+                f,
+                "argument {position} of function `{function}` is of type {declared:?}, but \
+                 {passed:?} was passed"
+            ),
+            Self::ReturnType {
+                function,
+                declared,
+                requested,
+            } => write!(
+                f,
+                "function `{function}` returns {declared:?}, but {requested:?} was requested"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
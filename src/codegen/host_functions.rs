@@ -0,0 +1,359 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use inkwell::{
+    AddressSpace,
+    context::Context,
+    execution_engine::ExecutionEngine,
+    module::{Linkage, Module},
+    values::FunctionValue,
+};
+
+use super::{
+    CallTarget, CodeGen, builtins, bytecode_function_type,
+    context_ergonomics::ContextErgonomics,
+    error::RuntimeError,
+    mangling,
+    type_store::host::StoredType,
+    types::{classes::ClassId, values::Value},
+};
+use crate::bytecode::{Identifier, TypeId, TypeTag};
+
+// Keeps the traits that hand raw values to the generated code from being implemented outside of
+// the crate, the runtime trusts those values to be what their tags say
+pub(in crate::codegen) mod sealed {
+    pub trait Sealed {}
+}
+
+/// A Rust type that can be passed between the generated code and the host functions. It's
+/// sealed, only the types of this crate implement it.
+pub trait HostValue: Sized + sealed::Sealed {
+    const TYPE: TypeTag;
+
+    fn from_raw(raw: u64) -> Self;
+    fn into_raw(self) -> u64;
+}
+
+impl sealed::Sealed for u64 {}
+
+impl HostValue for u64 {
+    const TYPE: TypeTag = TypeTag::U64;
+
+    fn from_raw(raw: u64) -> Self {
+        raw
+    }
+
+    fn into_raw(self) -> u64 {
+        self
+    }
+}
+
+// Takes the raw values of the arguments, which are already checked against the signature
+type Implementation = Box<dyn Fn(&[u64]) -> u64>;
+
+pub struct HostFunction {
+    // The types of the arguments, the host checks the values passed to the function against them
+    arguments: Vec<TypeId>,
+    return_type: TypeId,
+    // Of the values the function returns, always the one of the `return_type`
+    return_tag: TypeTag,
+    implementation: Implementation,
+}
+
+impl HostFunction {
+    /// The signature the function gets registered with in the type store, the arguments are
+    /// named by their position.
+    #[must_use]
+    pub fn signature(&self) -> StoredType {
+        StoredType::FunctionSignature {
+            class_id: ClassId::none(),
+            arguments: (0..)
+                .map(Identifier::new)
+                .zip(self.arguments.iter().copied())
+                .collect(),
+            return_type: self.return_type,
+        }
+    }
+}
+
+/// Implemented for all the Rust functions and closures that take and return `HostValue`s, with
+/// the signature derived from their types.
+pub trait IntoHostFunction<Arguments> {
+    fn into_host_function(self) -> HostFunction;
+}
+
+macro_rules! impl_into_host_function {
+    ($($argument:ident),*) => {
+        impl<TFunction, TReturn, $($argument),*> IntoHostFunction<($($argument,)*)> for TFunction
+        where
+            TFunction: Fn($($argument),*) -> TReturn + 'static,
+            TReturn: HostValue,
+            $($argument: HostValue),*
+        {
+            #[allow(unused_mut, unused_variables, non_snake_case)]
+            fn into_host_function(self) -> HostFunction {
+                HostFunction {
+                    arguments: vec![$($argument::TYPE.into()),*],
+                    return_type: TReturn::TYPE.into(),
+                    return_tag: TReturn::TYPE,
+                    implementation: Box::new(move |arguments| {
+                        let mut arguments = arguments.iter().copied();
+                        $(let $argument = $argument::from_raw(arguments.next().unwrap());)*
+
+                        self($($argument),*).into_raw()
+                    }),
+                }
+            }
+        }
+    };
+}
+
+impl_into_host_function!();
+impl_into_host_function!(A);
+impl_into_host_function!(A, B);
+impl_into_host_function!(A, B, C);
+impl_into_host_function!(A, B, C, D);
+
+/// The host functions registered in a session, callable from the bytecode as any other function.
+///
+/// Every module gets an internal wrapper for each of the functions, with the same signature as
+/// the bytecode functions have, that collects the arguments into an array and passes them to the
+/// host, which checks their types, unpacks them, calls the function and packs the result.
+#[derive(Default)]
+pub(in crate::codegen) struct HostFunctions {
+    functions: Vec<(String, HostFunction)>,
+}
+
+pub(in crate::codegen) fn register<'ctx>(
+    execution_engine: &ExecutionEngine<'ctx>,
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+) {
+    let call_host_function = declare_call_host_function(module, context);
+
+    execution_engine.add_global_mapping(
+        &call_host_function,
+        call_host_function_impl
+            as extern "C" fn(
+                *const RefCell<HostFunctions>,
+                u32,
+                *const *const Value,
+            ) -> *const Value as usize,
+    );
+}
+
+impl HostFunctions {
+    /// Returns false if there already is a function with that name.
+    pub(in crate::codegen) fn add(&mut self, name: &str, function: HostFunction) -> bool {
+        if self.functions.iter().any(|(existing, _)| existing == name) {
+            return false;
+        }
+
+        self.functions.push((name.to_string(), function));
+
+        true
+    }
+
+    pub(in crate::codegen) const fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    /// The functions are only ever added, so this also tells which ones were there at some point.
+    pub(in crate::codegen) const fn len(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub(in crate::codegen) fn arities(&self) -> HashMap<String, usize> {
+        self.functions
+            .iter()
+            .map(|(name, function)| (name.clone(), function.arguments.len()))
+            .collect()
+    }
+
+    /// Builds the wrappers into the module and makes them callable from the code that `codegen`
+    /// builds into it afterwards.
+    pub(in crate::codegen) fn declare<'ctx>(
+        this: &RefCell<Self>,
+        codegen: &mut CodeGen<'ctx>,
+        module: &Module<'ctx>,
+    ) {
+        let count = this.borrow().len();
+        Self::declare_first(this, count, codegen, module);
+    }
+
+    /// Same as `declare`, but only for the first `count` functions, leaving out the ones added
+    /// since.
+    pub(in crate::codegen) fn declare_first<'ctx>(
+        this: &RefCell<Self>,
+        count: usize,
+        codegen: &mut CodeGen<'ctx>,
+        module: &Module<'ctx>,
+    ) {
+        let call_host_function = declare_call_host_function(module, codegen.context);
+
+        for (index, (name, function)) in this.borrow().functions[..count].iter().enumerate() {
+            let wrapper = build_wrapper(
+                module,
+                codegen.context,
+                name,
+                function.arguments.len(),
+                call_host_function,
+                (std::ptr::from_ref(this), u32::try_from(index).unwrap()),
+            );
+
+            codegen
+                .functions
+                .insert(name.clone(), CallTarget::Direct(wrapper));
+        }
+    }
+}
+
+extern "C" fn call_host_function_impl(
+    host_functions: *const RefCell<HostFunctions>,
+    index: u32,
+    arguments: *const *const Value,
+) -> *const Value {
+    let host_functions = unsafe { &*host_functions }.borrow();
+    let (name, function) = &host_functions.functions[index as usize];
+
+    let arguments = if function.arguments.is_empty() {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(arguments, function.arguments.len()) }
+    };
+
+    let mut raw_arguments = Vec::with_capacity(arguments.len());
+    for (position, (argument, expected)) in arguments.iter().zip(&function.arguments).enumerate() {
+        // The tag can be anything at this point, so it has to be read as a plain byte, not a
+        // TypeTag
+        let actual = unsafe { *argument.cast::<u8>() };
+
+        // The host functions only take `HostValue`s, whose types are all tags
+        if u32::from(actual) != expected.as_u32() {
+            builtins::report_runtime_error(RuntimeError::UnexpectedArgumentType {
+                function: name.clone(),
+                position,
+                expected: *expected,
+                actual,
+            });
+
+            // The generated code can't handle errors yet, so it just continues with a dummy
+            // value, and the host reports the error once it returns
+            return allocate_value(function.return_tag, 0);
+        }
+
+        raw_arguments.push(unsafe { &**argument }.raw);
+    }
+
+    allocate_value(
+        function.return_tag,
+        (function.implementation)(&raw_arguments),
+    )
+}
+
+fn allocate_value(tag: TypeTag, raw: u64) -> *const Value {
+    // Same as the values allocated by the generated code, these are never freed
+    Box::leak(Box::new(Value {
+        tag,
+        unused_0: 0,
+        class_id: ClassId::none(),
+        unused_1: 0,
+        raw,
+    }))
+}
+
+/// The symbol of `call_host_function_impl`, mangled with the types it's declared with.
+fn call_host_function_symbol() -> String {
+    mangling::mangle_function(
+        module_path!(),
+        "call_host_function",
+        &[
+            "*const RefCell<HostFunctions>",
+            "u32",
+            "*const *const Value",
+        ],
+        Some("*const Value"),
+    )
+}
+
+fn declare_call_host_function<'ctx>(
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+) -> FunctionValue<'ctx> {
+    let symbol = call_host_function_symbol();
+
+    module.get_function(&symbol).unwrap_or_else(|| {
+        let pointer_type = context.ptr_type(AddressSpace::default());
+
+        module.add_function(
+            &symbol,
+            pointer_type.fn_type(
+                &[
+                    pointer_type.into(),
+                    context.i32_type().into(),
+                    pointer_type.into(),
+                ],
+                false,
+            ),
+            None,
+        )
+    })
+}
+
+fn build_wrapper<'ctx>(
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+    name: &str,
+    arity: usize,
+    call_host_function: FunctionValue<'ctx>,
+    (host_functions, index): (*const RefCell<HostFunctions>, u32),
+) -> FunctionValue<'ctx> {
+    let pointer_type = context.ptr_type(AddressSpace::default());
+    let wrapper = module.add_function(
+        &format!("host.{name}"),
+        bytecode_function_type(context, arity),
+        Some(Linkage::Internal),
+    );
+
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(wrapper, "entry"));
+
+    let arguments = builder
+        .build_array_alloca(
+            pointer_type,
+            context.const_u32(u32::try_from(arity).unwrap()),
+            "arguments",
+        )
+        .unwrap();
+
+    for (index, parameter) in wrapper.get_param_iter().enumerate() {
+        let slot = unsafe {
+            builder.build_gep(
+                pointer_type,
+                arguments,
+                &[context.const_u64(index as u64)],
+                "argument_slot",
+            )
+        }
+        .unwrap();
+
+        builder.build_store(slot, parameter).unwrap();
+    }
+
+    let result = builder
+        .build_call(
+            call_host_function,
+            &[
+                context.const_ptr(host_functions).into(),
+                context.const_u32(index).into(),
+                arguments.into(),
+            ],
+            "result",
+        )
+        .unwrap()
+        .try_as_basic_value()
+        .unwrap_left();
+
+    builder.build_return(Some(&result)).unwrap();
+
+    wrapper
+}
@@ -0,0 +1,317 @@
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+use inkwell::{
+    AddressSpace,
+    context::Context,
+    execution_engine::ExecutionEngine,
+    module::{Linkage, Module},
+    types::FunctionType,
+    values::{BasicMetadataValueEnum, FunctionValue, PointerValue},
+};
+
+use super::{
+    CallTarget, CodeGen, CodeGenError, bytecode_function_type,
+    context_ergonomics::ContextErgonomics, function_symbol, host_functions::HostFunctions,
+    mangling,
+};
+use crate::bytecode::{
+    FunctionDefinition, Import, import_arities, validate_functions_with_externals,
+};
+
+/// Functions that are only compiled once they get called for the first time.
+///
+/// Every function gets a global holding the address that all the calls go through, initially
+/// pointing at a stub. The stub asks the host to compile the real body (in a module of its own),
+/// patches the global with its address and forwards the call, so any later calls go straight to
+/// the compiled body. Functions that never get called are never compiled.
+///
+/// The host calls the functions through exported entry points, which also go through the global.
+pub(in crate::codegen) struct LazyFunctions<'ctx> {
+    context: &'ctx Context,
+    execution_engine: ExecutionEngine<'ctx>,
+    // The name of the module the functions were declared in, used to keep the symbols of
+    // different units apart
+    prefix: String,
+    // The unit the module is a part of, whose listing the debug info points to
+    unit: String,
+    imports: Vec<Import>,
+    arities: Vec<(String, usize)>,
+    definitions: Vec<Option<FunctionDefinition>>,
+    // Where the definitions start in the listing of the unit, for the debug info
+    lines: Vec<u32>,
+    source_directory: PathBuf,
+    // The modules the bodies get compiled into need their own wrappers for the host functions
+    host_functions: Rc<RefCell<HostFunctions>>,
+    // How many host functions there were when the functions were checked, the ones registered
+    // later can't be called, as they could collide with the imports
+    host_function_count: usize,
+}
+
+pub(in crate::codegen) fn register<'ctx>(
+    execution_engine: &ExecutionEngine<'ctx>,
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+) {
+    let compile_function = declare_compile_function(module, context);
+
+    execution_engine.add_global_mapping(
+        &compile_function,
+        compile_function_impl as extern "C" fn(*const RefCell<LazyFunctions>, u32) -> *const ()
+            as usize,
+    );
+}
+
+impl<'ctx> LazyFunctions<'ctx> {
+    /// Emits the stubs for the functions into the module and makes them callable from the code
+    /// that `codegen` builds into it afterwards. The returned value must be kept alive for as
+    /// long as the stubs can be called.
+    ///
+    /// The functions can call the `imports` from the other modules of the `unit`, which must
+    /// already be declared in `codegen`.
+    pub(in crate::codegen) fn declare(
+        codegen: &mut CodeGen<'ctx>,
+        module: &Module<'ctx>,
+        (unit, imports): (&str, &[Import]),
+        functions: Vec<FunctionDefinition>,
+        execution_engine: &ExecutionEngine<'ctx>,
+        host_functions: &Rc<RefCell<HostFunctions>>,
+    ) -> Result<Rc<RefCell<Self>>, CodeGenError> {
+        // Errors can't be reported from inside the stubs, so anything that could fail has to be
+        // caught before any code runs
+        let mut externals = host_functions.borrow().arities();
+        externals.extend(import_arities(imports));
+        validate_functions_with_externals(&functions, &externals)?;
+
+        let context = codegen.context;
+        let pointer_type = context.ptr_type(AddressSpace::default());
+        let prefix = module.get_name().to_string_lossy().into_owned();
+
+        let arities: Vec<_> = functions
+            .iter()
+            .map(|function| (function.name.clone(), function.arguments.len()))
+            .collect();
+        let lines = functions
+            .iter()
+            .map(|function| codegen.reserve_lines(function.instructions.len() + 1))
+            .collect();
+
+        let lazy_functions = Rc::new(RefCell::new(Self {
+            context,
+            execution_engine: execution_engine.clone(),
+            prefix: prefix.clone(),
+            unit: unit.to_string(),
+            imports: imports.to_vec(),
+            arities: arities.clone(),
+            definitions: functions.into_iter().map(Some).collect(),
+            lines,
+            source_directory: codegen.source_directory.clone(),
+            host_functions: Rc::clone(host_functions),
+            host_function_count: host_functions.borrow().len(),
+        }));
+
+        let compile_function = declare_compile_function(module, context);
+
+        for (index, (name, arity)) in arities.into_iter().enumerate() {
+            let symbol = function_symbol(&prefix, &name, arity);
+            let address = module.add_global(pointer_type, None, &format!("{symbol}.address"));
+            let stub = build_stub(
+                module,
+                context,
+                &format!("{symbol}.stub"),
+                bytecode_function_type(context, arity),
+                compile_function,
+                (Rc::as_ptr(&lazy_functions), u32::try_from(index).unwrap()),
+                address.as_pointer_value(),
+            );
+
+            address.set_initializer(&stub.as_global_value().as_pointer_value());
+
+            build_entry(
+                module,
+                context,
+                &symbol,
+                bytecode_function_type(context, arity),
+                address.as_pointer_value(),
+            );
+
+            codegen
+                .functions
+                .insert(name, CallTarget::Indirect { address, arity });
+        }
+
+        Ok(lazy_functions)
+    }
+
+    /// Compiles the body of the function and returns its address.
+    fn compile(&mut self, index: usize) -> usize {
+        let definition = self.definitions[index]
+            .take()
+            .expect("the stub gets replaced after the first call");
+
+        // The exported name belongs to the entry point
+        let name = format!(
+            "{}.body",
+            function_symbol(&self.prefix, &definition.name, definition.arguments.len())
+        );
+        let pointer_type = self.context.ptr_type(AddressSpace::default());
+
+        let mut codegen =
+            CodeGen::new(self.context).with_source_directory(self.source_directory.clone());
+        // The function is a part of the unit's listing, so the debug info points there
+        let module = codegen.create_module(&name, &self.unit);
+        // The functions were checked against these host functions and imports before the stubs
+        // were emitted, so any errors from here on are bugs in the compiler
+        HostFunctions::declare_first(
+            &self.host_functions,
+            self.host_function_count,
+            &mut codegen,
+            &module,
+        );
+        codegen
+            .declare_imports(&module, &self.unit, &self.imports)
+            .unwrap();
+        codegen
+            .functions
+            .extend(self.arities.iter().map(|(function, arity)| {
+                let address = module.add_global(
+                    pointer_type,
+                    None,
+                    &format!(
+                        "{}.address",
+                        function_symbol(&self.prefix, function, *arity)
+                    ),
+                );
+
+                (
+                    function.clone(),
+                    CallTarget::Indirect {
+                        address,
+                        arity: *arity,
+                    },
+                )
+            }));
+
+        let function = module.add_function(
+            &name,
+            bytecode_function_type(self.context, definition.arguments.len()),
+            None,
+        );
+
+        codegen
+            .build_function(function, definition, self.lines[index])
+            .unwrap();
+        codegen.verify(&module).unwrap();
+
+        self.execution_engine.add_module(&module).unwrap();
+        self.execution_engine.get_function_address(&name).unwrap()
+    }
+}
+
+extern "C" fn compile_function_impl(
+    lazy_functions: *const RefCell<LazyFunctions>,
+    index: u32,
+) -> *const () {
+    let lazy_functions = unsafe { &*lazy_functions };
+
+    lazy_functions.borrow_mut().compile(index as usize) as *const ()
+}
+
+/// The symbol of `compile_function_impl`, mangled with the types it's declared with.
+fn compile_function_symbol() -> String {
+    mangling::mangle_function(
+        module_path!(),
+        "compile_function",
+        &["*const RefCell<LazyFunctions>", "u32"],
+        Some("*const ()"),
+    )
+}
+
+fn declare_compile_function<'ctx>(
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+) -> FunctionValue<'ctx> {
+    let symbol = compile_function_symbol();
+
+    module.get_function(&symbol).unwrap_or_else(|| {
+        let pointer_type = context.ptr_type(AddressSpace::default());
+
+        module.add_function(
+            &symbol,
+            pointer_type.fn_type(&[pointer_type.into(), context.i32_type().into()], false),
+            None,
+        )
+    })
+}
+
+fn build_stub<'ctx>(
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+    name: &str,
+    function_type: FunctionType<'ctx>,
+    compile_function: FunctionValue<'ctx>,
+    (lazy_functions, index): (*const RefCell<LazyFunctions<'ctx>>, u32),
+    address: PointerValue<'ctx>,
+) -> FunctionValue<'ctx> {
+    let stub = module.add_function(name, function_type, Some(Linkage::Internal));
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(stub, "entry"));
+
+    let compiled = builder
+        .build_call(
+            compile_function,
+            &[
+                context.const_ptr(lazy_functions).into(),
+                context.const_u32(index).into(),
+            ],
+            "compiled",
+        )
+        .unwrap()
+        .try_as_basic_value()
+        .unwrap_left()
+        .into_pointer_value();
+
+    builder.build_store(address, compiled).unwrap();
+
+    let arguments: Vec<BasicMetadataValueEnum> = stub.get_param_iter().map(Into::into).collect();
+    let result = builder
+        .build_indirect_call(function_type, compiled, &arguments, "result")
+        .unwrap();
+    result.set_tail_call(true);
+
+    builder
+        .build_return(Some(&result.try_as_basic_value().unwrap_left()))
+        .unwrap();
+
+    stub
+}
+
+fn build_entry<'ctx>(
+    module: &Module<'ctx>,
+    context: &'ctx Context,
+    name: &str,
+    function_type: FunctionType<'ctx>,
+    address: PointerValue<'ctx>,
+) {
+    let entry = module.add_function(name, function_type, None);
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(entry, "entry"));
+
+    let function_pointer = builder
+        .build_load(
+            context.ptr_type(AddressSpace::default()),
+            address,
+            "function_pointer",
+        )
+        .unwrap()
+        .into_pointer_value();
+
+    let arguments: Vec<BasicMetadataValueEnum> = entry.get_param_iter().map(Into::into).collect();
+    let result = builder
+        .build_indirect_call(function_type, function_pointer, &arguments, "result")
+        .unwrap();
+    result.set_tail_call(true);
+
+    builder
+        .build_return(Some(&result.try_as_basic_value().unwrap_left()))
+        .unwrap();
+}
@@ -4,6 +4,7 @@ pub(in crate::codegen) mod raw_array;
 pub(in crate::codegen) mod representations;
 
 #[macro_export]
+#[doc(hidden)]
 macro_rules! get_field_inner {
     ($index:expr, $field_name:ident: $field_type:ty) => {
         paste::paste! {
@@ -41,6 +42,7 @@ macro_rules! get_field_inner {
 }
 
 #[macro_export]
+#[doc(hidden)]
 macro_rules! get_field {
     ($field_name_first:ident: $field_type_first:ty, $($field_name:ident: $field_type:ty),*) => {
         get_field_inner!(0u32, $field_name_first: $field_type_first);
@@ -58,6 +60,7 @@ macro_rules! get_field {
 }
 
 #[macro_export]
+#[doc(hidden)]
 macro_rules! llvm_struct {
     // TODO the lifetime here is kinda a lie, because the macros will all break if it's not called
     // ctx
@@ -11,6 +11,8 @@ use inkwell::{
 use super::representations::{LlvmRepresentation, OperandValue};
 use crate::codegen::{ConstOrValue, ContextErgonomics};
 
+// Nothing builds its arrays at runtime for now, the signature of main is a constant
+#[allow(unused)]
 pub(in crate::codegen) struct LlvmArray<'ctx, T: LlvmRepresentation<'ctx>> {
     pointer: PointerValue<'ctx>,
     length: ConstOrValue<'ctx, u64>,
@@ -18,6 +20,7 @@ pub(in crate::codegen) struct LlvmArray<'ctx, T: LlvmRepresentation<'ctx>> {
     phantom: PhantomData<T>,
 }
 
+#[allow(unused)]
 impl<'ctx, T: LlvmRepresentation<'ctx> + OperandValue<'ctx>> LlvmArray<'ctx, T>
 where
     <T as LlvmRepresentation<'ctx>>::LlvmValue: BasicValue<'ctx>,
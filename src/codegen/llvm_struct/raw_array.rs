@@ -7,6 +7,10 @@ use crate::codegen::{ConstOrValue, ContextErgonomics as _};
 pub(in crate::codegen) struct RawConstArray<T>(*const T);
 
 impl<T> RawConstArray<T> {
+    pub(in crate::codegen) const fn new(elements: *const T) -> Self {
+        Self(elements)
+    }
+
     pub unsafe fn iter(&self, length: usize) -> impl Iterator<Item = &T> {
         (0..length).map(|x| unsafe { &*self.0.add(x) })
     }
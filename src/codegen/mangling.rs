@@ -0,0 +1,326 @@
+//! The names the symbols of the generated code get, so that the runtime functions, the globals of
+//! the runtime modules and the bytecode functions can't collide with each other, nor with the
+//! symbols of the code they get linked with.
+//!
+//! The scheme is loosely modeled after the Itanium one:
+//!
+//! ```text
+//! symbol    := "_L" path [signature]
+//! path      := "N" segment+ "E"          ; the module path, ending with the name itself
+//! segment   := <length> <identifier>
+//! signature := "F" type* "E" result      ; the argument types, then the result
+//! result    := type | "v"                ; "v" for the functions that return nothing
+//! type      := "P" type                  ; a pointer
+//!            | "O" type                  ; an optional pointer
+//!            | "u"                       ; the unit type
+//!            | segment                   ; any other type, by its name
+//! ```
+//!
+//! So `TypeStoreGet` from `lilith::codegen::type_store::get`, taking an `u64` and returning a
+//! `*const Value` becomes `_LN6lilith7codegen10type_store3get12TypeStoreGetEF3u64EP5Value`, which
+//! demangles back to `lilith::codegen::type_store::get::TypeStoreGet(u64) -> *const Value`.
+//!
+//! The mangled names only consist of ASCII letters, digits and underscores, so they never have to
+//! be quoted in the IR, and the suffixes added to them (like `.body`) are kept apart by the dot.
+
+use std::fmt::Write as _;
+
+const PREFIX: &str = "_L";
+
+/// Mangles the name of a global, `path` being its `::` separated module path.
+pub(in crate::codegen) fn mangle_global(path: &str, name: &str) -> String {
+    let mut symbol = PREFIX.to_string();
+    push_path(&mut symbol, path, name);
+
+    symbol
+}
+
+/// Mangles the name of a function, `path` being its `::` separated module path. The types are
+/// given as written in Rust (for example `*const Value`), the result is `None` for procedures.
+pub(in crate::codegen) fn mangle_function(
+    path: &str,
+    name: &str,
+    arguments: &[&str],
+    result: Option<&str>,
+) -> String {
+    let mut symbol = mangle_global(path, name);
+
+    symbol.push('F');
+    for argument in arguments {
+        push_type(&mut symbol, argument);
+    }
+    symbol.push('E');
+
+    match result {
+        Some(result) => push_type(&mut symbol, result),
+        None => symbol.push('v'),
+    }
+
+    symbol
+}
+
+fn push_path(symbol: &mut String, path: &str, name: &str) {
+    symbol.push('N');
+    for segment in path.split("::").filter(|segment| !segment.is_empty()) {
+        push_segment(symbol, segment);
+    }
+    push_segment(symbol, name);
+    symbol.push('E');
+}
+
+fn push_segment(symbol: &mut String, segment: &str) {
+    write!(symbol, "{}{segment}", segment.len()).unwrap();
+}
+
+/// Encodes the type, from the tokens of the Rust type (as `stringify!` produces them, so there
+/// can be spaces all around).
+fn push_type(symbol: &mut String, r#type: &str) {
+    let r#type = r#type.trim();
+
+    if let Some(pointee) = r#type
+        .strip_prefix('*')
+        .map(str::trim_start)
+        .and_then(|pointer| {
+            pointer
+                .strip_prefix("const")
+                .or_else(|| pointer.strip_prefix("mut"))
+        })
+    {
+        symbol.push('P');
+        push_type(symbol, pointee);
+    } else if let Some(inner) = r#type
+        .strip_prefix("Option")
+        .map(str::trim_start)
+        .and_then(|option| option.strip_prefix('<'))
+        .and_then(|option| option.strip_suffix('>'))
+    {
+        symbol.push('O');
+        push_type(symbol, inner);
+    } else if r#type.replace(' ', "") == "()" {
+        symbol.push('u');
+    } else {
+        // Neither the path of the type nor its lifetimes make any difference to the generated
+        // code
+        let name = r#type.rsplit("::").next().unwrap_or(r#type);
+        let name = name.split('<').next().unwrap_or(name).trim();
+
+        push_segment(symbol, name);
+    }
+}
+
+/// Turns a mangled symbol back into a readable name, like
+/// `lilith::codegen::type_store::get::TypeStoreGet(u64) -> *const Value`. Any suffixes after the
+/// mangled part (like `.body`) are kept as they are.
+///
+/// Returns `None` if the symbol is not mangled.
+#[must_use]
+pub fn demangle(symbol: &str) -> Option<String> {
+    let mut demangler = Demangler {
+        rest: symbol.strip_prefix(PREFIX)?,
+    };
+    let mut readable = demangler.path()?;
+
+    if demangler.rest.starts_with('F') {
+        demangler.rest = &demangler.rest[1..];
+
+        let mut arguments = vec![];
+        while !demangler.eat('E') {
+            arguments.push(demangler.r#type()?);
+        }
+
+        write!(readable, "({})", arguments.join(", ")).unwrap();
+        if !demangler.eat('v') {
+            write!(readable, " -> {}", demangler.r#type()?).unwrap();
+        }
+    }
+
+    // The mangled part has to end where the identifier does, otherwise it's just something that
+    // happens to look like it
+    if demangler
+        .rest
+        .starts_with(|character: char| character.is_ascii_alphanumeric() || character == '_')
+    {
+        return None;
+    }
+
+    readable.push_str(demangler.rest);
+
+    Some(readable)
+}
+
+/// Demangles all the mangled symbols in the text, like an IR dump or a stack trace, leaving
+/// everything else as it is.
+#[must_use]
+pub fn demangle_symbols(text: &str) -> String {
+    let is_symbol_character =
+        |character: char| character.is_ascii_alphanumeric() || character == '_';
+
+    let mut demangled = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(PREFIX) {
+        // Only whole identifiers are symbols, not their ends
+        let is_identifier_start = !rest[..start].ends_with(is_symbol_character);
+        let end = rest[start..]
+            .find(|character: char| !is_symbol_character(character))
+            .map_or(rest.len(), |length| start + length);
+
+        demangled.push_str(&rest[..start]);
+        match demangle(&rest[start..end]).filter(|_| is_identifier_start) {
+            Some(symbol) => demangled.push_str(&symbol),
+            None => demangled.push_str(&rest[start..end]),
+        }
+
+        rest = &rest[end..];
+    }
+
+    demangled.push_str(rest);
+
+    demangled
+}
+
+struct Demangler<'symbol> {
+    rest: &'symbol str,
+}
+
+impl<'symbol> Demangler<'symbol> {
+    fn eat(&mut self, prefix: char) -> bool {
+        self.rest
+            .strip_prefix(prefix)
+            .map(|rest| self.rest = rest)
+            .is_some()
+    }
+
+    fn path(&mut self) -> Option<String> {
+        if !self.eat('N') {
+            return None;
+        }
+
+        let mut segments = vec![];
+        while !self.eat('E') {
+            segments.push(self.segment()?);
+        }
+
+        (!segments.is_empty()).then(|| segments.join("::"))
+    }
+
+    fn segment(&mut self) -> Option<&'symbol str> {
+        let digits = self
+            .rest
+            .find(|character: char| !character.is_ascii_digit())
+            .unwrap_or(self.rest.len());
+        let length: usize = self.rest[..digits].parse().ok()?;
+
+        // The length comes from the symbol, so it can be anything
+        let end = digits.checked_add(length)?;
+        let segment = self.rest.get(digits..end)?;
+        self.rest = &self.rest[end..];
+
+        Some(segment)
+    }
+
+    fn r#type(&mut self) -> Option<String> {
+        if self.eat('P') {
+            Some(format!("*const {}", self.r#type()?))
+        } else if self.eat('O') {
+            Some(format!("Option<{}>", self.r#type()?))
+        } else if self.eat('u') {
+            Some("()".to_string())
+        } else {
+            self.segment().map(str::to_string)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{demangle, demangle_symbols, mangle_function, mangle_global};
+
+    #[test]
+    fn mangled_symbols_demangle_back() {
+        let cases = [
+            (
+                mangle_function(
+                    "lilith::codegen::type_store::get",
+                    "TypeStoreGet",
+                    &["u64"],
+                    Some("*const Value"),
+                ),
+                "lilith::codegen::type_store::get::TypeStoreGet(u64) -> *const Value",
+            ),
+            (
+                mangle_function(
+                    "a::b",
+                    "Procedure",
+                    &["*mut  u8", "Option<*const ()>"],
+                    None,
+                ),
+                "a::b::Procedure(*const u8, Option<*const ()>)",
+            ),
+            (
+                mangle_function("", "Empty", &[], Some("crate::Value<'ctx>")),
+                "Empty() -> Value",
+            ),
+            (
+                mangle_global("type_store", "type_store"),
+                "type_store::type_store",
+            ),
+        ];
+
+        for (symbol, readable) in cases {
+            assert_eq!(demangle(&symbol).as_deref(), Some(readable), "{symbol}");
+        }
+
+        assert_eq!(
+            mangle_function(
+                "lilith::codegen::type_store::get",
+                "TypeStoreGet",
+                &["u64"],
+                Some("*const Value")
+            ),
+            "_LN6lilith7codegen10type_store3get12TypeStoreGetEF3u64EP5Value"
+        );
+        // The suffixes stay
+        assert_eq!(
+            demangle(&format!("{}.body", mangle_global("main", "f"))).as_deref(),
+            Some("main::f.body")
+        );
+    }
+
+    #[test]
+    fn malformed_symbols_are_rejected() {
+        for symbol in [
+            "main",
+            "_L",
+            "_LNE",
+            "_LN4main",
+            "_LN5mainE",
+            "_LN18446744073709551615aE",
+            "_LN99999999999999999999999aE",
+            "_LN4mainEF",
+            "_LN4mainEF3u64E",
+            "_LN4mainEF3u64EP",
+            // It has to end where the identifier does
+            "_LN4mainEx",
+        ] {
+            assert_eq!(demangle(symbol), None, "{symbol}");
+        }
+    }
+
+    #[test]
+    fn only_the_mangled_symbols_in_the_text_are_demangled() {
+        let symbol = mangle_function("main", "f", &["u64"], Some("u64"));
+        let text = format!(
+            "call i64 @{symbol}(i64 1) ; x{symbol} _LN18446744073709551615aE {symbol}.body _L"
+        );
+
+        assert_eq!(
+            demangle_symbols(&text),
+            format!(
+                "call i64 @main::f(u64) -> u64(i64 1) ; x{symbol} _LN18446744073709551615aE \
+                 main::f(u64) -> u64.body _L"
+            )
+        );
+        assert_eq!(demangle_symbols("nothing to see"), "nothing to see");
+    }
+}
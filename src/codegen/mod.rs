@@ -1,42 +1,121 @@
+pub(in crate::codegen) mod aot;
 pub(in crate::codegen) mod builtins;
+pub(in crate::codegen) mod cache;
+pub(in crate::codegen) mod calls;
 #[macro_use]
 pub(in crate::codegen) mod context;
 pub(in crate::codegen) mod context_ergonomics;
+pub(in crate::codegen) mod debug_info;
+pub(in crate::codegen) mod error;
+pub(in crate::codegen) mod host_functions;
+pub(in crate::codegen) mod lazy;
 #[macro_use]
 pub(in crate::codegen) mod llvm_struct;
+pub(in crate::codegen) mod mangling;
 pub(in crate::codegen) mod module;
+pub(in crate::codegen) mod session;
 pub(in crate::codegen) mod type_store;
 pub(in crate::codegen) mod types;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
+pub use aot::write_object_file;
+pub use calls::BytecodeArguments;
 use context::{Function, Procedure as _};
 use context_ergonomics::ContextErgonomics;
-use inkwell::{builder::Builder, context::Context};
-use llvm_struct::{opaque_struct::LlvmArray, representations::ConstOrValue};
+use debug_info::{DebugFunction, DebugInfo};
+pub use error::{CallError, CodeGenError, RuntimeError};
+pub use host_functions::{HostFunction, HostValue, IntoHostFunction};
+use inkwell::{
+    AddressSpace, IntPredicate,
+    builder::Builder,
+    context::Context,
+    module::{Linkage, Module},
+    types::FunctionType,
+    values::{BasicMetadataValueEnum, BasicValue, FunctionValue, GlobalValue, PointerValue},
+};
+use llvm_struct::representations::{ConstOrValue, LlvmRepresentation as _};
+pub use mangling::{demangle, demangle_symbols};
 use module::built_module::ModuleInterface as _;
+pub use session::{CompilationMode, Session};
 use type_store::TypeStoreInterface;
+pub use type_store::{
+    dump::{DumpedArgument, DumpedKind, DumpedType, TypeReference, TypeStoreDump},
+    handle::TypeStoreHandle,
+    host::StoredType,
+};
+pub use types::classes::ClassId;
 use types::{
-    classes::ClassId,
-    functions::{FunctionArgument, FunctionSignatureOpaque, FunctionSignatureProvider},
-    values::{ValueOpaque, ValueOpaquePointer, ValueProvider},
+    functions::{FunctionArgumentProvider, FunctionSignatureProvider},
+    values::{Value, ValueOpaque, ValueOpaquePointer, ValueProvider},
 };
 
-use crate::bytecode::{ByteCode, ConstValue, Expression, Identifier, TypeTag};
+use crate::bytecode::{
+    ByteCode, ConstValue, Expression, FunctionDefinition, Identifier, Import, TypeId, TypeTag,
+    ValidationError, validate_modules,
+};
+
+#[derive(Clone, Copy)]
+enum CallTarget<'ctx> {
+    Direct(FunctionValue<'ctx>),
+    /// The function is called through the pointer stored in the global, see `lazy`
+    Indirect {
+        address: GlobalValue<'ctx>,
+        arity: usize,
+    },
+}
 
 pub struct CodeGen<'ctx> {
     context: &'ctx Context,
     scope: HashMap<Identifier, ValueOpaquePointer<'ctx>>,
+    functions: HashMap<String, CallTarget<'ctx>>,
+    // The debug info of the module last created with `create_module`, and of the function that's
+    // being built into it
+    debug_info: Option<DebugInfo<'ctx>>,
+    debug_function: Option<DebugFunction<'ctx>>,
+    source_directory: PathBuf,
 }
 
+// The functions are indented in the listing, so their instructions start further to the right
+const TOP_LEVEL_COLUMN: u32 = 1;
+const FUNCTION_COLUMN: u32 = 5;
+
 impl<'ctx> CodeGen<'ctx> {
+    #[must_use]
     pub fn new(context: &'ctx Context) -> Self {
         Self {
             context,
             scope: HashMap::new(),
+            functions: HashMap::new(),
+            debug_info: None,
+            debug_function: None,
+            source_directory: std::env::current_dir().unwrap_or_default(),
         }
     }
 
+    /// Sets the directory in which the debug info expects the listings of the bytecode to be.
+    #[must_use]
+    pub fn with_source_directory(mut self, source_directory: PathBuf) -> Self {
+        self.source_directory = source_directory;
+        self
+    }
+
+    /// Creates a module to build bytecode into, along with its debug info. The source the debug
+    /// info refers to is the listing of the bytecode in `{source}.lil`, see `DebugInfo`.
+    pub(in crate::codegen) fn create_module(&mut self, name: &str, source: &str) -> Module<'ctx> {
+        let module = self.context.create_module(name);
+        // The call targets are values of the module they were declared in
+        self.functions.clear();
+        self.debug_info = Some(DebugInfo::new(
+            &module,
+            self.context,
+            &format!("{source}.lil"),
+            &self.source_directory,
+        ));
+
+        module
+    }
+
     fn build_expression(
         &mut self,
         expression: Expression,
@@ -44,13 +123,13 @@ impl<'ctx> CodeGen<'ctx> {
         // more level of abstraction tho, idk)
         builder: &Builder<'ctx>,
         context: &'ctx Context,
-    ) -> ValueOpaquePointer<'ctx> {
+    ) -> Result<ValueOpaquePointer<'ctx>, CodeGenError> {
         match expression {
             Expression::Add(left, right) => {
                 // TODO we should check if either of the values implements an interface that allows
                 // for the desired addition and execute on it, otherwise throw an error
-                let left = self.build_value(left, builder, context);
-                let right = self.build_value(right, builder, context);
+                let left = self.build_value(left, builder, context)?;
+                let right = self.build_value(right, builder, context)?;
 
                 let result_value = builder
                     .build_int_add(left.get_raw(builder), right.get_raw(builder), "sum_value")
@@ -59,7 +138,7 @@ impl<'ctx> CodeGen<'ctx> {
                 // TODO the .llvm_context here is needed because the value needs to know the
                 // context type, but perhaps we can switch up to dyn or something there to side-step the
                 // issue (I don't think the value should really have the knowledge of context type)
-                ValueProvider::new(context).make_value(
+                Ok(ValueProvider::new(context).make_value(
                     builder,
                     ValueOpaque {
                         tag: ConstOrValue::Const(TypeTag::U64),
@@ -68,27 +147,201 @@ impl<'ctx> CodeGen<'ctx> {
                         unused_1: ConstOrValue::Const(0),
                         raw: ConstOrValue::Value(result_value),
                     },
-                )
+                ))
             }
             Expression::Assignment(binding, value) => {
-                let expression = self.build_value(value, builder, context);
+                let expression = self.build_value(value, builder, context)?;
                 self.scope.insert(binding, expression);
-                expression
+                self.assign_variable(builder, binding, expression.ptr(), None);
+
+                Ok(expression)
             }
+            Expression::Call(name, arguments) => {
+                let Some(&target) = self.functions.get(&name) else {
+                    return Err(ValidationError::UndefinedFunction(name).into());
+                };
+
+                let arity = match target {
+                    CallTarget::Direct(function) => function.count_params() as usize,
+                    CallTarget::Indirect { arity, .. } => arity,
+                };
+                if arity != arguments.len() {
+                    return Err(ValidationError::ArityMismatch {
+                        function: name,
+                        expected: arity,
+                        actual: arguments.len(),
+                    }
+                    .into());
+                }
+
+                let arguments = arguments
+                    .into_iter()
+                    .map(|argument| {
+                        self.build_value(argument, builder, context)
+                            .map(|value| BasicMetadataValueEnum::from(value.ptr()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let call = match target {
+                    CallTarget::Direct(function) => {
+                        builder.build_call(function, &arguments, "call")
+                    }
+                    CallTarget::Indirect { address, arity } => {
+                        let function_pointer = builder
+                            .build_load(
+                                context.ptr_type(AddressSpace::default()),
+                                address.as_pointer_value(),
+                                "function_pointer",
+                            )
+                            .unwrap()
+                            .into_pointer_value();
+
+                        builder.build_indirect_call(
+                            bytecode_function_type(context, arity),
+                            function_pointer,
+                            &arguments,
+                            "call",
+                        )
+                    }
+                }
+                .unwrap();
+
+                Ok(ValueProvider::new(context)
+                    .opaque_pointer(call.try_as_basic_value().unwrap_left().into_pointer_value()))
+            }
+        }
+    }
+
+    /// Generates the `main` module for the bytecode, with its modules and the runtime modules it
+    /// depends on already linked in. The result is verified, but not yet attached to any
+    /// execution engine.
+    ///
+    /// # Errors
+    ///
+    /// If the bytecode is not valid, or the generated code fails the verification.
+    pub fn compile(&mut self, bytecode: ByteCode) -> Result<Module<'ctx>, CodeGenError> {
+        validate_modules(&bytecode)?;
+        let (module_lines, main_line) = bytecode.function_lines();
+
+        // Every bytecode module gets compiled on its own, same as it would be in a session, and
+        // only then linked together
+        let mut modules = vec![];
+        for (definition, line) in bytecode.modules.into_iter().zip(module_lines) {
+            let module = self.create_module(&module_name("main", &definition.name), "main");
+            self.skip_to_line(line);
+            self.declare_imports(&module, "main", &definition.imports)?;
+            self.define_functions(&module, definition.functions)?;
+            self.verify(&module)?;
+
+            modules.push(module);
+        }
+
+        let module = self.create_module("main", "main");
+        self.skip_to_line(main_line);
+        self.declare_imports(&module, "main", &bytecode.imports)?;
+        self.define_functions(&module, bytecode.functions)?;
+        self.build_main(&module, bytecode.instructions)?;
+
+        let runtime_modules = build_runtime_modules(self.context)?;
+
+        for linked in modules.into_iter().chain(runtime_modules) {
+            module
+                .link_in_module(linked)
+                .map_err(|error| CodeGenError::Linking(error.to_string()))?;
         }
+
+        Ok(module)
+    }
+
+    /// Declares the functions the module imports from the other modules of the `unit`, so that
+    /// the code built afterwards can call them. They're resolved when the modules get linked, or
+    /// by the execution engine.
+    pub(in crate::codegen) fn declare_imports(
+        &mut self,
+        module: &Module<'ctx>,
+        unit: &str,
+        imports: &[Import],
+    ) -> Result<(), CodeGenError> {
+        for import in imports {
+            let symbol = function_symbol(
+                &module_name(unit, &import.module),
+                &import.function,
+                import.arity,
+            );
+            let function = module.get_function(&symbol).unwrap_or_else(|| {
+                module.add_function(
+                    &symbol,
+                    bytecode_function_type(self.context, import.arity),
+                    None,
+                )
+            });
+
+            // Also catches the imports shadowing host functions
+            if self
+                .functions
+                .insert(import.function.clone(), CallTarget::Direct(function))
+                .is_some()
+            {
+                return Err(ValidationError::DuplicateFunction(import.function.clone()).into());
+            }
+        }
+
+        Ok(())
     }
 
-    pub fn execute(mut self, bytecode: ByteCode) -> u64 {
+    /// Defines the bytecode functions in the module, so that the code built afterwards can call
+    /// them.
+    pub(in crate::codegen) fn define_functions(
+        &mut self,
+        module: &Module<'ctx>,
+        functions: Vec<FunctionDefinition>,
+    ) -> Result<(), CodeGenError> {
+        let prefix = module.get_name().to_string_lossy().into_owned();
+
+        let mut values = vec![];
+        for definition in &functions {
+            // Exported under a name unique to the module, so the host can call it directly
+            let function = module.add_function(
+                &function_symbol(&prefix, &definition.name, definition.arguments.len()),
+                bytecode_function_type(self.context, definition.arguments.len()),
+                None,
+            );
+
+            if self
+                .functions
+                .insert(definition.name.clone(), CallTarget::Direct(function))
+                .is_some()
+            {
+                return Err(ValidationError::DuplicateFunction(definition.name.clone()).into());
+            }
+
+            values.push(function);
+        }
+
+        for (function, definition) in values.into_iter().zip(functions) {
+            let line = self.reserve_lines(definition.instructions.len() + 1);
+            self.build_function(function, definition, line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds an entry function named after the module that runs the instructions and returns
+    /// their result as an `u64`. The runtime modules are only declared, so they have to be
+    /// provided either by linking or by the execution engine.
+    pub(in crate::codegen) fn build_main(
+        &mut self,
+        module: &Module<'ctx>,
+        instructions: Vec<Expression>,
+    ) -> Result<(), CodeGenError> {
         // TODO the main module should also use the api from crate::codegen::module, instead of
         // straight up calling the inkwell apis
-        let module = self.context.create_module("main");
-        let execution_engine = module
-            .create_jit_execution_engine(inkwell::OptimizationLevel::Aggressive)
-            .unwrap();
+        let name = module.get_name().to_string_lossy().into_owned();
         let builder = self.context.create_builder();
+        self.scope.clear();
 
         let main = module.add_function(
-            "main",
+            &name,
             // TODO we should use the type_maker here, but that requires first that CodegenContext
             // does not use builder
             self.context.i64_type().fn_type(&[], false),
@@ -97,91 +350,351 @@ impl<'ctx> CodeGen<'ctx> {
         let entry_block = self.context.append_basic_block(main, "entry");
         builder.position_at_end(entry_block);
 
-        builtins::register(&execution_engine, &module, self.context);
+        let line = self.reserve_lines(instructions.len());
+        self.enter_function(main, line, TOP_LEVEL_COLUMN);
+        self.set_location(&builder, line);
+
+        builtins::declare(module, self.context);
 
-        let type_store_module = type_store::register(self.context);
         let type_store_api: TypeStoreInterface =
-            TypeStoreInterface::expose_to(&module, self.context);
+            TypeStoreInterface::expose_to(module, self.context);
+        self.build_runtime_check(module, main, &builder, &type_store_api);
 
-        let arguments = LlvmArray::const_length_new(
-            [FunctionArgument {
-                name: Identifier::new(1),
-                type_id: TypeTag::U64.into(),
-            }],
-            self.context,
-            &builder,
-        );
+        // Interning is idempotent, so running the program again leaves the store as it is
+        let signature_value = self.build_main_signature(module);
+        type_store_api.intern.build_call(&builder, signature_value);
+
+        // Without any instructions there's no result to return, the interpreter rejects such
+        // programs as well
+        let Some(result) = self.build_instructions(instructions, &builder, line)? else {
+            return Err(ValidationError::EmptyProgram.into());
+        };
+        self.build_checked_return(module, main, &builder, result, TypeTag::U64);
+
+        self.verify(module)
+    }
+
+    /// Adds the signature of `main`, which takes no arguments and returns an `u64`, to the module
+    /// as constants, returning the pointer to its value. The type store keeps pointing at the value if it's the first equal one interned, so
+    /// the constants have to live as long as the module is loaded, which they do.
+    fn build_main_signature(&self, module: &Module<'ctx>) -> PointerValue<'ctx> {
+        let context = self.context;
+        let add_constant = |name, value: &dyn BasicValue<'ctx>| {
+            let value = value.as_basic_value_enum();
+            let global = module.add_global(value.get_type(), None, name);
+            global.set_initializer(&value);
+            global.set_constant(true);
+            global.set_linkage(Linkage::Private);
+
+            global.as_pointer_value()
+        };
+
+        let argument_type = FunctionArgumentProvider::new(context).llvm_type();
+        let arguments = add_constant("main.signature.arguments", &argument_type.const_array(&[]));
 
-        let signature_ptr = FunctionSignatureProvider::new(self.context).make_value(
-            &builder,
-            FunctionSignatureOpaque {
-                class_id: ConstOrValue::Const(ClassId::none()),
-                argument_count: ConstOrValue::Const(1),
-                return_type_id: ConstOrValue::Const(TypeTag::U64.into()),
-                arguments: ConstOrValue::Value(arguments.as_pointer()),
-            },
+        let signature = add_constant(
+            "main.signature",
+            &FunctionSignatureProvider::new(context)
+                .llvm_type()
+                .const_named_struct(&[
+                    context
+                        .i16_type()
+                        .const_int(u64::from(ClassId::none().as_u16()), false)
+                        .into(),
+                    context.i16_type().const_zero().into(),
+                    context
+                        .const_u32(TypeId::from(TypeTag::U64).as_u32())
+                        .into(),
+                    arguments.into(),
+                ]),
         );
 
-        let ptr_int = builder
-            .build_ptr_to_int(
-                signature_ptr.ptr(),
-                self.context.i64_type(),
-                "signature_value_int",
-            )
+        add_constant(
+            "main.signature.value",
+            &ValueProvider::new(context)
+                .llvm_type()
+                .const_named_struct(&[
+                    TypeTag::llvm_type(context)
+                        .const_int(TypeTag::FunctionSignature as u64, false)
+                        .into(),
+                    context.i8_type().const_zero().into(),
+                    context
+                        .i16_type()
+                        .const_int(u64::from(ClassId::none().as_u16()), false)
+                        .into(),
+                    context.const_u32(0).into(),
+                    signature.const_to_int(context.i64_type()).into(),
+                ]),
+        )
+    }
+
+    /// Returns the raw value of the result, if it's of the expected type. Otherwise the mismatch is
+    /// reported to the host, and 0 is returned instead.
+    /// Makes the function report an error and return 0 if the type store hasn't been set up, as
+    /// when an object file is linked without running the global constructors of the runtime, or
+    /// the runtime has been torn down already. The store has no storage until its constructor
+    /// runs, and again after its destructor.
+    fn build_runtime_check(
+        &self,
+        module: &Module<'ctx>,
+        function: FunctionValue<'ctx>,
+        builder: &Builder<'ctx>,
+        type_store_api: &TypeStoreInterface<'ctx>,
+    ) {
+        let slots = type_store_api.type_store.get_slots(builder);
+        let is_initialized = builder.build_is_not_null(slots, "is_initialized").unwrap();
+
+        let initialized_block = self.context.append_basic_block(function, "initialized");
+        let uninitialized_block = self.context.append_basic_block(function, "uninitialized");
+        builder
+            .build_conditional_branch(is_initialized, initialized_block, uninitialized_block)
             .unwrap();
 
-        let signature_value = ValueProvider::new(self.context).make_value(
-            &builder,
-            ValueOpaque {
-                tag: ConstOrValue::Const(TypeTag::FunctionSignature),
-                unused_0: ConstOrValue::Const(0),
-                class_id: ConstOrValue::Const(ClassId::none()),
-                unused_1: ConstOrValue::Const(0),
-                raw: ConstOrValue::Value(ptr_int),
-            },
-        );
+        builder.position_at_end(uninitialized_block);
+        builtins::report_uninitialized_runtime(module, self.context).build_call(builder, ());
+        builder
+            .build_return(Some(&self.context.const_u64(0)))
+            .unwrap();
+
+        builder.position_at_end(initialized_block);
+    }
+
+    fn build_checked_return(
+        &self,
+        module: &Module<'ctx>,
+        function: FunctionValue<'ctx>,
+        builder: &Builder<'ctx>,
+        result: ValueOpaquePointer<'ctx>,
+        expected: TypeTag,
+    ) {
+        let expected = TypeTag::llvm_type(self.context).const_int(expected as u64, false);
+        let tag = result.get_tag(builder);
+
+        let is_expected = builder
+            .build_int_compare(IntPredicate::EQ, tag, expected, "is_expected_type")
+            .unwrap();
+
+        let valid_block = self.context.append_basic_block(function, "valid_result");
+        let invalid_block = self.context.append_basic_block(function, "invalid_result");
+        builder
+            .build_conditional_branch(is_expected, valid_block, invalid_block)
+            .unwrap();
 
-        type_store_api.add.build_call(
-            &builder,
-            (self.context.const_u32(1024), signature_value.ptr()),
+        builder.position_at_end(valid_block);
+        builder
+            .build_return(Some(&result.get_raw(builder)))
+            .unwrap();
+
+        builder.position_at_end(invalid_block);
+        builtins::report_unexpected_type(module, self.context)
+            .build_call(builder, (expected, result.ptr()));
+        builder
+            .build_return(Some(&self.context.const_u64(0)))
+            .unwrap();
+    }
+
+    /// Adds an entry function named after the module, that runs the instructions on top of the
+    /// previously executed units, with `bindings` being the locals they left behind. The entry
+    /// function has the signature `fn(bindings: *mut *const Value) -> *const Value`, and before
+    /// returning it fills the slots in `bindings` with all the locals in scope, in the order of
+    /// the returned identifiers.
+    pub(in crate::codegen) fn build_unit(
+        &mut self,
+        module: &Module<'ctx>,
+        instructions: Vec<Expression>,
+        bindings: &HashMap<Identifier, *const Value>,
+    ) -> Result<Vec<Identifier>, CodeGenError> {
+        let name = module.get_name().to_string_lossy().into_owned();
+        let builder = self.context.create_builder();
+        let pointer_type = self.context.ptr_type(AddressSpace::default());
+
+        let function = module.add_function(
+            &name,
+            pointer_type.fn_type(&[pointer_type.into()], false),
+            None,
         );
+        let entry_block = self.context.append_basic_block(function, "entry");
+        builder.position_at_end(entry_block);
 
-        let _first_type = type_store_api
-            .get
-            .build_call(&builder, self.context.const_u64(1024));
+        let line = self.reserve_lines(instructions.len());
+        self.enter_function(function, line, TOP_LEVEL_COLUMN);
+        self.set_location(&builder, line);
 
-        let mut result = None;
-        for instruction in bytecode.instructions {
-            result = Some(self.build_expression(instruction, &builder, self.context));
+        let value_provider = ValueProvider::new(self.context);
+        self.scope = bindings
+            .iter()
+            .map(|(identifier, value)| {
+                (
+                    *identifier,
+                    value_provider.opaque_pointer(self.context.const_ptr(*value)),
+                )
+            })
+            .collect();
+
+        let mut bound: Vec<_> = self
+            .scope
+            .iter()
+            .map(|(identifier, value)| (*identifier, value.ptr()))
+            .collect();
+        bound.sort_by_key(|(identifier, _)| identifier.as_u32());
+        for (identifier, value) in bound {
+            self.assign_variable(&builder, identifier, value, None);
         }
 
-        if let Some(result) = result {
-            // TODO we should codegen an actual check here to ensure this is an actual u64 and
-            // we're not just returning random whatever
+        let result = self.build_instructions(instructions, &builder, line)?;
+
+        let mut identifiers: Vec<_> = self.scope.keys().copied().collect();
+        identifiers.sort_by_key(|identifier| identifier.as_u32());
+
+        let slots = function.get_first_param().unwrap().into_pointer_value();
+        for (index, identifier) in identifiers.iter().enumerate() {
+            let slot = unsafe {
+                builder.build_gep(
+                    pointer_type,
+                    slots,
+                    &[self.context.const_u64(index as u64)],
+                    "binding_slot",
+                )
+            }
+            .unwrap();
+
             builder
-                .build_return(Some(&result.get_raw(&builder)))
+                .build_store(slot, self.scope[identifier].ptr())
                 .unwrap();
-        } else {
-            builder.build_return(None).unwrap();
         }
 
-        type_store_module.print_to_stderr();
-        type_store_module.verify().unwrap();
+        builder
+            .build_return(Some(
+                &result.map_or_else(|| pointer_type.const_null(), |result| result.ptr()),
+            ))
+            .unwrap();
 
-        module.print_to_stderr();
-        module.verify().unwrap();
+        self.verify(module)?;
 
-        module.link_in_module(type_store_module).unwrap();
+        Ok(identifiers)
+    }
+
+    /// Builds the body of the function, `line` is where the definition starts in the listing.
+    fn build_function(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        definition: FunctionDefinition,
+        line: u32,
+    ) -> Result<(), CodeGenError> {
+        let builder = self.context.create_builder();
+        let entry_block = self.context.append_basic_block(function, "entry");
+        builder.position_at_end(entry_block);
 
-        execution_engine.run_static_constructors();
-        let main = unsafe {
-            execution_engine
-                .get_function::<unsafe extern "C" fn() -> u64>("main")
-                .unwrap()
+        let outer_debug_function = self.enter_function(function, line, FUNCTION_COLUMN);
+        self.set_location(&builder, line);
+
+        let value_provider = ValueProvider::new(self.context);
+        let arguments = definition
+            .arguments
+            .iter()
+            .zip(function.get_param_iter())
+            .map(|(argument, parameter)| {
+                (
+                    *argument,
+                    value_provider.opaque_pointer(parameter.into_pointer_value()),
+                )
+            })
+            .collect();
+
+        let outer_scope = std::mem::replace(&mut self.scope, arguments);
+
+        for (position, argument) in (1..).zip(&definition.arguments) {
+            let value = self.scope[argument].ptr();
+            self.assign_variable(&builder, *argument, value, Some(position));
+        }
+
+        let result = self.build_instructions(definition.instructions, &builder, line + 1);
+        self.scope = outer_scope;
+        self.debug_function = outer_debug_function;
+
+        let Some(result) = result? else {
+            return Err(ValidationError::EmptyFunction(definition.name).into());
         };
-        execution_engine.run_static_destructors();
 
-        unsafe { main.call() }
+        builder.build_return(Some(&result.ptr())).unwrap();
+
+        Ok(())
+    }
+
+    /// Builds the instructions, `first_line` is the line of the first one in the listing.
+    fn build_instructions(
+        &mut self,
+        instructions: Vec<Expression>,
+        builder: &Builder<'ctx>,
+        first_line: u32,
+    ) -> Result<Option<ValueOpaquePointer<'ctx>>, CodeGenError> {
+        let mut result = None;
+        for (line, instruction) in (first_line..).zip(instructions) {
+            self.set_location(builder, line);
+            result = Some(self.build_expression(instruction, builder, self.context)?);
+        }
+
+        Ok(result)
+    }
+
+    fn reserve_lines(&mut self, count: usize) -> u32 {
+        self.debug_info
+            .as_mut()
+            .map_or(0, |debug_info| debug_info.reserve_lines(count))
+    }
+
+    /// Makes the code built afterwards start at the `line` of the listing, for the modules that
+    /// are only a part of it.
+    pub(in crate::codegen) fn skip_to_line(&mut self, line: u32) {
+        if let Some(debug_info) = &mut self.debug_info {
+            let next_line = debug_info.reserve_lines(0);
+            debug_info.reserve_lines(line.saturating_sub(next_line) as usize);
+        }
+    }
+
+    /// Makes the function the one that the debug info gets built for, returning the previous
+    /// one.
+    fn enter_function(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        line: u32,
+        column: u32,
+    ) -> Option<DebugFunction<'ctx>> {
+        let debug_function = self
+            .debug_info
+            .as_ref()
+            .map(|debug_info| debug_info.function(function, line, column));
+
+        std::mem::replace(&mut self.debug_function, debug_function)
+    }
+
+    fn set_location(&self, builder: &Builder<'ctx>, line: u32) {
+        if let (Some(debug_info), Some(debug_function)) = (&self.debug_info, &self.debug_function) {
+            debug_info.set_location(debug_function, builder, line);
+        }
+    }
+
+    fn assign_variable(
+        &mut self,
+        builder: &Builder<'ctx>,
+        identifier: Identifier,
+        value: PointerValue<'ctx>,
+        argument: Option<u32>,
+    ) {
+        if let (Some(debug_info), Some(debug_function)) =
+            (&self.debug_info, &mut self.debug_function)
+        {
+            debug_info.assign_variable(debug_function, builder, identifier, value, argument);
+        }
+    }
+
+    /// Finishes the debug info of the module and verifies it.
+    pub(in crate::codegen) fn verify(&self, module: &Module<'ctx>) -> Result<(), CodeGenError> {
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.finalize();
+        }
+
+        verify(module)
     }
 
     fn build_value(
@@ -189,11 +702,11 @@ impl<'ctx> CodeGen<'ctx> {
         value: crate::bytecode::Value,
         builder: &Builder<'ctx>,
         context: &'ctx Context,
-    ) -> ValueOpaquePointer<'ctx> {
+    ) -> Result<ValueOpaquePointer<'ctx>, CodeGenError> {
         match value {
             crate::bytecode::Value::Literal(const_value) => {
                 // TODO add some comfort methods for simple i*_type constants
-                ValueProvider::new(self.context).make_value(
+                Ok(ValueProvider::new(self.context).make_value(
                     builder,
                     ValueOpaque {
                         tag: ConstOrValue::Const(TypeTag::U64),
@@ -204,15 +717,61 @@ impl<'ctx> CodeGen<'ctx> {
                             ConstValue::U64(value) => value,
                         }),
                     },
-                )
-            }
-            crate::bytecode::Value::Local(identifier) => {
-                // TODO check here that the var actually exists
-                *self.scope.get(&identifier).unwrap()
+                ))
             }
+            crate::bytecode::Value::Local(identifier) => self
+                .scope
+                .get(&identifier)
+                .copied()
+                .ok_or_else(|| ValidationError::UndefinedLocal(identifier).into()),
             crate::bytecode::Value::Computed(expression) => {
                 self.build_expression(*expression, builder, context)
             }
         }
     }
 }
+
+/// All the bytecode functions take their arguments and return their result as pointers to
+/// `Value`.
+fn bytecode_function_type(context: &Context, arity: usize) -> FunctionType<'_> {
+    let pointer_type = context.ptr_type(AddressSpace::default());
+
+    pointer_type.fn_type(&vec![pointer_type.into(); arity], false)
+}
+
+/// The name of the LLVM module a bytecode module of the unit is compiled into.
+fn module_name(unit: &str, module: &str) -> String {
+    format!("{unit}.{module}")
+}
+
+/// The name the bytecode function is exported under from the module it's defined in, mangled
+/// with the module as its path.
+fn function_symbol(module: &str, function: &str, arity: usize) -> String {
+    mangling::mangle_function(
+        &module.replace('.', "::"),
+        function,
+        &vec!["*const Value"; arity],
+        Some("*const Value"),
+    )
+}
+
+/// Builds the modules of the runtime, the last of them runs the constructors of the others, see
+/// `ModuleBuilderProvider::build`.
+fn build_runtime_modules(context: &Context) -> Result<Vec<Module<'_>>, CodeGenError> {
+    let module_builder_provider = module::register(context);
+    let builders = vec![type_store::register(&module_builder_provider, context)];
+    let modules = module_builder_provider.build(builders)?;
+
+    for module in &modules {
+        verify(module)?;
+    }
+
+    Ok(modules)
+}
+
+fn verify(module: &Module<'_>) -> Result<(), CodeGenError> {
+    module.verify().map_err(|error| CodeGenError::Verification {
+        module: module.get_name().to_string_lossy().into_owned(),
+        message: error.to_string(),
+    })
+}
@@ -11,10 +11,25 @@ pub(in crate::codegen) trait ModuleInterface<'ctx, 'codegen, TBuilder> {
     fn expose_to(other: &Module<'ctx>, context: &'ctx Context) -> Self;
 }
 
+/// Describes what a runtime module provides to the others: its functions, and optionally its
+/// globals (given as the `llvm_struct!` they hold), which the other modules get typed pointers
+/// to. The globals are looked up by the name of the module that defines them, same as the one its
+/// `ModuleBuilder` was made with.
 #[macro_export]
+#[doc(hidden)]
 macro_rules! make_module_interface {
     (@builder($builder_name:ty) struct $name:ident {
         $($field_name:ident: $field_type:ty),+
+    }) => {
+        $crate::make_module_interface!(@builder($builder_name) struct $name {
+            $($field_name: $field_type),+
+        } @globals("") {});
+    };
+
+    (@builder($builder_name:ty) struct $name:ident {
+        $($field_name:ident: $field_type:ty),+
+    } @globals($module_name:expr) {
+        $($global_name:ident: $global_type:ident),*
     }) => {
         paste::paste!{
             pub(in $crate::codegen) trait [<$name Builder>]<'ctx, 'codegen> {
@@ -25,38 +40,63 @@ macro_rules! make_module_interface {
                         context: &'ctx inkwell::context::Context
                     ) -> $field_type;
                 )+
+                $(
+                    fn $global_name(&self) -> [<$global_type OpaquePointer>]<'ctx>;
+                )*
             }
-        }
-
-        pub(in $crate::codegen) struct $name<'ctx> {
-            $(pub $field_name: $field_type),+
-        }
 
-        impl<'ctx, 'codegen> $crate::codegen::module::built_module::ModuleInterface<'ctx, 'codegen, $builder_name> for $name<'ctx> {
-            fn register(
-                builder: &$builder_name,
-                module_builder: &mut $crate::codegen::module::ModuleBuilder<'ctx>,
-                context: &'ctx inkwell::context::Context
-        ) -> Self {
-                Self {
-                    $($field_name: builder.$field_name(module_builder, context)),+
-                }
+            // The modules using the interface don't necessarily need all of it
+            pub(in $crate::codegen) struct $name<'ctx> {
+                $(
+                    #[allow(unused)]
+                    pub $field_name: $field_type,
+                )+
+                $(
+                    #[allow(unused)]
+                    pub $global_name: [<$global_type OpaquePointer>]<'ctx>,
+                )*
             }
 
-            fn expose_to(
-                other: &inkwell::module::Module<'ctx>,
-                context: &'ctx inkwell::context::Context
+            impl<'ctx, 'codegen> $crate::codegen::module::built_module::ModuleInterface<'ctx, 'codegen, $builder_name> for $name<'ctx> {
+                fn register(
+                    builder: &$builder_name,
+                    module_builder: &mut $crate::codegen::module::ModuleBuilder<'ctx>,
+                    context: &'ctx inkwell::context::Context
             ) -> Self {
-                Self {
-                    $(
-                        $field_name: <$field_type>::new(
-                            other.add_function(
-                                <$field_type>::NAME,
-                                <$field_type>::llvm_type(context),
-                                None
-                            )
-                        ),
-                    )*
+                    Self {
+                        $($field_name: builder.$field_name(module_builder, context),)+
+                        $($global_name: builder.$global_name(),)*
+                    }
+                }
+
+                fn expose_to(
+                    other: &inkwell::module::Module<'ctx>,
+                    context: &'ctx inkwell::context::Context
+                ) -> Self {
+                    Self {
+                        $(
+                            $field_name: <$field_type>::new(
+                                other.add_function(
+                                    &<$field_type>::symbol(),
+                                    <$field_type>::llvm_type(context),
+                                    None
+                                )
+                            ),
+                        )*
+                        $(
+                            $global_name: {
+                                let provider = [<$global_type Provider>]::new(context);
+                                let global = $crate::codegen::module::add_global_import(
+                                    other,
+                                    provider.llvm_type(),
+                                    $module_name,
+                                    stringify!($global_name),
+                                );
+
+                                provider.opaque_pointer(global.as_pointer_value())
+                            },
+                        )*
+                    }
                 }
             }
         }
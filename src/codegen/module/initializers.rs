@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use crate::codegen::CodeGenError;
+
+/// The global constructors of the runtime modules, by name, along with the names of the ones
+/// they depend on. Collected from all the modules by `ModuleBuilderProvider::build`, so that the
+/// modules can depend on the constructors of each other.
+///
+/// Instead of being picked by hand, the priorities are derived from the dependencies: the ones
+/// without any get 0, and all the others one more than the highest of their dependencies. They're
+/// only asked for once all the constructors are added, so the order they're added in doesn't
+/// matter.
+#[derive(Default)]
+pub(in crate::codegen) struct Initializers {
+    dependencies: HashMap<String, Vec<String>>,
+    // Reported when the priorities are needed, as registering them can't fail
+    duplicates: Vec<String>,
+}
+
+impl Initializers {
+    pub(in crate::codegen) fn add(&mut self, name: &str, dependencies: &[&str]) {
+        let dependencies = dependencies.iter().map(ToString::to_string).collect();
+
+        if self
+            .dependencies
+            .insert(name.to_string(), dependencies)
+            .is_some()
+        {
+            self.duplicates.push(name.to_string());
+        }
+    }
+
+    /// # Errors
+    ///
+    /// If the initializer, or any of its (transitive) dependencies, is not defined, defined more
+    /// than once, or depends on itself.
+    pub(in crate::codegen) fn priority(&self, name: &str) -> Result<u32, CodeGenError> {
+        if let Some(duplicate) = self.duplicates.first() {
+            return Err(CodeGenError::DuplicateInitializer(duplicate.clone()));
+        }
+
+        self.resolve(name, &mut vec![])
+    }
+
+    fn resolve<'names>(
+        &'names self,
+        name: &'names str,
+        path: &mut Vec<&'names str>,
+    ) -> Result<u32, CodeGenError> {
+        if let Some(start) = path.iter().position(|visited| *visited == name) {
+            let mut cycle: Vec<_> = path[start..].iter().map(ToString::to_string).collect();
+            cycle.push(name.to_string());
+
+            return Err(CodeGenError::InitializerCycle(cycle));
+        }
+
+        let dependencies = self
+            .dependencies
+            .get(name)
+            .ok_or_else(|| CodeGenError::UndefinedInitializer(name.to_string()))?;
+
+        path.push(name);
+        let mut priority = 0;
+        for dependency in dependencies {
+            priority = priority.max(self.resolve(dependency, path)? + 1);
+        }
+        path.pop();
+
+        Ok(priority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Initializers;
+    use crate::codegen::CodeGenError;
+
+    #[test]
+    fn constructors_come_after_their_dependencies() {
+        let mut initializers = Initializers::default();
+        // Added before the ones they depend on, as if their module was built first
+        initializers.add("functions", &["types", "strings"]);
+        initializers.add("types", &["strings"]);
+        initializers.add("strings", &[]);
+        initializers.add("unrelated", &[]);
+
+        let priority = |name| initializers.priority(name).unwrap();
+        assert_eq!(priority("strings"), 0);
+        assert_eq!(priority("unrelated"), 0);
+        assert_eq!(priority("types"), 1);
+        assert_eq!(priority("functions"), 2);
+    }
+
+    #[test]
+    fn undefined_dependencies_are_reported() {
+        let mut initializers = Initializers::default();
+        initializers.add("types", &["strings"]);
+
+        assert!(matches!(
+            initializers.priority("types"),
+            Err(CodeGenError::UndefinedInitializer(name)) if name == "strings"
+        ));
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let mut initializers = Initializers::default();
+        initializers.add("first", &["second"]);
+        initializers.add("second", &["third"]);
+        initializers.add("third", &["second"]);
+
+        assert!(matches!(
+            initializers.priority("first"),
+            Err(CodeGenError::InitializerCycle(cycle)) if cycle == ["second", "third", "second"]
+        ));
+    }
+
+    #[test]
+    fn duplicates_are_reported() {
+        let mut initializers = Initializers::default();
+        initializers.add("types", &[]);
+        initializers.add("types", &[]);
+
+        assert!(matches!(
+            initializers.priority("types"),
+            Err(CodeGenError::DuplicateInitializer(name)) if name == "types"
+        ));
+    }
+}
@@ -1,19 +1,21 @@
 // TODO review if all the levels of abstractions here still make sense and are needed
 use crate::codegen::llvm_struct::representations::OperandValue;
 pub(in crate::codegen) mod built_module;
+mod initializers;
 
+use initializers::Initializers;
 use inkwell::{
     AddressSpace,
     context::Context,
     module::{Linkage, Module},
-    types::{BasicType, StructType},
-    values::{FunctionValue, GlobalValue, PointerValue},
+    types::BasicType,
+    values::{BasicValue, FunctionValue, GlobalValue, PointerValue},
 };
 
 use super::{
-    builtins::DebugTypeDefinition,
+    CodeGenError, builtins,
     context::{Function, Procedure},
-    llvm_struct::representations::ConstOrValue,
+    debug_info, mangling,
 };
 use crate::codegen::{
     context_ergonomics::ContextErgonomics,
@@ -39,6 +41,28 @@ pub fn register(context: &Context) -> ModuleBuilderProvider<'_> {
     }
 }
 
+/// The module `ModuleBuilderProvider::build` adds to run the constructors of the others.
+const INITIALIZERS_MODULE: &str = "initializers";
+
+/// Declares the global defined by the runtime module `module` in the `other` module (unless it's
+/// already there), to be resolved when the modules get linked, or by the execution engine.
+pub(in crate::codegen) fn add_global_import<'ctx>(
+    other: &Module<'ctx>,
+    r#type: impl BasicType<'ctx>,
+    module: &str,
+    name: &str,
+) -> GlobalValue<'ctx> {
+    let symbol = global_symbol(module, name);
+
+    other
+        .get_global(&symbol)
+        .unwrap_or_else(|| other.add_global(r#type, None, &symbol))
+}
+
+fn global_symbol(module: &str, name: &str) -> String {
+    mangling::mangle_global(module, name)
+}
+
 pub(in crate::codegen) struct ModuleBuilderProvider<'ctx> {
     global_constructors_provider: GlobalConstructorProvider<'ctx>,
     context: &'ctx Context,
@@ -46,61 +70,189 @@ pub(in crate::codegen) struct ModuleBuilderProvider<'ctx> {
 
 impl<'ctx> ModuleBuilderProvider<'ctx> {
     pub fn make_builder(&self, name: &str) -> ModuleBuilder<'ctx> {
-        ModuleBuilder::new(
-            name,
-            self.context,
-            self.global_constructors_provider.llvm_type(),
-        )
+        ModuleBuilder::new(name, self.context)
+    }
+
+    /// Builds the modules, followed by one more that runs all of their constructors, and their
+    /// destructors, from a single function each. The execution engine runs the constructors of
+    /// each module on its own, in the order the modules are added, so this way their order only
+    /// depends on the dependencies between them. Those are resolved once all the modules have
+    /// registered theirs, so the modules can depend on each other in any order.
+    ///
+    /// # Errors
+    ///
+    /// If the constructors depend on ones that are not defined, defined more than once, or on
+    /// themselves.
+    pub fn build(
+        self,
+        builders: Vec<ModuleBuilder<'ctx>>,
+    ) -> Result<Vec<Module<'ctx>>, CodeGenError> {
+        let mut initializers = Initializers::default();
+        let mut constructors = vec![];
+        let mut destructors = vec![];
+        let mut modules = vec![];
+
+        for builder in builders {
+            let (module, module_constructors, module_destructors) = builder.build();
+
+            for constructor in &module_constructors {
+                let dependencies: Vec<_> = constructor
+                    .dependencies
+                    .iter()
+                    .map(String::as_str)
+                    .collect();
+                initializers.add(&constructor.name, &dependencies);
+            }
+            constructors.extend(module_constructors);
+            destructors.extend(module_destructors);
+            modules.push(module);
+        }
+
+        let mut constructors = prioritize(&initializers, constructors)?;
+        let mut destructors = prioritize(&initializers, destructors)?;
+        // The destructors run the other way around
+        constructors.sort_by_key(|(priority, _)| *priority);
+        destructors.sort_by_key(|(priority, _)| std::cmp::Reverse(*priority));
+
+        let module = self.context.create_module(INITIALIZERS_MODULE);
+        self.build_global_constructor(&module, "llvm.global_ctors", "constructors", &constructors);
+        self.build_global_constructor(&module, "llvm.global_dtors", "destructors", &destructors);
+        modules.push(module);
+
+        Ok(modules)
+    }
+
+    /// Emits a function calling the constructors (or the destructors) in the given order, and
+    /// the special global that LLVM picks it up from.
+    fn build_global_constructor(
+        &self,
+        module: &Module<'ctx>,
+        global_name: &str,
+        name: &str,
+        targets: &[(u32, String)],
+    ) {
+        let context = self.context;
+        let function_type = GlobalConstructorFunction::llvm_type(context);
+        let function = module.add_function(
+            &global_symbol(INITIALIZERS_MODULE, name),
+            function_type,
+            None,
+        );
+        let builder = context.create_builder();
+        builder.position_at_end(context.append_basic_block(function, "entry"));
+        for (_, symbol) in targets {
+            let target = module.add_function(symbol, function_type, None);
+            builder.build_call(target, &[], "").unwrap();
+        }
+        builder.build_return(None).unwrap();
+
+        let global_constructor_type = self.global_constructors_provider.llvm_type();
+        let global = module.add_global(global_constructor_type.array_type(1), None, global_name);
+        global.set_linkage(Linkage::Appending);
+
+        // Always run, so there's no value the constructor is tied to
+        global.set_initializer(
+            &global_constructor_type.const_array(&[global_constructor_type.const_named_struct(&[
+                context.const_u32(0).into(),
+                function.as_global_value().as_pointer_value().into(),
+                context
+                    .ptr_type(AddressSpace::default())
+                    .const_null()
+                    .into(),
+            ])]),
+        );
     }
 }
 
 pub(in crate::codegen) struct ModuleBuilder<'ctx> {
     module: Module<'ctx>,
-    global_constructors: Vec<GlobalConstructorOpaque<'ctx>>,
-    global_constructor_type: StructType<'ctx>,
+    global_constructors: Vec<NamedConstructor>,
+    // Each of them undoes one of the constructors
+    global_destructors: Vec<NamedConstructor>,
 
     context: &'ctx Context,
 }
 
 impl<'ctx> ModuleBuilder<'ctx> {
-    fn new(name: &str, context: &'ctx Context, global_constructor_type: StructType<'ctx>) -> Self {
+    fn new(name: &str, context: &'ctx Context) -> Self {
         let module = context.create_module(name);
         // TODO this is a hack, it will link to the function defined in the main module, but we
         // should really invest into some real debug infrastructure
-        module.add_function(
-            "debug_type_definition",
-            DebugTypeDefinition::llvm_type(context),
-            None,
-        );
+        builtins::declare(&module, context);
         Self {
             module,
             global_constructors: vec![],
-            global_constructor_type,
+            global_destructors: vec![],
             context,
         }
     }
 
+    /// Makes the constructor run when the modules get loaded, before any of their code runs, but
+    /// after all the constructors named in the `dependencies` (which can be defined by any of the
+    /// modules built by the same `ModuleBuilderProvider`).
     pub fn add_global_constructor(
         &mut self,
-        priority: u32,
-        constructor: &GlobalConstructorFunction<'ctx>,
-        initialized_value: Option<GlobalValue<'ctx>>,
+        name: &str,
+        dependencies: &[&str],
+        constructor: GlobalConstructorFunction<'ctx>,
     ) {
-        // TODO the manual creation of ConstOrValue isn't very pretty, let's look into some
-        // automatic coercion, maybe implement into not generially, but for each type, since we
-        // have the macros for LlvmRepresentation anyway?
-        self.global_constructors.push(GlobalConstructorOpaque {
-            priority: ConstOrValue::Const(priority),
-            target: ConstOrValue::Value(constructor.as_global_value().as_pointer_value()),
-            initialized_value: ConstOrValue::Value(initialized_value.map_or_else(
-                || self.context.ptr_type(AddressSpace::default()).const_null(),
-                GlobalValue::as_pointer_value,
-            )),
+        let constructor = self.make_named_constructor(name, constructor, "constructor");
+        self.global_constructors.push(NamedConstructor {
+            dependencies: dependencies.iter().map(ToString::to_string).collect(),
+            ..constructor
         });
     }
 
-    pub(crate) fn add_global(&self, r#type: impl BasicType<'ctx>, name: &str) -> GlobalValue<'ctx> {
-        self.module.add_global(r#type, None, name)
+    /// Makes the destructor run when the modules get unloaded, after all of their code has run.
+    /// It undoes the constructor with the `name`, so it runs before the destructors of its
+    /// dependencies.
+    pub fn add_global_destructor(
+        &mut self,
+        name: &str,
+        destructor: GlobalConstructorFunction<'ctx>,
+    ) {
+        let destructor = self.make_named_constructor(name, destructor, "destructor");
+        self.global_destructors.push(destructor);
+    }
+
+    /// Names the function after the constructor, as it's called from the module of the
+    /// initializers, where it must not collide with the ones of the other modules.
+    fn make_named_constructor(
+        &self,
+        name: &str,
+        function: GlobalConstructorFunction<'ctx>,
+        kind: &str,
+    ) -> NamedConstructor {
+        let symbol = format!(
+            "{}.{kind}",
+            global_symbol(&self.module.get_name().to_string_lossy(), name)
+        );
+        function.as_global_value().set_name(&symbol);
+
+        NamedConstructor {
+            name: name.to_string(),
+            dependencies: vec![],
+            symbol,
+        }
+    }
+
+    /// Adds a global, mangled with the name of the module as its path, so that it can't collide
+    /// with the globals of the other modules. Without an initializer it's zeroed.
+    pub(crate) fn add_global(
+        &self,
+        r#type: impl BasicType<'ctx> + Copy,
+        name: &str,
+        initializer: Option<&dyn BasicValue<'ctx>>,
+    ) -> GlobalValue<'ctx> {
+        let symbol = global_symbol(&self.module.get_name().to_string_lossy(), name);
+        let global = self.module.add_global(r#type, None, &symbol);
+
+        match initializer {
+            Some(initializer) => global.set_initializer(initializer),
+            None => global.set_initializer(&r#type.as_basic_type_enum().const_zero()),
+        }
+
+        global
     }
 
     pub(in crate::codegen) fn build_procedure<
@@ -111,7 +263,9 @@ impl<'ctx> ModuleBuilder<'ctx> {
         build: impl Fn(FunctionValue<'ctx>, &'ctx Context, &Module<'ctx>),
     ) -> TProcedure {
         let signature = TProcedure::llvm_type(self.context);
-        let function = self.module.add_function(TProcedure::NAME, signature, None);
+        let function = self
+            .module
+            .add_function(&TProcedure::symbol(), signature, None);
 
         build(function, self.context, &self.module);
 
@@ -127,53 +281,47 @@ impl<'ctx> ModuleBuilder<'ctx> {
         build: impl Fn(FunctionValue<'ctx>, &'ctx Context, &Module<'ctx>),
     ) -> TFunction {
         let signature = TFunction::llvm_type(self.context);
-        let function = self.module.add_function(TFunction::NAME, signature, None);
+        let function = self
+            .module
+            .add_function(&TFunction::symbol(), signature, None);
 
         build(function, self.context, &self.module);
 
         TFunction::new(function)
     }
 
-    pub fn build(self) -> Module<'ctx> {
-        let Self {
-            module,
-            global_constructors,
-            global_constructor_type,
-            context: _,
-        } = self;
-
-        let global_constructors_array_type =
-            global_constructor_type.array_type(u32::try_from(global_constructors.len()).unwrap());
-
-        let global_constructors_value =
-            module.add_global(global_constructors_array_type, None, "llvm.global_ctors");
-
-        global_constructors_value.set_linkage(Linkage::Appending);
-        let constructors: Vec<_> = global_constructors
-            .iter()
-            .map(|x| {
-                // TODO this is very hacky, perhaps create $name Const for structs which are always
-                // made from consts?
-                let ConstOrValue::Const(priority) = x.priority else {
-                    todo!();
-                };
-                let ConstOrValue::Value(target) = x.target else {
-                    todo!();
-                };
-                let ConstOrValue::Value(initialized_value) = x.initialized_value else {
-                    todo!();
-                };
-
-                global_constructor_type.const_named_struct(&[
-                    self.context.const_u32(priority).into(),
-                    target.into(),
-                    initialized_value.into(),
-                ])
-            })
-            .collect();
-        global_constructors_value
-            .set_initializer(&global_constructor_type.const_array(&constructors));
-
-        module
+    /// Returns the module along with its constructors and destructors, which are called from the
+    /// module of the initializers, see `ModuleBuilderProvider::build`.
+    fn build(self) -> (Module<'ctx>, Vec<NamedConstructor>, Vec<NamedConstructor>) {
+        debug_info::describe_runtime_module(&self.module, self.context);
+
+        (
+            self.module,
+            self.global_constructors,
+            self.global_destructors,
+        )
     }
 }
+
+/// A global constructor (or destructor) whose priority is not known until all of them are.
+struct NamedConstructor {
+    name: String,
+    dependencies: Vec<String>,
+    // Of the function
+    symbol: String,
+}
+
+fn prioritize(
+    initializers: &Initializers,
+    named: Vec<NamedConstructor>,
+) -> Result<Vec<(u32, String)>, CodeGenError> {
+    named
+        .into_iter()
+        .map(|constructor| {
+            Ok((
+                initializers.priority(&constructor.name)?,
+                constructor.symbol,
+            ))
+        })
+        .collect()
+}
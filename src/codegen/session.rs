@@ -0,0 +1,593 @@
+use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc};
+
+use inkwell::{
+    OptimizationLevel,
+    context::Context,
+    execution_engine::{ExecutionEngine, JitFunction, UnsafeFunctionPointer},
+    module::Module,
+};
+
+use super::{
+    CodeGen, CodeGenError, build_runtime_modules, builtins,
+    cache::{CacheKey, ModuleCache},
+    calls::{self, BytecodeArguments},
+    error::CallError,
+    function_symbol,
+    host_functions::{self, HostFunctions, HostValue, IntoHostFunction},
+    lazy::{self, LazyFunctions},
+    module_name,
+    type_store::{dump::TypeStoreDump, handle::TypeStoreHandle, host::StoredType},
+    types::values::Value,
+};
+use crate::bytecode::{
+    ByteCode, Expression, FunctionDefinition, Identifier, Import, TypeId, ValidationError,
+    validate_modules,
+};
+
+type MainEntry = unsafe extern "C" fn() -> u64;
+type UnitEntry = unsafe extern "C" fn(*mut *const Value) -> *const Value;
+
+// The generated code is optimized by the execution engine as it gets compiled to machine code, and
+// by the module cache before it gets stored
+const OPTIMIZATION_LEVEL: OptimizationLevel = OptimizationLevel::Aggressive;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompilationMode {
+    /// All the functions of a unit are compiled before it starts running
+    #[default]
+    Eager,
+    /// Functions are only compiled when they're called for the first time, see `LazyFunctions`
+    Lazy,
+}
+
+/// A long-lived compilation and execution environment.
+///
+/// The builtins, runtime modules (like the type store) and the execution engine get initialized
+/// once, and then any number of bytecode units can be executed on top of them, all sharing the
+/// same runtime state.
+///
+/// The global constructors of the runtime run when the session is created, and its destructors
+/// when it's dropped, so the values the units return must not be used after that.
+pub struct Session<'ctx> {
+    codegen: CodeGen<'ctx>,
+    compilation_mode: CompilationMode,
+    execution_engine: ExecutionEngine<'ctx>,
+    // The values live in memory allocated by the generated code, which is never freed, so the
+    // pointers stay valid for as long as the execution engine does
+    bindings: HashMap<Identifier, *const Value>,
+    unit_count: usize,
+    // The stubs of lazily compiled functions hold pointers to these
+    lazy_functions: Vec<Rc<RefCell<LazyFunctions<'ctx>>>>,
+    // The wrappers of the host functions hold a pointer to this, so it has to stay put
+    host_functions: Rc<RefCell<HostFunctions>>,
+    listing_directory: Option<PathBuf>,
+    module_cache: Option<ModuleCache>,
+    // The bytecode functions of all the units, a function defined by a later unit shadows any
+    // earlier one with the same name
+    functions: HashMap<String, BytecodeFunction>,
+}
+
+struct BytecodeFunction {
+    symbol: String,
+    /// The id of its signature in the type store
+    type_id: u32,
+}
+
+impl<'ctx> Session<'ctx> {
+    /// # Errors
+    ///
+    /// If the runtime modules fail the verification, or the execution engine can't be created.
+    pub fn new(context: &'ctx Context) -> Result<Self, CodeGenError> {
+        let runtime_modules = build_runtime_modules(context)?;
+        // There's always the one running the constructors
+        let [runtime_module, other_runtime_modules @ ..] = runtime_modules.as_slice() else {
+            unreachable!();
+        };
+
+        let execution_engine = runtime_module
+            .create_jit_execution_engine(OPTIMIZATION_LEVEL)
+            .map_err(|error| CodeGenError::ExecutionEngine(error.to_string()))?;
+        for module in other_runtime_modules {
+            execution_engine.add_module(module).map_err(|()| {
+                CodeGenError::ExecutionEngine(format!(
+                    "module `{}` is already in use",
+                    module.get_name().to_string_lossy()
+                ))
+            })?;
+        }
+
+        builtins::register(&execution_engine, runtime_module, context);
+        lazy::register(&execution_engine, runtime_module, context);
+        host_functions::register(&execution_engine, runtime_module, context);
+        execution_engine.run_static_constructors();
+
+        Ok(Self {
+            codegen: CodeGen::new(context),
+            compilation_mode: CompilationMode::default(),
+            execution_engine,
+            bindings: HashMap::new(),
+            unit_count: 0,
+            lazy_functions: vec![],
+            host_functions: Rc::default(),
+            listing_directory: None,
+            module_cache: None,
+            functions: HashMap::new(),
+        })
+    }
+
+    #[must_use]
+    pub const fn with_compilation_mode(mut self, compilation_mode: CompilationMode) -> Self {
+        self.compilation_mode = compilation_mode;
+        self
+    }
+
+    /// Makes the session write the listing of every unit into the directory (as `{unit}.lil`),
+    /// so that debuggers can show the source the debug info of the generated code refers to.
+    #[must_use]
+    pub fn with_listing_directory(mut self, directory: PathBuf) -> Self {
+        self.codegen.source_directory.clone_from(&directory);
+        self.listing_directory = Some(directory);
+        self
+    }
+
+    /// Makes the session keep the compiled units in the directory, and load them from there
+    /// instead of compiling them again, as long as neither the units nor the compiler change.
+    ///
+    /// Only the units run with `execute` in the eager mode are cached, and only until any host
+    /// functions get registered, as the code generated for the rest depends on the state of the
+    /// session.
+    ///
+    /// # Errors
+    ///
+    /// If there's no target machine to optimize the cached code for.
+    pub fn with_module_cache(mut self, directory: PathBuf) -> Result<Self, CodeGenError> {
+        self.module_cache = Some(ModuleCache::new(directory, OPTIMIZATION_LEVEL)?);
+        Ok(self)
+    }
+
+    /// Makes the Rust function callable from the bytecode of the units executed afterwards, under
+    /// the `name`. Its signature is derived from the types it takes and returns, and gets interned
+    /// in the type store as a `StoredType::FunctionSignature`, whose id is returned. The values
+    /// passed to the function are checked against it when it gets called.
+    ///
+    /// # Errors
+    ///
+    /// If there already is a host function with the same name, or the type store can't be
+    /// reached.
+    pub fn register_function<Arguments>(
+        &self,
+        name: &str,
+        function: impl IntoHostFunction<Arguments>,
+    ) -> Result<TypeId, CodeGenError> {
+        let function = function.into_host_function();
+        let signature = self.intern_type(function.signature())?;
+
+        if self.host_functions.borrow_mut().add(name, function) {
+            Ok(signature)
+        } else {
+            Err(ValidationError::DuplicateFunction(name.to_string()).into())
+        }
+    }
+
+    /// Runs the bytecode as a standalone program and returns its result.
+    ///
+    /// # Errors
+    ///
+    /// If the bytecode fails to compile, or runs into an error, like a result of the wrong type.
+    pub fn execute(&mut self, bytecode: ByteCode) -> Result<u64, CodeGenError> {
+        let name = self.next_unit_name("main");
+        self.write_listing(&name, &bytecode)?;
+        let exports = exports(&name, &bytecode);
+
+        let key = self.cache_key(&name, &bytecode);
+        let cached = key
+            .as_ref()
+            .and_then(|key| self.load_cached(key, &name, &bytecode));
+        let modules = if let Some(modules) = cached {
+            modules
+        } else {
+            let (modules, instructions) = self.create_modules(&name, bytecode)?;
+            self.codegen.build_main(&modules.unit, instructions)?;
+
+            if let (Some(cache), Some(key)) = (&self.module_cache, &key) {
+                cache.store(key, &modules.iter().collect::<Vec<_>>())?;
+            }
+
+            modules
+        };
+
+        self.add_modules(&modules)?;
+        self.export_functions(exports)?;
+        let main = self.get_function::<MainEntry>(&name)?;
+        let result = unsafe { main.call() };
+
+        builtins::take_runtime_error().map_or(Ok(result), |error| Err(error.into()))
+    }
+
+    /// Runs the bytecode on top of the previously evaluated units, so that it can use the locals
+    /// they assigned, and returns the debug representation of its result, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// If the bytecode fails to compile, or runs into an error, like a result of the wrong type.
+    pub fn evaluate(&mut self, bytecode: ByteCode) -> Result<Option<String>, CodeGenError> {
+        let name = self.next_unit_name("unit");
+        self.write_listing(&name, &bytecode)?;
+        let exports = exports(&name, &bytecode);
+        let (modules, instructions) = self.create_modules(&name, bytecode)?;
+        let identifiers = self
+            .codegen
+            .build_unit(&modules.unit, instructions, &self.bindings)?;
+
+        self.add_modules(&modules)?;
+        self.export_functions(exports)?;
+        let entry = self.get_function::<UnitEntry>(&name)?;
+
+        let mut slots = vec![std::ptr::null(); identifiers.len()];
+        let result = unsafe { entry.call(slots.as_mut_ptr()) };
+        // The locals of a unit that failed might not have been assigned, so they aren't kept
+        if let Some(error) = builtins::take_runtime_error() {
+            return Err(error.into());
+        }
+
+        self.bindings.extend(identifiers.into_iter().zip(slots));
+
+        Ok((!result.is_null()).then(|| format!("{:?}", unsafe { &*result })))
+    }
+
+    /// Calls a bytecode function defined by one of the executed units. The arguments and the
+    /// result type are checked against the signature of the function from the type store, and
+    /// the result is checked once more when the function returns.
+    ///
+    /// # Errors
+    ///
+    /// If there's no such function, the arguments or the result type don't match its signature,
+    /// or it runs into an error.
+    pub fn call<TArguments: BytecodeArguments, TResult: HostValue>(
+        &self,
+        name: &str,
+        arguments: TArguments,
+    ) -> Result<TResult, CodeGenError> {
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| CallError::UndefinedFunction(name.to_string()))?;
+
+        let type_store = self.type_store()?;
+        let signature = type_store.get_raw_type(TypeId::from_raw(function.type_id));
+        let types = TArguments::types();
+        // The types the host can't read are as good as unknown to the check
+        let lookup = |id| type_store.get_type(id).ok().flatten();
+        calls::check_signature(name, signature, &types, TResult::TYPE, &lookup)?;
+
+        let address = self
+            .execution_engine
+            .get_function_address(&function.symbol)
+            .map_err(|error| CodeGenError::ExecutionEngine(error.to_string()))?;
+        let result = unsafe { calls::call(address, &types, arguments.into_raw()) };
+
+        builtins::take_runtime_error()
+            .map_or_else(|| result.map_err(Into::into), |error| Err(error.into()))
+    }
+
+    /// The type store of the session, for the threads running alongside it. The methods of the
+    /// session that work with the types go through it as well.
+    ///
+    /// # Errors
+    ///
+    /// If the type store can't be reached.
+    pub fn type_store(&self) -> Result<TypeStoreHandle<'_>, CodeGenError> {
+        TypeStoreHandle::new(&self.execution_engine)
+    }
+
+    /// Registers the type in the type store of the session, for the generated code (and the
+    /// host) to look up by its id.
+    ///
+    /// # Errors
+    ///
+    /// If the id is one of the `TypeTag`s, or there already is a type with the id, which is kept
+    /// as it is.
+    pub fn add_type(&self, id: TypeId, r#type: StoredType) -> Result<(), CodeGenError> {
+        self.type_store()?.add_type(id, r#type)
+    }
+
+    /// Looks the type up in the type store of the session.
+    ///
+    /// # Errors
+    ///
+    /// If the type store can't be reached, or the type has a tag the host doesn't know about.
+    pub fn get_type(&self, id: TypeId) -> Result<Option<StoredType>, CodeGenError> {
+        self.type_store()?.get_type(id)
+    }
+
+    /// Hands out an id no type in the type store of the session has, nor will get from it later,
+    /// for registering a type with `add_type`.
+    ///
+    /// # Errors
+    ///
+    /// If the type store can't be reached.
+    pub fn allocate_type_id(&self) -> Result<TypeId, CodeGenError> {
+        Ok(self.type_store()?.allocate_type_id())
+    }
+
+    /// Returns the id of the type from the type store of the session, adding it under a fresh id
+    /// if there's no equal one yet. The types are compared structurally, so interning the same
+    /// signature twice gives the same id. That only holds for the types that are interned, as
+    /// `add_type` and `update_type` store the type as it is, even if there's an equal one.
+    ///
+    /// # Errors
+    ///
+    /// If the type store can't be reached.
+    pub fn intern_type(&self, r#type: StoredType) -> Result<TypeId, CodeGenError> {
+        Ok(self.type_store()?.intern_type(r#type))
+    }
+
+    /// Whether a value of the type `from` can be used where the type `to` is expected, according
+    /// to the types in the type store of the session, see `StoredType::is_assignable` for the
+    /// rules. Unknown types aren't assignable to anything but themselves.
+    ///
+    /// # Errors
+    ///
+    /// If the type store can't be reached.
+    pub fn is_assignable(&self, from: TypeId, to: TypeId) -> Result<bool, CodeGenError> {
+        Ok(self.type_store()?.is_assignable(from, to))
+    }
+
+    /// Replaces the type with the id in the type store of the session. The type isn't interned,
+    /// so an equal type can end up stored under two ids, see `intern_type`.
+    ///
+    /// # Errors
+    ///
+    /// If the id is one of the `TypeTag`s, or there's no type with the id, in which case it's not
+    /// added either.
+    pub fn update_type(&self, id: TypeId, r#type: StoredType) -> Result<(), CodeGenError> {
+        self.type_store()?.update_type(id, r#type)
+    }
+
+    /// Removes the type with the id from the type store of the session.
+    ///
+    /// # Errors
+    ///
+    /// If the id is one of the `TypeTag`s, or there's no type with the id.
+    pub fn remove_type(&self, id: TypeId) -> Result<(), CodeGenError> {
+        self.type_store()?.remove_type(id)
+    }
+
+    /// Reads all the types from the type store of the session, ordered by their ids. That
+    /// includes the ones the executed units registered, like the signatures of their functions.
+    ///
+    /// # Errors
+    ///
+    /// If the type store can't be reached.
+    pub fn types(&self) -> Result<Vec<(TypeId, StoredType)>, CodeGenError> {
+        Ok(self.type_store()?.types())
+    }
+
+    /// The types in the type store of the session with their names resolved, to print as a
+    /// table or as JSON when debugging.
+    ///
+    /// # Errors
+    ///
+    /// If the type store can't be reached.
+    pub fn dump_types(&self) -> Result<TypeStoreDump, CodeGenError> {
+        Ok(self.type_store()?.dump())
+    }
+
+    /// Registers the signatures of the exported functions of the unit in the type store, making
+    /// them callable with `call`.
+    fn export_functions(&mut self, exports: Vec<Export>) -> Result<(), CodeGenError> {
+        for Export {
+            name,
+            symbol,
+            arguments,
+        } in exports
+        {
+            // The functions with the same arguments share their signature
+            let type_id = self
+                .type_store()?
+                .intern_raw_type(calls::bytecode_signature(&arguments))
+                .as_u32();
+
+            self.functions
+                .insert(name, BytecodeFunction { symbol, type_id });
+        }
+
+        Ok(())
+    }
+
+    fn next_unit_name(&mut self, prefix: &str) -> String {
+        let name = format!("{prefix}_{}", self.unit_count);
+        self.unit_count += 1;
+
+        name
+    }
+
+    fn write_listing(&self, name: &str, bytecode: &ByteCode) -> Result<(), CodeGenError> {
+        let Some(directory) = &self.listing_directory else {
+            return Ok(());
+        };
+
+        let path = directory.join(format!("{name}.lil"));
+        std::fs::create_dir_all(directory)
+            .and_then(|()| std::fs::write(&path, bytecode.to_string()))
+            .map_err(|error| CodeGenError::Listing { path, error })
+    }
+
+    /// Compiles the modules of the unit, then creates the module of the unit itself, with the
+    /// functions defined outside of the modules. The instructions of the unit are returned to be
+    /// built into it.
+    fn create_modules(
+        &mut self,
+        unit: &str,
+        bytecode: ByteCode,
+    ) -> Result<(UnitModules<'ctx>, Vec<Expression>), CodeGenError> {
+        validate_modules(&bytecode)?;
+        let (module_lines, line) = bytecode.function_lines();
+
+        let mut modules = vec![];
+        for (definition, module_line) in bytecode.modules.into_iter().zip(module_lines) {
+            let module = self.create_module(
+                (&module_name(unit, &definition.name), unit),
+                module_line,
+                &definition.imports,
+                definition.functions,
+            )?;
+            self.codegen.verify(&module)?;
+
+            modules.push(module);
+        }
+
+        let module =
+            self.create_module((unit, unit), line, &bytecode.imports, bytecode.functions)?;
+
+        Ok((
+            UnitModules {
+                modules,
+                unit: module,
+            },
+            bytecode.instructions,
+        ))
+    }
+
+    /// The key of the unit in the module cache, if it's cached at all.
+    fn cache_key(&self, unit: &str, bytecode: &ByteCode) -> Option<CacheKey> {
+        let cache = self.module_cache.as_ref()?;
+
+        // The code generated for the lazily compiled functions and for the calls to the host
+        // functions points into the memory of the session, so it can't be reused by another one
+        (self.compilation_mode == CompilationMode::Eager && self.host_functions.borrow().is_empty())
+            .then(|| cache.key(unit, bytecode, &self.codegen.source_directory))
+    }
+
+    fn load_cached(
+        &self,
+        key: &CacheKey,
+        unit: &str,
+        bytecode: &ByteCode,
+    ) -> Option<UnitModules<'ctx>> {
+        let names: Vec<_> = bytecode
+            .modules
+            .iter()
+            .map(|module| module_name(unit, &module.name))
+            .chain([unit.to_string()])
+            .collect();
+
+        let mut modules = self
+            .module_cache
+            .as_ref()?
+            .load(key, &names, self.codegen.context)?;
+        let unit = modules.pop()?;
+
+        Some(UnitModules { modules, unit })
+    }
+
+    /// Creates a module of the unit, with the `functions` starting at the `line` of its listing.
+    fn create_module(
+        &mut self,
+        (name, unit): (&str, &str),
+        line: u32,
+        imports: &[Import],
+        functions: Vec<FunctionDefinition>,
+    ) -> Result<Module<'ctx>, CodeGenError> {
+        let module = self.codegen.create_module(name, unit);
+        self.codegen.skip_to_line(line);
+        HostFunctions::declare(&self.host_functions, &mut self.codegen, &module);
+        self.codegen.declare_imports(&module, unit, imports)?;
+
+        match self.compilation_mode {
+            CompilationMode::Eager => self.codegen.define_functions(&module, functions)?,
+            CompilationMode::Lazy => self.lazy_functions.push(LazyFunctions::declare(
+                &mut self.codegen,
+                &module,
+                (unit, imports),
+                functions,
+                &self.execution_engine,
+                &self.host_functions,
+            )?),
+        }
+
+        Ok(module)
+    }
+
+    fn add_modules(&self, modules: &UnitModules<'ctx>) -> Result<(), CodeGenError> {
+        for module in modules.iter() {
+            self.execution_engine.add_module(module).map_err(|()| {
+                CodeGenError::ExecutionEngine(format!(
+                    "module `{}` is already in use",
+                    module.get_name().to_string_lossy()
+                ))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn get_function<F: UnsafeFunctionPointer>(
+        &self,
+        name: &str,
+    ) -> Result<JitFunction<'ctx, F>, CodeGenError> {
+        unsafe { self.execution_engine.get_function::<F>(name) }
+            .map_err(|error| CodeGenError::ExecutionEngine(error.to_string()))
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        // The runtime state is shared by all the units, so it's only torn down once none of them
+        // can run anymore. The execution engine doesn't do this on its own
+        self.execution_engine.run_static_destructors();
+    }
+}
+
+/// The modules an unit gets compiled into.
+struct UnitModules<'ctx> {
+    /// One for each of the bytecode modules
+    modules: Vec<Module<'ctx>>,
+    /// The functions and the instructions outside of the bytecode modules
+    unit: Module<'ctx>,
+}
+
+impl<'ctx> UnitModules<'ctx> {
+    fn iter(&self) -> impl Iterator<Item = &Module<'ctx>> {
+        self.modules.iter().chain([&self.unit])
+    }
+}
+
+struct Export {
+    /// The name the function can be called by from the host, qualified with the name of its
+    /// module if it's in one
+    name: String,
+    symbol: String,
+    arguments: Vec<Identifier>,
+}
+
+/// All the functions defined outside of the modules of the unit get exported, along with the
+/// functions the modules export.
+fn exports(unit: &str, bytecode: &ByteCode) -> Vec<Export> {
+    let modules = bytecode.modules.iter().flat_map(|module| {
+        module
+            .functions
+            .iter()
+            .filter(|function| module.exports.contains(&function.name))
+            .map(|function| Export {
+                name: format!("{}.{}", module.name, function.name),
+                symbol: function_symbol(
+                    &module_name(unit, &module.name),
+                    &function.name,
+                    function.arguments.len(),
+                ),
+                arguments: function.arguments.clone(),
+            })
+    });
+
+    bytecode
+        .functions
+        .iter()
+        .map(|function| Export {
+            name: function.name.clone(),
+            symbol: function_symbol(unit, &function.name, function.arguments.len()),
+            arguments: function.arguments.clone(),
+        })
+        .chain(modules)
+        .collect()
+}
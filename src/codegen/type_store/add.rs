@@ -1,119 +1,239 @@
-use inkwell::{IntPredicate, context::Context};
+use inkwell::{
+    AddressSpace, IntPredicate, builder::Builder, context::Context, values::FunctionValue,
+};
 
-use super::{TypeStoreOpaquePointer, TypeValueOpaque, TypeValueProvider};
+use super::{
+    SLOT_EMPTY, SLOT_OCCUPIED, TypeStoreOpaquePointer, TypeValueOpaque, TypeValueProvider,
+    index::TypeStoreIndexType,
+    initializer::build_slots_malloc,
+    lock::{build_lock, build_unlock},
+    probe::TypeStoreProbe,
+};
 use crate::{
     bytecode::Value,
     codegen::{
-        ContextErgonomics, llvm_struct::representations::ConstOrValue, module,
-        types::values::ValueProvider,
+        ContextErgonomics,
+        context::{Function as _, Procedure as _},
+        llvm_struct::representations::ConstOrValue,
+        module,
     },
 };
 
-make_function_type!(TypeStoreAdd, (id:u32, value: *const Value));
+make_function_type!(TypeStoreAdd, (id:u32, value: *const Value): *const Value);
+make_function_type!(TypeStoreInsert, (id:u32, value: *const Value): *const Value);
 
+/// Stores the type under the id, unless there already is one. The ids are supposed to be unique,
+/// so the existing type is kept, and returned to let the caller know. Otherwise it's null. The
+/// store keeps pointing at the value, so it has to stay around for as long as the store does.
 pub(super) fn make_add<'ctx>(
     module_builder: &module::ModuleBuilder<'ctx>,
     type_store: TypeStoreOpaquePointer<'ctx>,
+    insert: TypeStoreInsert<'ctx>,
 ) -> TypeStoreAdd<'ctx> {
-    module_builder.build_procedure::<_, TypeStoreAdd>(|function, context, _module| {
+    module_builder.build_function::<_, _, TypeStoreAdd>(|function, context, module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        builder.position_at_end(entry);
+
+        build_lock(context, module, &builder, type_store);
+        let existing = insert.build_call(
+            &builder,
+            (
+                function.get_nth_param(0).unwrap().into_int_value(),
+                function.get_nth_param(1).unwrap().into_pointer_value(),
+            ),
+        );
+        build_unlock(context, &builder, type_store);
+        builder.build_return(Some(&existing)).unwrap();
+    })
+}
+
+/// Same as `add`, for the functions of the store that already hold its lock.
+pub(super) fn make_insert<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    (probe, index_type): (TypeStoreProbe<'ctx>, TypeStoreIndexType<'ctx>),
+) -> TypeStoreInsert<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreInsert>(|function, context, _module| {
         let builder = context.create_builder();
         let entry = context.append_basic_block(function, "entry");
+        let grow_block = context.append_basic_block(function, "grow");
+        let insert_block = context.append_basic_block(function, "insert");
+        let duplicate_block = context.append_basic_block(function, "duplicate");
+        let new_block = context.append_basic_block(function, "new");
 
         builder.position_at_end(entry);
 
-        let store_length = type_store.get_length(&builder);
-        let store_capacity = type_store.get_capacity(&builder);
+        let count = type_store.get_count(&builder);
+        let capacity = type_store.get_capacity(&builder);
 
-        let is_capacity_too_small = builder
-            .build_int_compare(
-                IntPredicate::ULE,
-                store_capacity,
-                store_length,
-                "is_at_capacity",
-            )
+        // Grows before the new type would make it more than 3/4 full, as the probing gets slow
+        // long before the table is actually full
+        let new_count = builder
+            .build_int_add(count, context.const_u32(1), "new_count")
+            .unwrap();
+        let load = builder
+            .build_int_mul(new_count, context.const_u32(4), "load")
+            .unwrap();
+        let max_load = builder
+            .build_int_mul(capacity, context.const_u32(3), "max_load")
+            .unwrap();
+        let is_too_full = builder
+            .build_int_compare(IntPredicate::UGT, load, max_load, "is_too_full")
             .unwrap();
-        let add_capacity_block = context.append_basic_block(function, "add_capacity");
-        let continue_block = context.append_basic_block(function, "continue");
         builder
-            .build_conditional_branch(is_capacity_too_small, add_capacity_block, continue_block)
+            .build_conditional_branch(is_too_full, grow_block, insert_block)
             .unwrap();
 
-        builder.position_at_end(add_capacity_block);
-
-        expand_capacity(type_store, context, &builder);
-
-        builder.build_unconditional_branch(continue_block).unwrap();
-        builder.position_at_end(continue_block);
+        builder.position_at_end(grow_block);
+        build_grow(type_store, probe, context, &builder, function);
+        builder.build_unconditional_branch(insert_block).unwrap();
 
-        let store_types = type_store.get_types(&builder);
-        let new_value_spot = unsafe {
-            builder.build_gep(
-                TypeValueProvider::new(context).llvm_type(),
-                store_types,
-                &[store_length],
-                "new_value_spot",
+        builder.position_at_end(insert_block);
+        let id = function.get_first_param().unwrap().into_int_value();
+        let slot_ptr = probe.build_call(
+            &builder,
+            (
+                type_store.get_slots(&builder),
+                type_store.get_capacity(&builder),
+                id,
+            ),
+        );
+        let slot = TypeValueProvider::new(context).opaque_pointer(slot_ptr);
+        let is_new = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                slot.get_state(&builder),
+                context.i8_type().const_int(u64::from(SLOT_EMPTY), false),
+                "is_new",
             )
-        }
-        .unwrap();
+            .unwrap();
+        builder
+            .build_conditional_branch(is_new, new_block, duplicate_block)
+            .unwrap();
 
-        let new_value = builder
-            .build_load(
-                ValueProvider::new(context).llvm_type(),
-                function.get_nth_param(1).unwrap().into_pointer_value(),
-                "new_value",
-            )
+        builder.position_at_end(duplicate_block);
+        builder
+            .build_return(Some(&slot.get_type(&builder)))
+            .unwrap();
+
+        builder.position_at_end(new_block);
+        builder
+            .build_store(type_store.get_count_ptr(&builder), new_count)
             .unwrap();
 
+        let value = function.get_nth_param(1).unwrap().into_pointer_value();
         TypeValueProvider::new(context).fill_in(
-            new_value_spot,
+            slot_ptr,
             &builder,
             TypeValueOpaque {
-                id: ConstOrValue::Value(function.get_first_param().unwrap().into_int_value()),
-                r#type: ConstOrValue::Value(new_value.into_struct_value()),
+                state: ConstOrValue::Const(SLOT_OCCUPIED),
+                id: ConstOrValue::Value(id),
+                r#type: ConstOrValue::Value(value),
             },
         );
+        index_type.build_call(&builder, (id, value));
 
-        let new_length = builder
-            .build_int_add(store_length, context.const_u32(1), "added_length")
-            .unwrap();
         builder
-            .build_store(type_store.get_length_ptr(&builder), new_length)
+            .build_return(Some(
+                &context.ptr_type(AddressSpace::default()).const_null(),
+            ))
             .unwrap();
-
-        builder.build_return(None).unwrap();
     })
 }
 
-fn expand_capacity<'ctx>(
+/// Doubles the capacity, moving all the types over to where they belong in the bigger table.
+fn build_grow<'ctx>(
     type_store: TypeStoreOpaquePointer<'ctx>,
+    probe: TypeStoreProbe<'ctx>,
     context: &'ctx Context,
-    builder: &inkwell::builder::Builder<'ctx>,
+    builder: &Builder<'ctx>,
+    function: FunctionValue<'ctx>,
 ) {
-    let store_capacity = type_store.get_capacity(builder);
-
+    let slot_type = TypeValueProvider::new(context).llvm_type();
+    let loop_block = context.append_basic_block(function, "rehash");
+    let check_block = context.append_basic_block(function, "rehash_check");
+    let move_block = context.append_basic_block(function, "rehash_move");
+    let next_block = context.append_basic_block(function, "rehash_next");
+    let done_block = context.append_basic_block(function, "rehashed");
+
+    let old_slots = type_store.get_slots(builder);
+    let old_capacity = type_store.get_capacity(builder);
     let new_capacity = builder
-        .build_int_mul(store_capacity, context.const_u32(2), "new_capacity")
+        .build_int_mul(old_capacity, context.const_u32(2), "new_capacity")
+        .unwrap();
+    let new_slots = build_slots_malloc(context, builder, new_capacity);
+    let grow_block = builder.get_insert_block().unwrap();
+    builder.build_unconditional_branch(loop_block).unwrap();
+
+    builder.position_at_end(loop_block);
+    let index = builder.build_phi(context.i32_type(), "index").unwrap();
+    let is_end = builder
+        .build_int_compare(
+            IntPredicate::UGE,
+            index.as_basic_value().into_int_value(),
+            old_capacity,
+            "is_end",
+        )
+        .unwrap();
+    builder
+        .build_conditional_branch(is_end, done_block, check_block)
         .unwrap();
 
+    builder.position_at_end(check_block);
+    let old_slot_ptr = unsafe {
+        builder.build_gep(
+            slot_type,
+            old_slots,
+            &[index.as_basic_value().into_int_value()],
+            "old_slot",
+        )
+    }
+    .unwrap();
+    let old_slot = TypeValueProvider::new(context).opaque_pointer(old_slot_ptr);
+    let is_occupied = builder
+        .build_int_compare(
+            IntPredicate::EQ,
+            old_slot.get_state(builder),
+            context.i8_type().const_int(u64::from(SLOT_OCCUPIED), false),
+            "is_occupied",
+        )
+        .unwrap();
     builder
-        .build_store(type_store.get_capacity_ptr(builder), new_capacity)
+        .build_conditional_branch(is_occupied, move_block, next_block)
         .unwrap();
 
-    let new_types = builder
-        .build_array_malloc(
-            TypeValueProvider::new(context).llvm_type(),
-            new_capacity,
-            "new_types",
+    builder.position_at_end(move_block);
+    // The ids are unique, so the probe always ends at an empty slot
+    let new_slot_ptr =
+        probe.build_call(builder, (new_slots, new_capacity, old_slot.get_id(builder)));
+    let moved = builder
+        .build_load(slot_type, old_slot_ptr, "moved")
+        .unwrap();
+    builder.build_store(new_slot_ptr, moved).unwrap();
+    builder.build_unconditional_branch(next_block).unwrap();
+
+    builder.position_at_end(next_block);
+    let next_index = builder
+        .build_int_add(
+            index.as_basic_value().into_int_value(),
+            context.const_u32(1),
+            "next_index",
         )
         .unwrap();
+    builder.build_unconditional_branch(loop_block).unwrap();
 
-    let store_types = type_store.get_types(builder);
+    index.add_incoming(&[
+        (&context.const_u32(0), grow_block),
+        (&next_index, next_block),
+    ]);
 
+    builder.position_at_end(done_block);
+    builder.build_free(old_slots).unwrap();
     builder
-        .build_memmove(new_types, 1, store_types, 1, store_capacity)
+        .build_store(type_store.get_slots_ptr(builder), new_slots)
         .unwrap();
-
     builder
-        .build_store(type_store.get_types_ptr(builder), new_types)
+        .build_store(type_store.get_capacity_ptr(builder), new_capacity)
         .unwrap();
 }
@@ -0,0 +1,89 @@
+use inkwell::IntPredicate;
+
+use super::{
+    SLOT_OCCUPIED, TypeStoreOpaquePointer, TypeValueProvider,
+    lock::{build_lock, build_unlock},
+    probe::TypeStoreProbe,
+};
+use crate::codegen::{ContextErgonomics, context::Function as _, module};
+
+make_function_type!(TypeStoreAllocateId, (): u32);
+make_function_type!(TypeStoreNextId, (): u32);
+
+/// Hands out a fresh id for a type, above the range of the `TypeTag`s. The ids are counted up,
+/// skipping the ones the host already registered types under.
+pub(super) fn make_allocate_id<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    next_id: TypeStoreNextId<'ctx>,
+) -> TypeStoreAllocateId<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreAllocateId>(|function, context, module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        builder.position_at_end(entry);
+
+        build_lock(context, module, &builder, type_store);
+        let id = next_id.build_call(&builder, ());
+        build_unlock(context, &builder, type_store);
+        builder.build_return(Some(&id)).unwrap();
+    })
+}
+
+/// Same as `allocate_id`, for the functions of the store that already hold its lock.
+pub(super) fn make_next_id<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    probe: TypeStoreProbe<'ctx>,
+) -> TypeStoreNextId<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreNextId>(|function, context, _module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let loop_block = context.append_basic_block(function, "loop");
+        let next_block = context.append_basic_block(function, "next");
+        let done_block = context.append_basic_block(function, "done");
+        builder.position_at_end(entry);
+
+        let first_id = type_store.get_next_id(&builder);
+        builder.build_unconditional_branch(loop_block).unwrap();
+
+        builder.position_at_end(loop_block);
+        let id = builder.build_phi(context.i32_type(), "id").unwrap();
+        let id_value = id.as_basic_value().into_int_value();
+        let slot_ptr = probe.build_call(
+            &builder,
+            (
+                type_store.get_slots(&builder),
+                type_store.get_capacity(&builder),
+                id_value,
+            ),
+        );
+        let is_taken = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                TypeValueProvider::new(context)
+                    .opaque_pointer(slot_ptr)
+                    .get_state(&builder),
+                context.i8_type().const_int(u64::from(SLOT_OCCUPIED), false),
+                "is_taken",
+            )
+            .unwrap();
+        // TODO the ids never get reused, so after 2^32 of them this wraps around into the tags
+        let next_id = builder
+            .build_int_add(id_value, context.const_u32(1), "next_id")
+            .unwrap();
+        builder
+            .build_conditional_branch(is_taken, next_block, done_block)
+            .unwrap();
+
+        builder.position_at_end(next_block);
+        builder.build_unconditional_branch(loop_block).unwrap();
+
+        id.add_incoming(&[(&first_id, entry), (&next_id, next_block)]);
+
+        builder.position_at_end(done_block);
+        builder
+            .build_store(type_store.get_next_id_ptr(&builder), next_id)
+            .unwrap();
+        builder.build_return(Some(&id_value)).unwrap();
+    })
+}
@@ -0,0 +1,509 @@
+use inkwell::{
+    AddressSpace, IntPredicate,
+    basic_block::BasicBlock,
+    builder::Builder,
+    context::Context,
+    values::{IntValue, PointerValue},
+};
+
+use super::{
+    SLOT_OCCUPIED, TypeStoreOpaquePointer, TypeValueProvider,
+    equal::{build_all_equal, build_raw_pointer},
+    lock::{build_lock, build_unlock},
+    probe::TypeStoreProbe,
+};
+use crate::{
+    bytecode::{TypeId, TypeTag},
+    codegen::{
+        ContextErgonomics,
+        context::Function,
+        llvm_struct::{
+            basic_value_enum::IntoValue,
+            representations::{ConstOrValue, LlvmRepresentation, OperandValue},
+        },
+        module,
+        types::{
+            classes::ClassId,
+            functions::{FunctionArgumentProvider, FunctionSignatureProvider},
+            values::{ValueOpaque, ValueOpaquePointer, ValueProvider},
+        },
+    },
+};
+
+// A pair of types that's being checked further up the stack, and so is assumed to be assignable,
+// the pairs form a list through the stack frames of `TypeStoreCheckAssignable`
+llvm_struct! {
+    struct AssumedAssignable {
+        from: u32,
+        to: u32,
+        next: *const AssumedAssignable
+    }
+}
+
+make_function_type!(TypeStoreIsAssignable, (from: u32, to: u32): u8);
+make_function_type!(
+    TypeStoreCheckAssignable,
+    (from: u32, to: u32, assumed: *const AssumedAssignable): u8
+);
+
+/// Returns 1 if a value of the type `from` can be used where the type `to` is expected, 0 if it
+/// can't. A type is always assignable to itself, even an unknown one, otherwise unknown types
+/// aren't assignable to anything. The ids of the `TypeTag`s stand for the primitives.
+///
+/// The types have to be of the same kind, and the class of `to` has to be the same as the one of
+/// `from`, or none. A function signature is assignable if it takes arguments that are at least
+/// as general as the expected ones, and returns a result that is at least as specific, the names
+/// of the arguments don't matter as they're passed by position. Everything else has to be the
+/// same, see `equal`.
+pub(super) fn make_is_assignable<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    probe: TypeStoreProbe<'ctx>,
+) -> TypeStoreIsAssignable<'ctx> {
+    let check_assignable = make_check_assignable(module_builder, type_store, probe);
+
+    module_builder.build_function::<_, _, TypeStoreIsAssignable>(|function, context, module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        builder.position_at_end(entry);
+
+        build_lock(context, module, &builder, type_store);
+        let is_assignable = check_assignable.build_call(
+            &builder,
+            (
+                function.get_nth_param(0).unwrap().into_int_value(),
+                function.get_nth_param(1).unwrap().into_int_value(),
+                context.ptr_type(AddressSpace::default()).const_null(),
+            ),
+        );
+        build_unlock(context, &builder, type_store);
+        builder.build_return(Some(&is_assignable)).unwrap();
+    })
+}
+
+/// Same as `is_assignable`, for checking the types the signatures refer to while the store is
+/// locked. The types referring to each other in a cycle would make the check recurse forever, so
+/// the pairs in the `assumed` list are taken to be assignable, as they're being checked already.
+fn make_check_assignable<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    probe: TypeStoreProbe<'ctx>,
+) -> TypeStoreCheckAssignable<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreCheckAssignable>(|function, context, _module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let assumed_block = context.append_basic_block(function, "assumed");
+        let resolve_block = context.append_basic_block(function, "resolve");
+        let signatures_block = context.append_basic_block(function, "signatures");
+        let assignable_block = context.append_basic_block(function, "assignable");
+        let unassignable_block = context.append_basic_block(function, "unassignable");
+        builder.position_at_end(entry);
+
+        let value_type = ValueProvider::new(context).llvm_type();
+        let from_primitive = builder.build_alloca(value_type, "from_primitive").unwrap();
+        let to_primitive = builder.build_alloca(value_type, "to_primitive").unwrap();
+        let assumption_provider = AssumedAssignableProvider::new(context);
+        let assumption = builder
+            .build_alloca(assumption_provider.llvm_type(), "assumption")
+            .unwrap();
+        let from_id = function.get_nth_param(0).unwrap().into_int_value();
+        let to_id = function.get_nth_param(1).unwrap().into_int_value();
+        let assumed = function.get_nth_param(2).unwrap().into_pointer_value();
+
+        // A type is always assignable to itself, which also ends the recursion for the types
+        // that refer to themselves
+        let is_same_id = builder
+            .build_int_compare(IntPredicate::EQ, from_id, to_id, "is_same_id")
+            .unwrap();
+        builder
+            .build_conditional_branch(is_same_id, assignable_block, assumed_block)
+            .unwrap();
+
+        builder.position_at_end(assumed_block);
+        build_find_assumption(
+            context,
+            &builder,
+            (assumed, from_id, to_id),
+            (assignable_block, resolve_block),
+        );
+
+        builder.position_at_end(resolve_block);
+        let from = build_resolve(
+            context,
+            &builder,
+            (type_store, probe),
+            (from_id, from_primitive),
+            unassignable_block,
+        );
+        let to = build_resolve(
+            context,
+            &builder,
+            (type_store, probe),
+            (to_id, to_primitive),
+            unassignable_block,
+        );
+        build_check_kinds(
+            context,
+            &builder,
+            (from, to),
+            (signatures_block, assignable_block, unassignable_block),
+        );
+
+        builder.position_at_end(signatures_block);
+        assumption_provider.fill_in(
+            assumption,
+            &builder,
+            AssumedAssignableOpaque {
+                from: ConstOrValue::Value(from_id),
+                to: ConstOrValue::Value(to_id),
+                next: ConstOrValue::Value(assumed),
+            },
+        );
+        build_check_signatures(
+            context,
+            &builder,
+            (TypeStoreCheckAssignable::new(function), assumption),
+            (from.get_raw(&builder), to.get_raw(&builder)),
+            (assignable_block, unassignable_block),
+        );
+
+        builder.position_at_end(assignable_block);
+        builder
+            .build_return(Some(&context.i8_type().const_int(1, false)))
+            .unwrap();
+
+        builder.position_at_end(unassignable_block);
+        builder
+            .build_return(Some(&context.i8_type().const_zero()))
+            .unwrap();
+    })
+}
+
+/// Checks that the values of the types are of the same kind, branching to `signatures_block` if
+/// they both point to signatures, which are checked further. Anything else has to be the same.
+fn build_check_kinds<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    (from, to): (ValueOpaquePointer<'ctx>, ValueOpaquePointer<'ctx>),
+    (signatures_block, assignable_block, unassignable_block): (
+        BasicBlock<'ctx>,
+        BasicBlock<'ctx>,
+        BasicBlock<'ctx>,
+    ),
+) {
+    let function = builder.get_insert_block().unwrap().get_parent().unwrap();
+    let kind_block = context.append_basic_block(function, "kind");
+    let raw_block = context.append_basic_block(function, "raw");
+
+    let is_same_kind = builder
+        .build_and(
+            build_all_equal(builder, &[(from.get_tag(builder), to.get_tag(builder))]),
+            build_accepts_class(
+                context,
+                builder,
+                from.get_class_id(builder),
+                to.get_class_id(builder),
+            ),
+            "is_same_kind",
+        )
+        .unwrap();
+    builder
+        .build_conditional_branch(is_same_kind, kind_block, unassignable_block)
+        .unwrap();
+
+    // The id of the `FunctionSignature` tag stands for a primitive with the same tag but no
+    // signature behind it, so only the values that point to one are compared as signatures
+    builder.position_at_end(kind_block);
+    let null = context.i64_type().const_zero();
+    let is_signature_tag = builder
+        .build_int_compare(
+            IntPredicate::EQ,
+            from.get_tag(builder),
+            context
+                .i8_type()
+                .const_int(TypeTag::FunctionSignature as u64, false),
+            "is_signature_tag",
+        )
+        .unwrap();
+    let has_signatures = builder
+        .build_and(
+            builder
+                .build_int_compare(IntPredicate::NE, from.get_raw(builder), null, "from_set")
+                .unwrap(),
+            builder
+                .build_int_compare(IntPredicate::NE, to.get_raw(builder), null, "to_set")
+                .unwrap(),
+            "has_signatures",
+        )
+        .unwrap();
+    let is_signature = builder
+        .build_and(is_signature_tag, has_signatures, "is_signature")
+        .unwrap();
+    builder
+        .build_conditional_branch(is_signature, signatures_block, raw_block)
+        .unwrap();
+
+    builder.position_at_end(raw_block);
+    let is_same_raw = build_all_equal(builder, &[(from.get_raw(builder), to.get_raw(builder))]);
+    builder
+        .build_conditional_branch(is_same_raw, assignable_block, unassignable_block)
+        .unwrap();
+}
+
+/// Looks for the pair of types in the `assumed` list, branching to `found_block` if it's there,
+/// and to `missing_block` otherwise.
+fn build_find_assumption<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    (assumed, from, to): (PointerValue<'ctx>, IntValue<'ctx>, IntValue<'ctx>),
+    (found_block, missing_block): (BasicBlock<'ctx>, BasicBlock<'ctx>),
+) {
+    let start_block = builder.get_insert_block().unwrap();
+    let function = start_block.get_parent().unwrap();
+    let loop_block = context.append_basic_block(function, "assumption");
+    let check_block = context.append_basic_block(function, "assumption_check");
+    let next_block = context.append_basic_block(function, "assumption_next");
+    builder.build_unconditional_branch(loop_block).unwrap();
+
+    builder.position_at_end(loop_block);
+    let current = builder.build_phi(assumed.get_type(), "current").unwrap();
+    let current_value = current.as_basic_value().into_pointer_value();
+    let is_end = builder.build_is_null(current_value, "is_end").unwrap();
+    builder
+        .build_conditional_branch(is_end, missing_block, check_block)
+        .unwrap();
+
+    builder.position_at_end(check_block);
+    let assumption = AssumedAssignableProvider::new(context).opaque_pointer(current_value);
+    let is_assumed = build_all_equal(
+        builder,
+        &[
+            (assumption.get_from(builder), from),
+            (assumption.get_to(builder), to),
+        ],
+    );
+    let next = assumption.get_next(builder);
+    builder
+        .build_conditional_branch(is_assumed, found_block, next_block)
+        .unwrap();
+
+    builder.position_at_end(next_block);
+    builder.build_unconditional_branch(loop_block).unwrap();
+
+    current.add_incoming(&[(&assumed, start_block), (&next, next_block)]);
+}
+
+/// Finds the type with the id, branching to `missing_block` if there's none. The primitives
+/// aren't in the store, so their values are made up in the `primitive` stack slot instead.
+fn build_resolve<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    (type_store, probe): (TypeStoreOpaquePointer<'ctx>, TypeStoreProbe<'ctx>),
+    (id, primitive): (IntValue<'ctx>, PointerValue<'ctx>),
+    missing_block: BasicBlock<'ctx>,
+) -> ValueOpaquePointer<'ctx> {
+    let function = builder.get_insert_block().unwrap().get_parent().unwrap();
+    let primitive_block = context.append_basic_block(function, "primitive");
+    let stored_block = context.append_basic_block(function, "stored");
+    let resolved_block = context.append_basic_block(function, "resolved");
+
+    let is_primitive = builder
+        .build_int_compare(
+            IntPredicate::ULT,
+            id,
+            context.const_u32(TypeId::RESERVED),
+            "is_primitive",
+        )
+        .unwrap();
+    builder
+        .build_conditional_branch(is_primitive, primitive_block, stored_block)
+        .unwrap();
+
+    builder.position_at_end(primitive_block);
+    let tag = builder
+        .build_int_truncate(id, context.i8_type(), "tag")
+        .unwrap();
+    ValueProvider::new(context).fill_in(
+        primitive,
+        builder,
+        ValueOpaque {
+            tag: ConstOrValue::Value(tag),
+            unused_0: ConstOrValue::Const(0),
+            class_id: ConstOrValue::Const(ClassId::none()),
+            unused_1: ConstOrValue::Const(0),
+            raw: ConstOrValue::Const(0),
+        },
+    );
+    builder.build_unconditional_branch(resolved_block).unwrap();
+
+    builder.position_at_end(stored_block);
+    let slot = TypeValueProvider::new(context).opaque_pointer(probe.build_call(
+        builder,
+        (
+            type_store.get_slots(builder),
+            type_store.get_capacity(builder),
+            id,
+        ),
+    ));
+    let is_occupied = builder
+        .build_int_compare(
+            IntPredicate::EQ,
+            slot.get_state(builder),
+            context.i8_type().const_int(u64::from(SLOT_OCCUPIED), false),
+            "is_occupied",
+        )
+        .unwrap();
+    let stored = slot.get_type(builder);
+    builder
+        .build_conditional_branch(is_occupied, resolved_block, missing_block)
+        .unwrap();
+
+    builder.position_at_end(resolved_block);
+    let resolved = builder.build_phi(primitive.get_type(), "resolved").unwrap();
+    resolved.add_incoming(&[(&primitive, primitive_block), (&stored, stored_block)]);
+
+    ValueProvider::new(context).opaque_pointer(resolved.as_basic_value().into_pointer_value())
+}
+
+/// Whether a value of the class `from` can be used where `to` is expected, see
+/// `ClassId::is_accepted_by`.
+fn build_accepts_class<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    from: IntValue<'ctx>,
+    to: IntValue<'ctx>,
+) -> IntValue<'ctx> {
+    let is_same_class = build_all_equal(builder, &[(from, to)]);
+    let is_classless = builder
+        .build_int_compare(
+            IntPredicate::EQ,
+            to,
+            context.i16_type().const_zero(),
+            "is_classless",
+        )
+        .unwrap();
+
+    builder
+        .build_or(is_same_class, is_classless, "accepts_class")
+        .unwrap()
+}
+
+/// Checks the signatures the raw values point to, branching to one of the blocks. The arguments
+/// are checked the other way around than the return types, as the function gets called with the
+/// arguments meant for `to`. The types they refer to are checked with the `assumed` list.
+fn build_check_signatures<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    (is_assignable, assumed): (TypeStoreCheckAssignable<'ctx>, PointerValue<'ctx>),
+    (from, to): (IntValue<'ctx>, IntValue<'ctx>),
+    (assignable_block, unassignable_block): (BasicBlock<'ctx>, BasicBlock<'ctx>),
+) {
+    let signatures_block = builder.get_insert_block().unwrap();
+    let function = signatures_block.get_parent().unwrap();
+    let arguments_block = context.append_basic_block(function, "arguments");
+    let element_block = context.append_basic_block(function, "argument");
+    let next_block = context.append_basic_block(function, "next");
+    let return_block = context.append_basic_block(function, "return");
+
+    let is_assignable = |from_id, to_id| {
+        let result = is_assignable.build_call(builder, (from_id, to_id, assumed));
+
+        builder
+            .build_int_compare(
+                IntPredicate::NE,
+                result,
+                context.i8_type().const_zero(),
+                "is_assignable",
+            )
+            .unwrap()
+    };
+
+    let signature_provider = FunctionSignatureProvider::new(context);
+    let from = signature_provider.opaque_pointer(build_raw_pointer(context, builder, from));
+    let to = signature_provider.opaque_pointer(build_raw_pointer(context, builder, to));
+    let is_same_shape = builder
+        .build_and(
+            build_accepts_class(
+                context,
+                builder,
+                from.get_class_id(builder),
+                to.get_class_id(builder),
+            ),
+            build_all_equal(
+                builder,
+                &[(
+                    from.get_argument_count(builder),
+                    to.get_argument_count(builder),
+                )],
+            ),
+            "is_same_shape",
+        )
+        .unwrap();
+    let argument_count = builder
+        .build_int_z_extend(
+            from.get_argument_count(builder),
+            context.i32_type(),
+            "argument_count",
+        )
+        .unwrap();
+    let from_arguments = from.get_arguments(builder);
+    let to_arguments = to.get_arguments(builder);
+    builder
+        .build_conditional_branch(is_same_shape, arguments_block, unassignable_block)
+        .unwrap();
+
+    builder.position_at_end(arguments_block);
+    let index = builder.build_phi(context.i32_type(), "index").unwrap();
+    let index_value = index.as_basic_value().into_int_value();
+    let is_end = builder
+        .build_int_compare(IntPredicate::UGE, index_value, argument_count, "is_end")
+        .unwrap();
+    builder
+        .build_conditional_branch(is_end, return_block, element_block)
+        .unwrap();
+
+    builder.position_at_end(element_block);
+    let argument_provider = FunctionArgumentProvider::new(context);
+    let argument = |arguments, name| {
+        let pointer = unsafe {
+            builder.build_gep(
+                argument_provider.llvm_type(),
+                arguments,
+                &[index_value],
+                name,
+            )
+        }
+        .unwrap();
+
+        argument_provider.opaque_pointer(pointer)
+    };
+    let from_argument = argument(from_arguments, "from_argument");
+    let to_argument = argument(to_arguments, "to_argument");
+    let accepts_argument = is_assignable(
+        to_argument.get_type_id(builder),
+        from_argument.get_type_id(builder),
+    );
+    builder
+        .build_conditional_branch(accepts_argument, next_block, unassignable_block)
+        .unwrap();
+
+    builder.position_at_end(next_block);
+    let next_index = builder
+        .build_int_add(index_value, context.const_u32(1), "next_index")
+        .unwrap();
+    builder.build_unconditional_branch(arguments_block).unwrap();
+
+    index.add_incoming(&[
+        (&context.i32_type().const_zero(), signatures_block),
+        (&next_index, next_block),
+    ]);
+
+    builder.position_at_end(return_block);
+    let returns_assignable = is_assignable(
+        from.get_return_type_id(builder),
+        to.get_return_type_id(builder),
+    );
+    builder
+        .build_conditional_branch(returns_assignable, assignable_block, unassignable_block)
+        .unwrap();
+}
@@ -0,0 +1,50 @@
+use inkwell::{AddressSpace, values::PointerValue};
+
+use super::TypeStoreProvider;
+use crate::{
+    bytecode::TypeId,
+    codegen::{
+        llvm_struct::representations::ConstOrValue,
+        module::{self, GlobalConstructorFunction},
+    },
+};
+
+/// Frees the storage the initializer allocated, and empties the store, so that it's never freed
+/// twice. The values the types point to are not owned by the store, so they're left alone.
+pub(super) fn make_type_store_destructor<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: PointerValue<'ctx>,
+) -> GlobalConstructorFunction<'ctx> {
+    module_builder.build_procedure::<_, GlobalConstructorFunction>(|function, context, _module| {
+        let entry = context.append_basic_block(function, "entry");
+        let builder = context.create_builder();
+        builder.position_at_end(entry);
+
+        let type_store_provider = TypeStoreProvider::new(context);
+        let opaque_type_store = type_store_provider.opaque_pointer(type_store);
+
+        // Freeing null does nothing, so running the destructor again is harmless
+        builder
+            .build_free(opaque_type_store.get_slots(&builder))
+            .unwrap();
+        builder
+            .build_free(opaque_type_store.get_index(&builder))
+            .unwrap();
+
+        type_store_provider.fill_in(
+            type_store,
+            &builder,
+            super::TypeStoreOpaque {
+                slots: ConstOrValue::Value(context.ptr_type(AddressSpace::default()).const_null()),
+                count: ConstOrValue::Const(0),
+                capacity: ConstOrValue::Const(0),
+                next_id: ConstOrValue::Const(TypeId::RESERVED),
+                lock: ConstOrValue::Const(0),
+                index: ConstOrValue::Value(context.ptr_type(AddressSpace::default()).const_null()),
+                index_count: ConstOrValue::Const(0),
+                index_capacity: ConstOrValue::Const(0),
+            },
+        );
+        builder.build_return(None).unwrap();
+    })
+}
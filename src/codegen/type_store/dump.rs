@@ -0,0 +1,252 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write as _},
+};
+
+use super::host::StoredType;
+use crate::{
+    bytecode::{TypeId, TypeTag},
+    codegen::types::classes::ClassId,
+};
+
+/// A snapshot of the types in the type store, with the types they refer to resolved to readable
+/// names, for seeing what a program registered. It prints as a table, or as JSON with `to_json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeStoreDump {
+    /// Ordered by their ids
+    pub types: Vec<DumpedType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpedType {
+    pub id: TypeId,
+    /// How the type reads, like `fn($1: u64) -> u64`
+    pub name: String,
+    /// The class the values of the type have, if any
+    pub class_id: ClassId,
+    pub kind: DumpedKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DumpedKind {
+    Primitive(TypeTag),
+    FunctionSignature {
+        arguments: Vec<DumpedArgument>,
+        return_type: TypeReference,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpedArgument {
+    /// The name of the argument, as it's written in the bytecode
+    pub name: String,
+    pub r#type: TypeReference,
+}
+
+/// A type one of the types refers to by its id, which doesn't have to be in the store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeReference {
+    pub id: TypeId,
+    pub name: String,
+}
+
+impl TypeStoreDump {
+    /// Resolves the names of the types, as they are in the store.
+    #[must_use]
+    pub fn new(mut types: Vec<(TypeId, StoredType)>) -> Self {
+        types.sort_by_key(|(id, _)| id.as_u32());
+        let names = Names {
+            types: types.iter().cloned().collect(),
+        };
+
+        Self {
+            types: types
+                .into_iter()
+                .map(|(id, r#type)| DumpedType {
+                    id,
+                    name: names.name(id, &mut vec![]),
+                    class_id: match r#type {
+                        StoredType::Primitive { class_id, .. }
+                        | StoredType::FunctionSignature { class_id, .. } => class_id,
+                    },
+                    kind: match r#type {
+                        StoredType::Primitive { tag, .. } => DumpedKind::Primitive(tag),
+                        StoredType::FunctionSignature {
+                            arguments,
+                            return_type,
+                            ..
+                        } => DumpedKind::FunctionSignature {
+                            arguments: arguments
+                                .into_iter()
+                                .map(|(name, type_id)| DumpedArgument {
+                                    name: name.to_string(),
+                                    r#type: names.reference(type_id),
+                                })
+                                .collect(),
+                            return_type: names.reference(return_type),
+                        },
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the types as a JSON object, with the types in its `types` array.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let types: Vec<_> = self.types.iter().map(DumpedType::to_json).collect();
+
+        format!("{{\"types\":[{}]}}", types.join(","))
+    }
+}
+
+impl DumpedType {
+    fn to_json(&self) -> String {
+        let kind = match &self.kind {
+            DumpedKind::Primitive(tag) => {
+                format!(
+                    "\"kind\":\"primitive\",\"tag\":{}",
+                    json_string(tag_name(*tag))
+                )
+            }
+            DumpedKind::FunctionSignature {
+                arguments,
+                return_type,
+            } => {
+                let arguments: Vec<_> = arguments
+                    .iter()
+                    .map(|argument| {
+                        format!(
+                            "{{\"name\":{},\"type\":{}}}",
+                            json_string(&argument.name),
+                            argument.r#type.to_json()
+                        )
+                    })
+                    .collect();
+
+                format!(
+                    "\"kind\":\"function_signature\",\"arguments\":[{}],\"return_type\":{}",
+                    arguments.join(","),
+                    return_type.to_json()
+                )
+            }
+        };
+
+        format!(
+            "{{\"id\":{},\"name\":{},\"class_id\":{},{kind}}}",
+            self.id.as_u32(),
+            json_string(&self.name),
+            self.class_id.as_u16()
+        )
+    }
+}
+
+impl TypeReference {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"id\":{},\"name\":{}}}",
+            self.id.as_u32(),
+            json_string(&self.name)
+        )
+    }
+}
+
+impl Display for TypeStoreDump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id_width = self
+            .types
+            .iter()
+            .map(|r#type| r#type.id.as_u32().to_string().len())
+            .max()
+            .unwrap_or(0)
+            .max("id".len());
+
+        writeln!(f, "{:>id_width$}  {:<18}  type", "id", "kind")?;
+        for r#type in &self.types {
+            let kind = match r#type.kind {
+                DumpedKind::Primitive(_) => "primitive",
+                DumpedKind::FunctionSignature { .. } => "function signature",
+            };
+
+            writeln!(
+                f,
+                "{:>id_width$}  {kind:<18}  {}",
+                r#type.id.as_u32(),
+                r#type.name
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolves the ids the types refer to, the ones of the `TypeTag`s stand for the primitives.
+struct Names {
+    types: HashMap<TypeId, StoredType>,
+}
+
+impl Names {
+    fn reference(&self, id: TypeId) -> TypeReference {
+        TypeReference {
+            id,
+            name: self.name(id, &mut vec![]),
+        }
+    }
+
+    /// The types can refer to each other in a cycle, so the ones that are being named already
+    /// (and the unknown ones) are left as their ids.
+    fn name(&self, id: TypeId, naming: &mut Vec<TypeId>) -> String {
+        if let Some(tag) = u8::try_from(id.as_u32()).ok().and_then(TypeTag::from_value) {
+            return tag_name(tag).to_string();
+        }
+
+        let Some(r#type) = self.types.get(&id).filter(|_| !naming.contains(&id)) else {
+            return format!("#{}", id.as_u32());
+        };
+
+        match r#type {
+            StoredType::Primitive { tag, .. } => tag_name(*tag).to_string(),
+            StoredType::FunctionSignature {
+                arguments,
+                return_type,
+                ..
+            } => {
+                naming.push(id);
+                let arguments: Vec<_> = arguments
+                    .iter()
+                    .map(|(name, type_id)| format!("{name}: {}", self.name(*type_id, naming)))
+                    .collect();
+                let return_type = self.name(*return_type, naming);
+                naming.pop();
+
+                format!("fn({}) -> {return_type}", arguments.join(", "))
+            }
+        }
+    }
+}
+
+const fn tag_name(tag: TypeTag) -> &'static str {
+    match tag {
+        TypeTag::Primitive => "primitive",
+        TypeTag::U64 => "u64",
+        TypeTag::FunctionSignature => "fn",
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            character if character.is_control() => {
+                write!(escaped, "\\u{:04x}", u32::from(character)).unwrap();
+            }
+            character => escaped.push(character),
+        }
+    }
+    escaped.push('"');
+
+    escaped
+}
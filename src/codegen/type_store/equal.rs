@@ -0,0 +1,233 @@
+use inkwell::{
+    AddressSpace, IntPredicate,
+    basic_block::BasicBlock,
+    builder::Builder,
+    context::Context,
+    values::{IntValue, PointerValue},
+};
+
+use crate::{
+    bytecode::TypeTag,
+    codegen::{
+        module,
+        types::{
+            functions::{FunctionArgumentProvider, FunctionSignatureProvider},
+            values::{Value, ValueProvider},
+        },
+    },
+};
+
+make_function_type!(TypeStoreTypesEqual, (left: *const Value, right: *const Value): u8);
+
+/// Compares the types structurally, returning 1 if they're the same. The function signatures are
+/// compared by their classes, arguments (names included) and return types, everything else by
+/// its raw value.
+pub(super) fn make_types_equal<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+) -> TypeStoreTypesEqual<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreTypesEqual>(|function, context, _module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let kind_block = context.append_basic_block(function, "kind");
+        let raw_block = context.append_basic_block(function, "raw");
+        let signatures_block = context.append_basic_block(function, "signatures");
+        let equal_block = context.append_basic_block(function, "equal");
+        let different_block = context.append_basic_block(function, "different");
+        builder.position_at_end(entry);
+
+        let value_provider = ValueProvider::new(context);
+        let left =
+            value_provider.opaque_pointer(function.get_nth_param(0).unwrap().into_pointer_value());
+        let right =
+            value_provider.opaque_pointer(function.get_nth_param(1).unwrap().into_pointer_value());
+
+        let is_same_kind = build_all_equal(
+            &builder,
+            &[
+                (left.get_tag(&builder), right.get_tag(&builder)),
+                (left.get_class_id(&builder), right.get_class_id(&builder)),
+            ],
+        );
+        builder
+            .build_conditional_branch(is_same_kind, kind_block, different_block)
+            .unwrap();
+
+        builder.position_at_end(kind_block);
+        let is_signature = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                left.get_tag(&builder),
+                context
+                    .i8_type()
+                    .const_int(TypeTag::FunctionSignature as u64, false),
+                "is_signature",
+            )
+            .unwrap();
+        builder
+            .build_conditional_branch(is_signature, signatures_block, raw_block)
+            .unwrap();
+
+        builder.position_at_end(raw_block);
+        let is_same_raw = build_all_equal(
+            &builder,
+            &[(left.get_raw(&builder), right.get_raw(&builder))],
+        );
+        builder
+            .build_conditional_branch(is_same_raw, equal_block, different_block)
+            .unwrap();
+
+        builder.position_at_end(signatures_block);
+        build_compare_signatures(
+            context,
+            &builder,
+            (left.get_raw(&builder), right.get_raw(&builder)),
+            equal_block,
+            different_block,
+        );
+
+        builder.position_at_end(equal_block);
+        builder
+            .build_return(Some(&context.i8_type().const_int(1, false)))
+            .unwrap();
+
+        builder.position_at_end(different_block);
+        builder
+            .build_return(Some(&context.i8_type().const_zero()))
+            .unwrap();
+    })
+}
+
+/// Whether all the pairs are equal.
+pub(super) fn build_all_equal<'ctx>(
+    builder: &Builder<'ctx>,
+    pairs: &[(IntValue<'ctx>, IntValue<'ctx>)],
+) -> IntValue<'ctx> {
+    pairs
+        .iter()
+        .map(|(left, right)| {
+            builder
+                .build_int_compare(IntPredicate::EQ, *left, *right, "is_equal")
+                .unwrap()
+        })
+        .reduce(|all, equal| builder.build_and(all, equal, "are_equal").unwrap())
+        .unwrap()
+}
+
+/// The values keep the pointers to what they describe in their raw part.
+pub(super) fn build_raw_pointer<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    raw: IntValue<'ctx>,
+) -> PointerValue<'ctx> {
+    builder
+        .build_int_to_ptr(
+            raw,
+            context.ptr_type(AddressSpace::default()),
+            "raw_pointer",
+        )
+        .unwrap()
+}
+
+/// Compares the signatures the raw values point to, branching to one of the blocks.
+fn build_compare_signatures<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    (left, right): (IntValue<'ctx>, IntValue<'ctx>),
+    equal_block: BasicBlock<'ctx>,
+    different_block: BasicBlock<'ctx>,
+) {
+    let signatures_block = builder.get_insert_block().unwrap();
+    let function = signatures_block.get_parent().unwrap();
+    let arguments_block = context.append_basic_block(function, "arguments");
+    let element_block = context.append_basic_block(function, "argument");
+    let next_block = context.append_basic_block(function, "next");
+
+    let signature_provider = FunctionSignatureProvider::new(context);
+    let left = signature_provider.opaque_pointer(build_raw_pointer(context, builder, left));
+    let right = signature_provider.opaque_pointer(build_raw_pointer(context, builder, right));
+    let is_same_shape = build_all_equal(
+        builder,
+        &[
+            (left.get_class_id(builder), right.get_class_id(builder)),
+            (
+                left.get_argument_count(builder),
+                right.get_argument_count(builder),
+            ),
+            (
+                left.get_return_type_id(builder),
+                right.get_return_type_id(builder),
+            ),
+        ],
+    );
+    let argument_count = builder
+        .build_int_z_extend(
+            left.get_argument_count(builder),
+            context.i32_type(),
+            "argument_count",
+        )
+        .unwrap();
+    let left_arguments = left.get_arguments(builder);
+    let right_arguments = right.get_arguments(builder);
+    builder
+        .build_conditional_branch(is_same_shape, arguments_block, different_block)
+        .unwrap();
+
+    builder.position_at_end(arguments_block);
+    let index = builder.build_phi(context.i32_type(), "index").unwrap();
+    let index_value = index.as_basic_value().into_int_value();
+    let is_end = builder
+        .build_int_compare(IntPredicate::UGE, index_value, argument_count, "is_end")
+        .unwrap();
+    builder
+        .build_conditional_branch(is_end, equal_block, element_block)
+        .unwrap();
+
+    builder.position_at_end(element_block);
+    let argument_provider = FunctionArgumentProvider::new(context);
+    let argument = |arguments, name| {
+        let pointer = unsafe {
+            builder.build_gep(
+                argument_provider.llvm_type(),
+                arguments,
+                &[index_value],
+                name,
+            )
+        }
+        .unwrap();
+
+        argument_provider.opaque_pointer(pointer)
+    };
+    let left_argument = argument(left_arguments, "left_argument");
+    let right_argument = argument(right_arguments, "right_argument");
+    let is_same_argument = build_all_equal(
+        builder,
+        &[
+            (
+                left_argument.get_name(builder),
+                right_argument.get_name(builder),
+            ),
+            (
+                left_argument.get_type_id(builder),
+                right_argument.get_type_id(builder),
+            ),
+        ],
+    );
+    builder
+        .build_conditional_branch(is_same_argument, next_block, different_block)
+        .unwrap();
+
+    builder.position_at_end(next_block);
+    let next_index = builder
+        .build_int_add(
+            index_value,
+            context.i32_type().const_int(1, false),
+            "next_index",
+        )
+        .unwrap();
+    builder.build_unconditional_branch(arguments_block).unwrap();
+
+    index.add_incoming(&[
+        (&context.i32_type().const_zero(), signatures_block),
+        (&next_index, next_block),
+    ]);
+}
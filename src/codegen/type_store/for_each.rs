@@ -0,0 +1,118 @@
+use inkwell::{AddressSpace, IntPredicate};
+
+use super::{
+    SLOT_OCCUPIED, TypeStoreOpaquePointer, TypeValueProvider,
+    lock::{build_lock, build_unlock},
+};
+use crate::codegen::{ContextErgonomics, module};
+
+// The visitor is a `void (ptr data, u32 id, ptr value)`, called with the data given to for_each
+make_function_type!(TypeStoreForEach, (visitor: *const (), data: *const ()));
+
+/// Calls the visitor with every type in the store, in no particular order. The store stays locked
+/// meanwhile, so the visitor must not use it.
+pub(super) fn make_for_each<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+) -> TypeStoreForEach<'ctx> {
+    module_builder.build_procedure::<_, TypeStoreForEach>(|function, context, module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let loop_block = context.append_basic_block(function, "loop");
+        let check_block = context.append_basic_block(function, "check");
+        let visit_block = context.append_basic_block(function, "visit");
+        let next_block = context.append_basic_block(function, "next");
+        let done_block = context.append_basic_block(function, "done");
+        builder.position_at_end(entry);
+
+        let pointer_type = context.ptr_type(AddressSpace::default());
+        let visitor_type = context.void_type().fn_type(
+            &[
+                pointer_type.into(),
+                context.i32_type().into(),
+                pointer_type.into(),
+            ],
+            false,
+        );
+        let visitor = function.get_nth_param(0).unwrap().into_pointer_value();
+        let data = function.get_nth_param(1).unwrap().into_pointer_value();
+
+        build_lock(context, module, &builder, type_store);
+        let locked_block = builder.get_insert_block().unwrap();
+        let slot_type = TypeValueProvider::new(context).llvm_type();
+        let slots = type_store.get_slots(&builder);
+        let capacity = type_store.get_capacity(&builder);
+        builder.build_unconditional_branch(loop_block).unwrap();
+
+        builder.position_at_end(loop_block);
+        let index = builder.build_phi(context.i32_type(), "index").unwrap();
+        let is_end = builder
+            .build_int_compare(
+                IntPredicate::UGE,
+                index.as_basic_value().into_int_value(),
+                capacity,
+                "is_end",
+            )
+            .unwrap();
+        builder
+            .build_conditional_branch(is_end, done_block, check_block)
+            .unwrap();
+
+        builder.position_at_end(check_block);
+        let slot_ptr = unsafe {
+            builder.build_gep(
+                slot_type,
+                slots,
+                &[index.as_basic_value().into_int_value()],
+                "slot",
+            )
+        }
+        .unwrap();
+        let slot = TypeValueProvider::new(context).opaque_pointer(slot_ptr);
+        let is_occupied = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                slot.get_state(&builder),
+                context.i8_type().const_int(u64::from(SLOT_OCCUPIED), false),
+                "is_occupied",
+            )
+            .unwrap();
+        builder
+            .build_conditional_branch(is_occupied, visit_block, next_block)
+            .unwrap();
+
+        builder.position_at_end(visit_block);
+        builder
+            .build_indirect_call(
+                visitor_type,
+                visitor,
+                &[
+                    data.into(),
+                    slot.get_id(&builder).into(),
+                    slot.get_type(&builder).into(),
+                ],
+                "visit",
+            )
+            .unwrap();
+        builder.build_unconditional_branch(next_block).unwrap();
+
+        builder.position_at_end(next_block);
+        let next_index = builder
+            .build_int_add(
+                index.as_basic_value().into_int_value(),
+                context.const_u32(1),
+                "next_index",
+            )
+            .unwrap();
+        builder.build_unconditional_branch(loop_block).unwrap();
+
+        index.add_incoming(&[
+            (&context.const_u32(0), locked_block),
+            (&next_index, next_block),
+        ]);
+
+        builder.position_at_end(done_block);
+        build_unlock(context, &builder, type_store);
+        builder.build_return(None).unwrap();
+    })
+}
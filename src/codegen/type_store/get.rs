@@ -1,45 +1,84 @@
-use super::{TypeStoreOpaquePointer, TypeValueProvider};
-use crate::codegen::{ContextErgonomics, module, types::values::Value};
+use inkwell::{AddressSpace, IntPredicate};
+
+use super::{
+    SLOT_OCCUPIED, TypeStoreOpaquePointer, TypeValueProvider,
+    lock::{build_lock, build_unlock},
+    probe::TypeStoreProbe,
+};
+use crate::codegen::{ContextErgonomics, context::Function as _, module, types::values::Value};
 
 make_function_type!(TypeStoreGet, (id: u64): *const Value);
 
+/// Looks the type up by its id, returning null if there's none.
 pub(super) fn make_get<'ctx>(
     module_builder: &module::ModuleBuilder<'ctx>,
     type_store: TypeStoreOpaquePointer<'ctx>,
+    probe: TypeStoreProbe<'ctx>,
 ) -> TypeStoreGet<'ctx> {
     module_builder.build_function::<_, _, TypeStoreGet>(|function, context, module| {
         let builder = context.create_builder();
         let entry = context.append_basic_block(function, "entry");
+        let lookup_block = context.append_basic_block(function, "lookup");
+        let found_block = context.append_basic_block(function, "found");
+        let absent_block = context.append_basic_block(function, "absent");
+        let missing_block = context.append_basic_block(function, "missing");
         builder.position_at_end(entry);
 
-        // TODO the types here should have some kinda array type, so we don't have to duck around
-        // with the GEP manually
-        let elements = type_store.get_types(&builder);
-        let element_type = TypeValueProvider::new(context).llvm_type();
-
-        let element_ptr = unsafe {
-            builder.build_gep(
-                element_type,
-                elements,
-                // TODO we should actually take the argument we got as the first arg and use it to
-                // access the right element based on that ID
-                &[context.const_u64(0)],
-                "element",
+        // The ids in the store are u32, so anything that doesn't fit is simply not found
+        let wide_id = function.get_first_param().unwrap().into_int_value();
+        let is_too_big = builder
+            .build_int_compare(
+                IntPredicate::UGT,
+                wide_id,
+                context.const_u64(u64::from(u32::MAX)),
+                "is_too_big",
             )
-        }
-        .unwrap();
-
-        let result = TypeValueProvider::new(context)
-            .opaque_pointer(element_ptr)
-            .get_type_ptr(&builder);
-
+            .unwrap();
         builder
-            .build_call(
-                module.get_function("debug_type_definition").unwrap(),
-                &[result.into()],
-                "type_definition_debug",
+            .build_conditional_branch(is_too_big, missing_block, lookup_block)
+            .unwrap();
+
+        builder.position_at_end(lookup_block);
+        let id = builder
+            .build_int_truncate(wide_id, context.i32_type(), "id")
+            .unwrap();
+        build_lock(context, module, &builder, type_store);
+        let slot_ptr = probe.build_call(
+            &builder,
+            (
+                type_store.get_slots(&builder),
+                type_store.get_capacity(&builder),
+                id,
+            ),
+        );
+        let slot = TypeValueProvider::new(context).opaque_pointer(slot_ptr);
+        let is_occupied = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                slot.get_state(&builder),
+                context.i8_type().const_int(u64::from(SLOT_OCCUPIED), false),
+                "is_occupied",
             )
             .unwrap();
+        builder
+            .build_conditional_branch(is_occupied, found_block, absent_block)
+            .unwrap();
+
+        builder.position_at_end(found_block);
+        let result = slot.get_type(&builder);
+        // The type itself never moves, so it can be used once the store is unlocked
+        build_unlock(context, &builder, type_store);
         builder.build_return(Some(&result)).unwrap();
+
+        builder.position_at_end(absent_block);
+        build_unlock(context, &builder, type_store);
+        builder.build_unconditional_branch(missing_block).unwrap();
+
+        builder.position_at_end(missing_block);
+        builder
+            .build_return(Some(
+                &context.ptr_type(AddressSpace::default()).const_null(),
+            ))
+            .unwrap();
     })
 }
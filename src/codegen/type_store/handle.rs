@@ -0,0 +1,193 @@
+use std::marker::PhantomData;
+
+use inkwell::execution_engine::{ExecutionEngine, UnsafeFunctionPointer};
+
+use super::{
+    add::TypeStoreAdd, allocate::TypeStoreAllocateId, assignable::TypeStoreIsAssignable,
+    dump::TypeStoreDump, for_each::TypeStoreForEach, get::TypeStoreGet, host::StoredType,
+    intern::TypeStoreIntern, remove::TypeStoreRemove, update::TypeStoreUpdate,
+};
+use crate::{
+    bytecode::TypeId,
+    codegen::{
+        CodeGenError,
+        context::{Function as _, Procedure as _},
+        types::values::Value,
+    },
+};
+
+type AddEntry = unsafe extern "C" fn(u32, *const Value) -> *const Value;
+type GetEntry = unsafe extern "C" fn(u64) -> *const Value;
+type AllocateIdEntry = unsafe extern "C" fn() -> u32;
+type InternEntry = unsafe extern "C" fn(*const Value) -> u32;
+type UpdateEntry = unsafe extern "C" fn(u32, *const Value) -> u8;
+type RemoveEntry = unsafe extern "C" fn(u32) -> u8;
+type IsAssignableEntry = unsafe extern "C" fn(u32, u32) -> u8;
+type Visitor = extern "C" fn(*mut Vec<(TypeId, StoredType)>, u32, *const Value);
+type ForEachEntry = unsafe extern "C" fn(Visitor, *mut Vec<(TypeId, StoredType)>);
+
+/// The type store of a session, for the host to use from any number of threads at once.
+///
+/// The functions of the store hold its lock while they run. The handle can't outlive the session,
+/// which tears the store down.
+#[derive(Clone, Copy)]
+pub struct TypeStoreHandle<'session> {
+    add: AddEntry,
+    get: GetEntry,
+    allocate_id: AllocateIdEntry,
+    intern: InternEntry,
+    update: UpdateEntry,
+    remove: RemoveEntry,
+    is_assignable: IsAssignableEntry,
+    for_each: ForEachEntry,
+    session: PhantomData<&'session ()>,
+}
+
+impl<'session> TypeStoreHandle<'session> {
+    /// Looks the functions of the store up in the execution engine the runtime modules were added
+    /// to.
+    pub(in crate::codegen) fn new(
+        execution_engine: &'session ExecutionEngine<'_>,
+    ) -> Result<Self, CodeGenError> {
+        fn function<F: UnsafeFunctionPointer>(
+            execution_engine: &ExecutionEngine<'_>,
+            symbol: &str,
+        ) -> Result<F, CodeGenError> {
+            let function = unsafe { execution_engine.get_function::<F>(symbol) }
+                .map_err(|error| CodeGenError::ExecutionEngine(error.to_string()))?;
+
+            // The execution engine outlives the handle, so the code stays where it is
+            Ok(unsafe { function.into_raw() })
+        }
+
+        Ok(Self {
+            add: function(execution_engine, &TypeStoreAdd::symbol())?,
+            get: function(execution_engine, &TypeStoreGet::symbol())?,
+            allocate_id: function(execution_engine, &TypeStoreAllocateId::symbol())?,
+            intern: function(execution_engine, &TypeStoreIntern::symbol())?,
+            update: function(execution_engine, &TypeStoreUpdate::symbol())?,
+            remove: function(execution_engine, &TypeStoreRemove::symbol())?,
+            is_assignable: function(execution_engine, &TypeStoreIsAssignable::symbol())?,
+            for_each: function(execution_engine, &TypeStoreForEach::symbol())?,
+            session: PhantomData,
+        })
+    }
+
+    /// Registers the type under the id, see `Session::add_type`.
+    ///
+    /// # Errors
+    ///
+    /// If the id is one of the `TypeTag`s, or there already is a type with the id, which is kept
+    /// as it is.
+    pub fn add_type(&self, id: TypeId, r#type: StoredType) -> Result<(), CodeGenError> {
+        check_not_reserved(id)?;
+
+        if unsafe { (self.add)(id.as_u32(), r#type.into_raw()) }.is_null() {
+            Ok(())
+        } else {
+            Err(CodeGenError::DuplicateType(id))
+        }
+    }
+
+    /// Looks the type up by its id.
+    ///
+    /// # Errors
+    ///
+    /// If the type has a tag the host doesn't know about.
+    pub fn get_type(&self, id: TypeId) -> Result<Option<StoredType>, CodeGenError> {
+        unsafe { StoredType::from_raw(self.get_raw_type(id)) }
+            .map_err(|tag| CodeGenError::UnknownTypeTag { id, tag })
+    }
+
+    /// The value the store keeps for the type, null if there's none.
+    pub(in crate::codegen) fn get_raw_type(&self, id: TypeId) -> *const Value {
+        unsafe { (self.get)(u64::from(id.as_u32())) }
+    }
+
+    /// Hands out a fresh id, see `Session::allocate_type_id`.
+    #[must_use]
+    pub fn allocate_type_id(&self) -> TypeId {
+        TypeId::from_raw(unsafe { (self.allocate_id)() })
+    }
+
+    /// Returns the id of the type, adding it if it's new, see `Session::intern_type`.
+    #[must_use]
+    pub fn intern_type(&self, r#type: StoredType) -> TypeId {
+        self.intern_raw_type(r#type.into_raw())
+    }
+
+    pub(in crate::codegen) fn intern_raw_type(&self, r#type: *const Value) -> TypeId {
+        TypeId::from_raw(unsafe { (self.intern)(r#type) })
+    }
+
+    /// Replaces the type with the id.
+    ///
+    /// # Errors
+    ///
+    /// If the id is one of the `TypeTag`s, or there's no type with the id, in which case it's not
+    /// added either.
+    pub fn update_type(&self, id: TypeId, r#type: StoredType) -> Result<(), CodeGenError> {
+        check_not_reserved(id)?;
+
+        if unsafe { (self.update)(id.as_u32(), r#type.into_raw()) } == 0 {
+            Err(CodeGenError::UndefinedType(id))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Removes the type with the id.
+    ///
+    /// # Errors
+    ///
+    /// If the id is one of the `TypeTag`s, or there's no type with the id.
+    pub fn remove_type(&self, id: TypeId) -> Result<(), CodeGenError> {
+        check_not_reserved(id)?;
+
+        if unsafe { (self.remove)(id.as_u32()) } == 0 {
+            Err(CodeGenError::UndefinedType(id))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// See `Session::is_assignable`.
+    #[must_use]
+    pub fn is_assignable(&self, from: TypeId, to: TypeId) -> bool {
+        unsafe { (self.is_assignable)(from.as_u32(), to.as_u32()) != 0 }
+    }
+
+    /// All the types in the store, ordered by their ids, see `Session::types`.
+    #[must_use]
+    pub fn types(&self) -> Vec<(TypeId, StoredType)> {
+        extern "C" fn visit(types: *mut Vec<(TypeId, StoredType)>, id: u32, r#type: *const Value) {
+            // Anything the host can't decode is left out
+            if let Ok(Some(r#type)) = unsafe { StoredType::from_raw(r#type) } {
+                unsafe { &mut *types }.push((TypeId::from_raw(id), r#type));
+            }
+        }
+
+        let mut types = vec![];
+        unsafe { (self.for_each)(visit, &raw mut types) };
+        types.sort_by_key(|(id, _)| id.as_u32());
+
+        types
+    }
+
+    /// The types in the store with their names resolved, for debugging. It can be taken while
+    /// other threads are running, as it's read with the store locked.
+    #[must_use]
+    pub fn dump(&self) -> TypeStoreDump {
+        TypeStoreDump::new(self.types())
+    }
+}
+
+/// The ids of the `TypeTag`s stand for the primitives wherever they're looked up, so a type stored
+/// under one of them would never be seen.
+const fn check_not_reserved(id: TypeId) -> Result<(), CodeGenError> {
+    if id.as_u32() < TypeId::RESERVED {
+        Err(CodeGenError::ReservedType(id))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{
+    bytecode::{Identifier, TypeId, TypeTag},
+    codegen::{
+        llvm_struct::raw_array::RawConstArray,
+        types::{
+            classes::ClassId,
+            functions::{FunctionArgument, FunctionSignature},
+            values::Value,
+        },
+    },
+};
+
+/// A type in the type store, as the host sees it. The class restricts the values of the type to
+/// the ones of the class, unless it's none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoredType {
+    Primitive {
+        tag: TypeTag,
+        class_id: ClassId,
+    },
+    FunctionSignature {
+        class_id: ClassId,
+        arguments: Vec<(Identifier, TypeId)>,
+        return_type: TypeId,
+    },
+}
+
+impl StoredType {
+    /// A primitive with no class, the same as the id of its tag stands for.
+    #[must_use]
+    pub const fn primitive(tag: TypeTag) -> Self {
+        Self::Primitive {
+            tag,
+            class_id: ClassId::none(),
+        }
+    }
+
+    /// Builds the runtime value the type store keeps for the type. The store keeps pointing at
+    /// it, so it's never freed. The class of a signature is put both on its value and on the
+    /// signature itself.
+    pub(in crate::codegen) fn into_raw(self) -> *const Value {
+        let (tag, class_id, raw) = match self {
+            Self::Primitive { tag, class_id } => (tag, class_id, 0),
+            Self::FunctionSignature {
+                class_id,
+                arguments,
+                return_type,
+            } => {
+                let arguments: Box<[_]> = arguments
+                    .into_iter()
+                    .map(|(name, type_id)| FunctionArgument { name, type_id })
+                    .collect();
+
+                let signature = Box::leak(Box::new(FunctionSignature {
+                    class_id,
+                    argument_count: u16::try_from(arguments.len()).unwrap(),
+                    return_type_id: return_type,
+                    arguments: RawConstArray::new(Box::leak(arguments).as_ptr()),
+                }));
+
+                (
+                    TypeTag::FunctionSignature,
+                    class_id,
+                    std::ptr::from_ref(signature) as u64,
+                )
+            }
+        };
+
+        Box::leak(Box::new(Value {
+            tag,
+            unused_0: 0,
+            class_id,
+            unused_1: 0,
+            raw,
+        }))
+    }
+
+    /// Whether a value of the type `from` can be used where the type `to` is expected, the same
+    /// as `TypeStoreIsAssignable` checks at runtime, for the code that only has the types the
+    /// `lookup` finds (which doesn't have to include the primitives, the ids of the `TypeTag`s
+    /// stand for them). Unknown types aren't assignable to anything but themselves.
+    #[must_use]
+    pub fn is_assignable(
+        from: TypeId,
+        to: TypeId,
+        lookup: &impl Fn(TypeId) -> Option<Self>,
+    ) -> bool {
+        Self::check_assignable(from, to, lookup, &mut vec![])
+    }
+
+    /// The type-level half of `is_assignable`, with the types the signatures refer to looked up
+    /// by the `lookup`. The arguments are checked the other way around than the return types, as
+    /// the function gets called with the arguments meant for `to`, and their names don't matter.
+    #[must_use]
+    pub fn is_assignable_to(&self, to: &Self, lookup: &impl Fn(TypeId) -> Option<Self>) -> bool {
+        self.check_assignable_to(to, lookup, &mut vec![])
+    }
+
+    /// `assumed` holds the pairs of types that are being checked further up, which are taken to
+    /// be assignable, so that the types referring to each other in a cycle don't recurse forever.
+    fn check_assignable(
+        from: TypeId,
+        to: TypeId,
+        lookup: &impl Fn(TypeId) -> Option<Self>,
+        assumed: &mut Vec<(TypeId, TypeId)>,
+    ) -> bool {
+        if from == to || assumed.contains(&(from, to)) {
+            return true;
+        }
+
+        match (Self::resolve(from, lookup), Self::resolve(to, lookup)) {
+            (Some(from_type), Some(to_type)) => {
+                assumed.push((from, to));
+                let is_assignable = from_type.check_assignable_to(&to_type, lookup, assumed);
+                assumed.pop();
+
+                is_assignable
+            }
+            _ => false,
+        }
+    }
+
+    fn check_assignable_to(
+        &self,
+        to: &Self,
+        lookup: &impl Fn(TypeId) -> Option<Self>,
+        assumed: &mut Vec<(TypeId, TypeId)>,
+    ) -> bool {
+        match (self, to) {
+            (
+                Self::Primitive {
+                    tag: from_tag,
+                    class_id: from_class_id,
+                },
+                Self::Primitive {
+                    tag: to_tag,
+                    class_id: to_class_id,
+                },
+            ) => from_tag == to_tag && from_class_id.is_accepted_by(*to_class_id),
+            (
+                Self::FunctionSignature {
+                    class_id: from_class_id,
+                    arguments: from_arguments,
+                    return_type: from_return_type,
+                },
+                Self::FunctionSignature {
+                    class_id: to_class_id,
+                    arguments: to_arguments,
+                    return_type: to_return_type,
+                },
+            ) => {
+                from_class_id.is_accepted_by(*to_class_id)
+                    && from_arguments.len() == to_arguments.len()
+                    && from_arguments.iter().zip(to_arguments).all(
+                        |((_, from_argument), (_, to_argument))| {
+                            Self::check_assignable(*to_argument, *from_argument, lookup, assumed)
+                        },
+                    )
+                    && Self::check_assignable(*from_return_type, *to_return_type, lookup, assumed)
+            }
+            _ => false,
+        }
+    }
+
+    fn resolve(id: TypeId, lookup: &impl Fn(TypeId) -> Option<Self>) -> Option<Self> {
+        if id.as_u32() < TypeId::RESERVED {
+            u8::try_from(id.as_u32())
+                .ok()
+                .and_then(TypeTag::from_value)
+                .map(Self::primitive)
+        } else {
+            lookup(id)
+        }
+    }
+
+    /// Reads the type back from the value the type store returned, `None` if it's null. A type
+    /// with a tag the host doesn't know about can't be read, the error is its raw tag.
+    ///
+    /// # Safety
+    ///
+    /// The value has to come from the type store, or be null.
+    pub(in crate::codegen) unsafe fn from_raw(value: *const Value) -> Result<Option<Self>, u8> {
+        if value.is_null() {
+            return Ok(None);
+        }
+
+        // Read as a byte first, the tag might be one the host doesn't know about
+        let raw_tag = unsafe { *value.cast::<u8>() };
+        let tag = TypeTag::from_value(raw_tag).ok_or(raw_tag)?;
+        if !matches!(tag, TypeTag::FunctionSignature) {
+            return Ok(Some(Self::Primitive {
+                tag,
+                class_id: unsafe { (*value).class_id },
+            }));
+        }
+
+        let signature = unsafe { &*((*value).raw as *const FunctionSignature) };
+        let arguments = unsafe {
+            signature
+                .arguments
+                .iter(usize::from(signature.argument_count))
+        }
+        .map(|argument| (argument.name, argument.type_id))
+        .collect();
+
+        Ok(Some(Self::FunctionSignature {
+            class_id: signature.class_id,
+            arguments,
+            return_type: signature.return_type_id,
+        }))
+    }
+}
@@ -0,0 +1,587 @@
+use inkwell::{
+    IntPredicate,
+    basic_block::BasicBlock,
+    builder::Builder,
+    context::Context,
+    values::{IntValue, PointerValue},
+};
+
+use super::{
+    IndexedTypeOpaque, IndexedTypeProvider, SLOT_EMPTY, SLOT_OCCUPIED, TypeStoreOpaquePointer,
+    TypeValueProvider,
+    equal::{TypeStoreTypesEqual, build_raw_pointer},
+    initializer::build_zeroed_malloc,
+    probe::{TypeStoreProbe, build_hash},
+};
+use crate::{
+    bytecode::TypeTag,
+    codegen::{
+        ContextErgonomics,
+        context::Function as _,
+        llvm_struct::representations::ConstOrValue,
+        module,
+        types::{
+            functions::{FunctionArgumentProvider, FunctionSignatureProvider},
+            values::{Value, ValueProvider},
+        },
+    },
+};
+
+make_function_type!(TypeStoreHashType, (value: *const Value): u32);
+make_function_type!(TypeStoreIndexType, (id: u32, value: *const Value));
+make_function_type!(TypeStoreFindEqual, (value: *const Value): u32);
+
+// FNV-1a, over the parts of the types that `equal` compares
+const HASH_OFFSET: u32 = 0x811C_9DC5;
+const HASH_PRIME: u32 = 0x0100_0193;
+
+/// Hashes the type by its structure, so that the types `TypeStoreTypesEqual` finds equal always
+/// get the same hash.
+pub(super) fn make_hash_type<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+) -> TypeStoreHashType<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreHashType>(|function, context, _module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let raw_block = context.append_basic_block(function, "raw");
+        let signature_block = context.append_basic_block(function, "signature");
+        let done_block = context.append_basic_block(function, "done");
+        builder.position_at_end(entry);
+
+        let value = ValueProvider::new(context)
+            .opaque_pointer(function.get_first_param().unwrap().into_pointer_value());
+        let hash = build_mix(
+            context,
+            &builder,
+            context.const_u32(HASH_OFFSET),
+            &[value.get_tag(&builder), value.get_class_id(&builder)],
+        );
+        let is_signature = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                value.get_tag(&builder),
+                context
+                    .i8_type()
+                    .const_int(TypeTag::FunctionSignature as u64, false),
+                "is_signature",
+            )
+            .unwrap();
+        builder
+            .build_conditional_branch(is_signature, signature_block, raw_block)
+            .unwrap();
+
+        builder.position_at_end(raw_block);
+        let raw = value.get_raw(&builder);
+        let high_raw = builder
+            .build_right_shift(raw, context.const_u64(32), false, "high_raw")
+            .unwrap();
+        let raw_hash = build_mix(context, &builder, hash, &[raw, high_raw]);
+        builder.build_unconditional_branch(done_block).unwrap();
+
+        builder.position_at_end(signature_block);
+        let signature_hash = build_hash_signature(context, &builder, value.get_raw(&builder), hash);
+        let signature_end = builder.get_insert_block().unwrap();
+        builder.build_unconditional_branch(done_block).unwrap();
+
+        builder.position_at_end(done_block);
+        let result = builder.build_phi(context.i32_type(), "result").unwrap();
+        result.add_incoming(&[(&raw_hash, raw_block), (&signature_hash, signature_end)]);
+        let result = build_hash(context, &builder, result.as_basic_value().into_int_value());
+        builder.build_return(Some(&result)).unwrap();
+    })
+}
+
+/// Adds the type stored under the id to the index, which has to be done whenever a type gets
+/// stored. The entries of the types that get replaced or removed stay behind, which is harmless,
+/// as `find_equal` only trusts the types in the table, and they're dropped whenever the index gets
+/// rebuilt.
+///
+/// The index is rebuilt from the table instead of growing, once it would get more than 3/4 full,
+/// as anything stored is in the table already.
+pub(super) fn make_index_type<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    hash_type: TypeStoreHashType<'ctx>,
+) -> TypeStoreIndexType<'ctx> {
+    module_builder.build_procedure::<_, TypeStoreIndexType>(|function, context, _module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let rebuild_block = context.append_basic_block(function, "rebuild");
+        let add_block = context.append_basic_block(function, "add");
+        builder.position_at_end(entry);
+
+        let count = type_store.get_index_count(&builder);
+        let capacity = type_store.get_index_capacity(&builder);
+        let new_count = builder
+            .build_int_add(count, context.const_u32(1), "new_count")
+            .unwrap();
+        let load = builder
+            .build_int_mul(new_count, context.const_u32(4), "load")
+            .unwrap();
+        let max_load = builder
+            .build_int_mul(capacity, context.const_u32(3), "max_load")
+            .unwrap();
+        let is_too_full = builder
+            .build_int_compare(IntPredicate::UGT, load, max_load, "is_too_full")
+            .unwrap();
+        builder
+            .build_conditional_branch(is_too_full, rebuild_block, add_block)
+            .unwrap();
+
+        builder.position_at_end(rebuild_block);
+        build_rebuild(context, &builder, type_store, hash_type);
+        builder.build_return(None).unwrap();
+
+        builder.position_at_end(add_block);
+        let hash = hash_type.build_call(
+            &builder,
+            function.get_nth_param(1).unwrap().into_pointer_value(),
+        );
+        build_add_entry(
+            context,
+            &builder,
+            (type_store.get_index(&builder), capacity),
+            (hash, function.get_first_param().unwrap().into_int_value()),
+        );
+        builder
+            .build_store(type_store.get_index_count_ptr(&builder), new_count)
+            .unwrap();
+        builder.build_return(None).unwrap();
+    })
+}
+
+/// Returns the id of a type in the store that's structurally equal to the value, or 0 if there's
+/// none, as no type is ever stored under it.
+pub(super) fn make_find_equal<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    (probe, hash_type, types_equal): (
+        TypeStoreProbe<'ctx>,
+        TypeStoreHashType<'ctx>,
+        TypeStoreTypesEqual<'ctx>,
+    ),
+) -> TypeStoreFindEqual<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreFindEqual>(|function, context, _module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let search_block = context.append_basic_block(function, "search");
+        let loop_block = context.append_basic_block(function, "loop");
+        let check_hash_block = context.append_basic_block(function, "check_hash");
+        let check_stored_block = context.append_basic_block(function, "check_stored");
+        let next_block = context.append_basic_block(function, "next");
+        let found_block = context.append_basic_block(function, "found");
+        let missing_block = context.append_basic_block(function, "missing");
+        builder.position_at_end(entry);
+
+        let value = function.get_first_param().unwrap().into_pointer_value();
+        let index = type_store.get_index(&builder);
+        let capacity = type_store.get_index_capacity(&builder);
+        // There's no index until the first type gets stored
+        let is_unindexed = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                capacity,
+                context.const_u32(0),
+                "is_unindexed",
+            )
+            .unwrap();
+        builder
+            .build_conditional_branch(is_unindexed, missing_block, search_block)
+            .unwrap();
+
+        builder.position_at_end(search_block);
+        let hash = hash_type.build_call(&builder, value);
+        let mask = builder
+            .build_int_sub(capacity, context.const_u32(1), "mask")
+            .unwrap();
+        let start = builder.build_and(hash, mask, "start").unwrap();
+        builder.build_unconditional_branch(loop_block).unwrap();
+
+        builder.position_at_end(loop_block);
+        let position = builder.build_phi(context.i32_type(), "position").unwrap();
+        let position_value = position.as_basic_value().into_int_value();
+        let entry_provider = IndexedTypeProvider::new(context);
+        let indexed = entry_provider.opaque_pointer(
+            unsafe {
+                builder.build_gep(
+                    entry_provider.llvm_type(),
+                    index,
+                    &[position_value],
+                    "indexed",
+                )
+            }
+            .unwrap(),
+        );
+        let is_empty = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                indexed.get_state(&builder),
+                context.i8_type().const_int(u64::from(SLOT_EMPTY), false),
+                "is_empty",
+            )
+            .unwrap();
+        builder
+            .build_conditional_branch(is_empty, missing_block, check_hash_block)
+            .unwrap();
+
+        builder.position_at_end(check_hash_block);
+        let is_same_hash = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                indexed.get_hash(&builder),
+                hash,
+                "is_same_hash",
+            )
+            .unwrap();
+        builder
+            .build_conditional_branch(is_same_hash, check_stored_block, next_block)
+            .unwrap();
+
+        // The entry might be left behind by a type that's been replaced or removed since
+        builder.position_at_end(check_stored_block);
+        let id = indexed.get_id(&builder);
+        build_check_stored(
+            context,
+            &builder,
+            type_store,
+            (probe, types_equal),
+            (id, value),
+            (found_block, next_block),
+        );
+
+        builder.position_at_end(next_block);
+        let next_position = builder
+            .build_int_add(position_value, context.const_u32(1), "next_position")
+            .unwrap();
+        let next_position = builder
+            .build_and(next_position, mask, "wrapped_position")
+            .unwrap();
+        builder.build_unconditional_branch(loop_block).unwrap();
+
+        position.add_incoming(&[(&start, search_block), (&next_position, next_block)]);
+
+        builder.position_at_end(found_block);
+        builder.build_return(Some(&id)).unwrap();
+
+        builder.position_at_end(missing_block);
+        builder.build_return(Some(&context.const_u32(0))).unwrap();
+    })
+}
+
+/// Branches to the found block if the type stored under the id is equal to the value, and to the
+/// next block if it's not, or there's none.
+fn build_check_stored<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    (probe, types_equal): (TypeStoreProbe<'ctx>, TypeStoreTypesEqual<'ctx>),
+    (id, value): (IntValue<'ctx>, PointerValue<'ctx>),
+    (found_block, next_block): (BasicBlock<'ctx>, BasicBlock<'ctx>),
+) {
+    let function = builder.get_insert_block().unwrap().get_parent().unwrap();
+    let compare_block = context.append_basic_block(function, "compare");
+
+    let slot = TypeValueProvider::new(context).opaque_pointer(probe.build_call(
+        builder,
+        (
+            type_store.get_slots(builder),
+            type_store.get_capacity(builder),
+            id,
+        ),
+    ));
+    let is_occupied = builder
+        .build_int_compare(
+            IntPredicate::EQ,
+            slot.get_state(builder),
+            context.i8_type().const_int(u64::from(SLOT_OCCUPIED), false),
+            "is_occupied",
+        )
+        .unwrap();
+    builder
+        .build_conditional_branch(is_occupied, compare_block, next_block)
+        .unwrap();
+
+    builder.position_at_end(compare_block);
+    let is_equal = types_equal.build_call(builder, (slot.get_type(builder), value));
+    let is_equal = builder
+        .build_int_compare(
+            IntPredicate::NE,
+            is_equal,
+            context.i8_type().const_zero(),
+            "is_equal",
+        )
+        .unwrap();
+    builder
+        .build_conditional_branch(is_equal, found_block, next_block)
+        .unwrap();
+}
+
+/// Hashes the signature the raw value points to, leaving the builder after the loop over its
+/// arguments.
+fn build_hash_signature<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    raw: IntValue<'ctx>,
+    hash: IntValue<'ctx>,
+) -> IntValue<'ctx> {
+    let signature_block = builder.get_insert_block().unwrap();
+    let function = signature_block.get_parent().unwrap();
+    let arguments_loop = context.append_basic_block(function, "arguments");
+    let argument_block = context.append_basic_block(function, "argument");
+    let arguments_done = context.append_basic_block(function, "arguments_done");
+
+    let signature = FunctionSignatureProvider::new(context)
+        .opaque_pointer(build_raw_pointer(context, builder, raw));
+    let signature_hash = build_mix(
+        context,
+        builder,
+        hash,
+        &[
+            signature.get_class_id(builder),
+            signature.get_argument_count(builder),
+            signature.get_return_type_id(builder),
+        ],
+    );
+    let argument_count = builder
+        .build_int_z_extend(
+            signature.get_argument_count(builder),
+            context.i32_type(),
+            "argument_count",
+        )
+        .unwrap();
+    let arguments = signature.get_arguments(builder);
+    builder.build_unconditional_branch(arguments_loop).unwrap();
+
+    builder.position_at_end(arguments_loop);
+    let index = builder.build_phi(context.i32_type(), "index").unwrap();
+    let index_value = index.as_basic_value().into_int_value();
+    let arguments_hash = builder
+        .build_phi(context.i32_type(), "arguments_hash")
+        .unwrap();
+    let arguments_hash_value = arguments_hash.as_basic_value().into_int_value();
+    let is_end = builder
+        .build_int_compare(IntPredicate::UGE, index_value, argument_count, "is_end")
+        .unwrap();
+    builder
+        .build_conditional_branch(is_end, arguments_done, argument_block)
+        .unwrap();
+
+    builder.position_at_end(argument_block);
+    let argument_provider = FunctionArgumentProvider::new(context);
+    let argument = argument_provider.opaque_pointer(
+        unsafe {
+            builder.build_gep(
+                argument_provider.llvm_type(),
+                arguments,
+                &[index_value],
+                "argument",
+            )
+        }
+        .unwrap(),
+    );
+    let hash_with_argument = build_mix(
+        context,
+        builder,
+        arguments_hash_value,
+        &[argument.get_name(builder), argument.get_type_id(builder)],
+    );
+    let next_index = builder
+        .build_int_add(index_value, context.const_u32(1), "next_index")
+        .unwrap();
+    builder.build_unconditional_branch(arguments_loop).unwrap();
+
+    index.add_incoming(&[
+        (&context.const_u32(0), signature_block),
+        (&next_index, argument_block),
+    ]);
+    arguments_hash.add_incoming(&[
+        (&signature_hash, signature_block),
+        (&hash_with_argument, argument_block),
+    ]);
+
+    builder.position_at_end(arguments_done);
+
+    arguments_hash_value
+}
+
+/// Folds the words into the hash, the narrower ones get zero extended.
+fn build_mix<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    hash: IntValue<'ctx>,
+    words: &[IntValue<'ctx>],
+) -> IntValue<'ctx> {
+    words.iter().fold(hash, |hash, word| {
+        let word = builder
+            .build_int_cast_sign_flag(*word, context.i32_type(), false, "word")
+            .unwrap();
+        let hash = builder.build_xor(hash, word, "hash").unwrap();
+
+        builder
+            .build_int_mul(hash, context.const_u32(HASH_PRIME), "hash")
+            .unwrap()
+    })
+}
+
+/// Puts the entry into the first empty slot from where the hash points, there's always one as the
+/// index is never full.
+fn build_add_entry<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    (index, capacity): (PointerValue<'ctx>, IntValue<'ctx>),
+    (hash, id): (IntValue<'ctx>, IntValue<'ctx>),
+) {
+    let start_block = builder.get_insert_block().unwrap();
+    let function = start_block.get_parent().unwrap();
+    let loop_block = context.append_basic_block(function, "add_entry");
+    let next_block = context.append_basic_block(function, "add_entry_next");
+    let fill_block = context.append_basic_block(function, "add_entry_fill");
+
+    let mask = builder
+        .build_int_sub(capacity, context.const_u32(1), "mask")
+        .unwrap();
+    let start = builder.build_and(hash, mask, "start").unwrap();
+    builder.build_unconditional_branch(loop_block).unwrap();
+
+    builder.position_at_end(loop_block);
+    let position = builder.build_phi(context.i32_type(), "position").unwrap();
+    let position_value = position.as_basic_value().into_int_value();
+    let entry_provider = IndexedTypeProvider::new(context);
+    let entry_ptr = unsafe {
+        builder.build_gep(
+            entry_provider.llvm_type(),
+            index,
+            &[position_value],
+            "entry",
+        )
+    }
+    .unwrap();
+    let is_empty = builder
+        .build_int_compare(
+            IntPredicate::EQ,
+            entry_provider.opaque_pointer(entry_ptr).get_state(builder),
+            context.i8_type().const_int(u64::from(SLOT_EMPTY), false),
+            "is_empty",
+        )
+        .unwrap();
+    builder
+        .build_conditional_branch(is_empty, fill_block, next_block)
+        .unwrap();
+
+    builder.position_at_end(next_block);
+    let next_position = builder
+        .build_int_add(position_value, context.const_u32(1), "next_position")
+        .unwrap();
+    let next_position = builder
+        .build_and(next_position, mask, "wrapped_position")
+        .unwrap();
+    builder.build_unconditional_branch(loop_block).unwrap();
+
+    position.add_incoming(&[(&start, start_block), (&next_position, next_block)]);
+
+    builder.position_at_end(fill_block);
+    entry_provider.fill_in(
+        entry_ptr,
+        builder,
+        IndexedTypeOpaque {
+            state: ConstOrValue::Const(SLOT_OCCUPIED),
+            hash: ConstOrValue::Value(hash),
+            id: ConstOrValue::Value(id),
+        },
+    );
+}
+
+/// Replaces the index with one of all the types in the table, twice as big as the table, so that
+/// it has room for as many updates before it's rebuilt again.
+fn build_rebuild<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    hash_type: TypeStoreHashType<'ctx>,
+) {
+    let rebuild_block = builder.get_insert_block().unwrap();
+    let function = rebuild_block.get_parent().unwrap();
+    let loop_block = context.append_basic_block(function, "reindex");
+    let check_block = context.append_basic_block(function, "reindex_check");
+    let add_block = context.append_basic_block(function, "reindex_add");
+    let next_block = context.append_basic_block(function, "reindex_next");
+    let done_block = context.append_basic_block(function, "reindexed");
+
+    let slots = type_store.get_slots(builder);
+    let slots_capacity = type_store.get_capacity(builder);
+    let capacity = builder
+        .build_int_mul(slots_capacity, context.const_u32(2), "index_capacity")
+        .unwrap();
+    let index = build_zeroed_malloc(
+        context,
+        builder,
+        IndexedTypeProvider::new(context).llvm_type(),
+        capacity,
+    );
+    builder.build_unconditional_branch(loop_block).unwrap();
+
+    builder.position_at_end(loop_block);
+    let position = builder.build_phi(context.i32_type(), "position").unwrap();
+    let position_value = position.as_basic_value().into_int_value();
+    let is_end = builder
+        .build_int_compare(IntPredicate::UGE, position_value, slots_capacity, "is_end")
+        .unwrap();
+    builder
+        .build_conditional_branch(is_end, done_block, check_block)
+        .unwrap();
+
+    builder.position_at_end(check_block);
+    let slot_provider = TypeValueProvider::new(context);
+    let slot = slot_provider.opaque_pointer(
+        unsafe { builder.build_gep(slot_provider.llvm_type(), slots, &[position_value], "slot") }
+            .unwrap(),
+    );
+    let is_occupied = builder
+        .build_int_compare(
+            IntPredicate::EQ,
+            slot.get_state(builder),
+            context.i8_type().const_int(u64::from(SLOT_OCCUPIED), false),
+            "is_occupied",
+        )
+        .unwrap();
+    builder
+        .build_conditional_branch(is_occupied, add_block, next_block)
+        .unwrap();
+
+    builder.position_at_end(add_block);
+    let hash = hash_type.build_call(builder, slot.get_type(builder));
+    build_add_entry(
+        context,
+        builder,
+        (index, capacity),
+        (hash, slot.get_id(builder)),
+    );
+    builder.build_unconditional_branch(next_block).unwrap();
+
+    builder.position_at_end(next_block);
+    let next_position = builder
+        .build_int_add(position_value, context.const_u32(1), "next_position")
+        .unwrap();
+    builder.build_unconditional_branch(loop_block).unwrap();
+
+    position.add_incoming(&[
+        (&context.const_u32(0), rebuild_block),
+        (&next_position, next_block),
+    ]);
+
+    builder.position_at_end(done_block);
+    // Freeing null does nothing, so there doesn't have to be an index yet
+    builder.build_free(type_store.get_index(builder)).unwrap();
+    builder
+        .build_store(type_store.get_index_ptr(builder), index)
+        .unwrap();
+    builder
+        .build_store(type_store.get_index_capacity_ptr(builder), capacity)
+        .unwrap();
+    builder
+        .build_store(
+            type_store.get_index_count_ptr(builder),
+            type_store.get_count(builder),
+        )
+        .unwrap();
+}
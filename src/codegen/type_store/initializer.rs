@@ -1,10 +1,19 @@
-use inkwell::values::PointerValue;
+use inkwell::{
+    AddressSpace,
+    builder::Builder,
+    context::Context,
+    types::StructType,
+    values::{IntValue, PointerValue},
+};
 
-use super::{TypeStoreProvider, TypeValueProvider};
-use crate::codegen::{
-    ContextErgonomics as _,
-    llvm_struct::representations::ConstOrValue,
-    module::{self, GlobalConstructorFunction},
+use super::{INITIAL_CAPACITY, TypeStoreProvider, TypeValueProvider};
+use crate::{
+    bytecode::TypeId,
+    codegen::{
+        ContextErgonomics as _,
+        llvm_struct::representations::ConstOrValue,
+        module::{self, GlobalConstructorFunction},
+    },
 };
 
 pub(super) fn make_type_store_initializer<'ctx>(
@@ -16,24 +25,60 @@ pub(super) fn make_type_store_initializer<'ctx>(
         let builder = context.create_builder();
         builder.position_at_end(entry);
 
-        // TODO we should be using make_value from TypeValueProvider here
-        let types = builder
-            .build_array_malloc(
-                TypeValueProvider::new(context).llvm_type(),
-                context.const_u32(1),
-                "types",
-            )
-            .unwrap();
+        let slots = build_slots_malloc(context, &builder, context.const_u32(INITIAL_CAPACITY));
 
         TypeStoreProvider::new(context).fill_in(
             type_store,
             &builder,
             super::TypeStoreOpaque {
-                types: ConstOrValue::Value(types),
-                length: ConstOrValue::Const(0),
-                capacity: ConstOrValue::Const(1),
+                slots: ConstOrValue::Value(slots),
+                count: ConstOrValue::Const(0),
+                capacity: ConstOrValue::Const(INITIAL_CAPACITY),
+                next_id: ConstOrValue::Const(TypeId::RESERVED),
+                lock: ConstOrValue::Const(0),
+                index: ConstOrValue::Value(context.ptr_type(AddressSpace::default()).const_null()),
+                index_count: ConstOrValue::Const(0),
+                index_capacity: ConstOrValue::Const(0),
             },
         );
         builder.build_return(None).unwrap();
     })
 }
+
+/// Allocates the slots of the table, all of them empty.
+pub(super) fn build_slots_malloc<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    capacity: IntValue<'ctx>,
+) -> PointerValue<'ctx> {
+    build_zeroed_malloc(
+        context,
+        builder,
+        TypeValueProvider::new(context).llvm_type(),
+        capacity,
+    )
+}
+
+/// Allocates an array of the slot type, zeroed, which is how the tables mark their slots empty.
+pub(super) fn build_zeroed_malloc<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    slot_type: StructType<'ctx>,
+    capacity: IntValue<'ctx>,
+) -> PointerValue<'ctx> {
+    let slots = builder
+        .build_array_malloc(slot_type, capacity, "slots")
+        .unwrap();
+
+    let capacity = builder
+        .build_int_z_extend(capacity, context.i64_type(), "capacity")
+        .unwrap();
+    let size = builder
+        .build_int_mul(slot_type.size_of().unwrap(), capacity, "slots_size")
+        .unwrap();
+    builder
+        .build_memset(slots, 1, context.i8_type().const_zero(), size)
+        .unwrap();
+
+    slots
+}
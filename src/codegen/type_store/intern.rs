@@ -0,0 +1,57 @@
+use inkwell::IntPredicate;
+
+use super::{
+    TypeStoreOpaquePointer,
+    add::TypeStoreInsert,
+    allocate::TypeStoreNextId,
+    index::TypeStoreFindEqual,
+    lock::{build_lock, build_unlock},
+};
+use crate::codegen::{ContextErgonomics, context::Function as _, module, types::values::Value};
+
+make_function_type!(TypeStoreIntern, (value: *const Value): u32);
+
+/// Returns the id of the type in the store that's structurally equal to the value, adding the
+/// value under a fresh id if there's none. As long as all the types get registered this way,
+/// comparing the ids is as good as comparing the types. `add` and `update` don't look for equal
+/// types, so they can store a type twice under different ids, then it's one of them that's
+/// returned.
+pub(super) fn make_intern<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    (next_id, insert, find_equal): (
+        TypeStoreNextId<'ctx>,
+        TypeStoreInsert<'ctx>,
+        TypeStoreFindEqual<'ctx>,
+    ),
+) -> TypeStoreIntern<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreIntern>(|function, context, module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let found_block = context.append_basic_block(function, "found");
+        let missing_block = context.append_basic_block(function, "missing");
+        builder.position_at_end(entry);
+
+        // Nothing else can add the same type meanwhile, as the store stays locked until it's added
+        build_lock(context, module, &builder, type_store);
+        let value = function.get_first_param().unwrap().into_pointer_value();
+        let found_id = find_equal.build_call(&builder, value);
+        let is_found = builder
+            .build_int_compare(IntPredicate::NE, found_id, context.const_u32(0), "is_found")
+            .unwrap();
+        builder
+            .build_conditional_branch(is_found, found_block, missing_block)
+            .unwrap();
+
+        builder.position_at_end(found_block);
+        build_unlock(context, &builder, type_store);
+        builder.build_return(Some(&found_id)).unwrap();
+
+        builder.position_at_end(missing_block);
+        // The id is fresh, so adding can't fail
+        let id = next_id.build_call(&builder, ());
+        insert.build_call(&builder, (id, value));
+        build_unlock(context, &builder, type_store);
+        builder.build_return(Some(&id)).unwrap();
+    })
+}
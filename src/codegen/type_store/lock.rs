@@ -0,0 +1,66 @@
+use inkwell::{AtomicOrdering, AtomicRMWBinOp, builder::Builder, context::Context, module::Module};
+
+use super::TypeStoreOpaquePointer;
+use crate::codegen::ContextErgonomics;
+
+/// Spins until no other thread holds the lock of the store, then takes it. The lock isn't
+/// reentrant, so the functions of the store that call each other go through the ones that don't
+/// lock.
+pub(super) fn build_lock<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+) {
+    let function = builder.get_insert_block().unwrap().get_parent().unwrap();
+    let spin_block = context.append_basic_block(function, "lock");
+    let wait_block = context.append_basic_block(function, "lock_wait");
+    let locked_block = context.append_basic_block(function, "locked");
+    builder.build_unconditional_branch(spin_block).unwrap();
+
+    builder.position_at_end(spin_block);
+    let exchange = builder
+        .build_cmpxchg(
+            type_store.get_lock_ptr(builder),
+            context.const_u32(0),
+            context.const_u32(1),
+            AtomicOrdering::Acquire,
+            AtomicOrdering::Monotonic,
+        )
+        .unwrap();
+    let is_locked = builder
+        .build_extract_value(exchange, 1, "is_locked")
+        .unwrap()
+        .into_int_value();
+    builder
+        .build_conditional_branch(is_locked, locked_block, wait_block)
+        .unwrap();
+
+    // The holder might have been preempted, so the thread gives way instead of burning its time
+    // slice
+    builder.position_at_end(wait_block);
+    let sched_yield = module.get_function("sched_yield").unwrap_or_else(|| {
+        module.add_function("sched_yield", context.i32_type().fn_type(&[], false), None)
+    });
+    builder.build_call(sched_yield, &[], "").unwrap();
+    builder.build_unconditional_branch(spin_block).unwrap();
+
+    builder.position_at_end(locked_block);
+}
+
+/// Releases the lock taken by `build_lock`, making everything written meanwhile visible to the
+/// thread that takes it next.
+pub(super) fn build_unlock<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+) {
+    builder
+        .build_atomicrmw(
+            AtomicRMWBinOp::Xchg,
+            type_store.get_lock_ptr(builder),
+            context.const_u32(0),
+            AtomicOrdering::Release,
+        )
+        .unwrap();
+}
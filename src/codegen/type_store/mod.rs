@@ -1,4 +1,4 @@
-use inkwell::{context::Context, module::Module, values::PointerValue};
+use inkwell::{context::Context, values::PointerValue};
 
 use crate::codegen::{
     context::{Function, Procedure},
@@ -6,12 +6,38 @@ use crate::codegen::{
     module::ModuleBuilder,
 };
 pub(in crate::codegen) mod add;
+pub(in crate::codegen) mod allocate;
+pub(in crate::codegen) mod assignable;
+pub(in crate::codegen) mod destructor;
+pub(in crate::codegen) mod dump;
+pub(in crate::codegen) mod equal;
+pub(in crate::codegen) mod for_each;
 pub(in crate::codegen) mod get;
+pub(in crate::codegen) mod handle;
+pub(in crate::codegen) mod host;
+pub(in crate::codegen) mod index;
 pub(in crate::codegen) mod initializer;
+pub(in crate::codegen) mod intern;
+mod lock;
+pub(in crate::codegen) mod probe;
+pub(in crate::codegen) mod remove;
+pub(in crate::codegen) mod update;
 
-use add::{TypeStoreAdd, make_add};
+use add::{TypeStoreAdd, TypeStoreInsert, make_add, make_insert};
+use allocate::{TypeStoreAllocateId, TypeStoreNextId, make_allocate_id, make_next_id};
+use assignable::{TypeStoreIsAssignable, make_is_assignable};
+use destructor::make_type_store_destructor;
+use equal::{TypeStoreTypesEqual, make_types_equal};
+use for_each::{TypeStoreForEach, make_for_each};
 use get::{TypeStoreGet, make_get};
+use index::{
+    TypeStoreFindEqual, TypeStoreIndexType, make_find_equal, make_hash_type, make_index_type,
+};
 use initializer::make_type_store_initializer;
+use intern::{TypeStoreIntern, make_intern};
+use probe::{TypeStoreProbe, make_probe};
+use remove::{TypeStoreRemove, make_remove};
+use update::{TypeStoreUpdate, make_update};
 
 use super::module::{self, built_module::ModuleInterface};
 use crate::{
@@ -23,31 +49,102 @@ use crate::{
     make_module_interface,
 };
 
-// TODO This is good enough for a prototype, but should really be replaced with a hashtable of some
-// sorts, because otherwise finding a type will require a linear scan
+// A slot of the hash table, the id and type are only meaningful if it's occupied. The type is
+// kept by pointer, so the pointers handed out stay valid while the table changes
 llvm_struct! {
     struct TypeValue {
+        state: u8,
         id: u32,
-        r#type: Value
+        r#type: *const Value
     }
 }
 
+// A slot of the index, the hash is the one of the type stored under the id when it was indexed
+llvm_struct! {
+    struct IndexedType {
+        state: u8,
+        hash: u32,
+        id: u32
+    }
+}
+
+// An open addressing hash table, keyed by the type id, see `probe` for how the slots are found. It
+// grows before it gets more than 3/4 full, so there's always an empty slot for the probing to end
+// at. The types are also indexed by their structure, for `intern`, see `index`. The functions of
+// the store hold the lock while they use it, see `lock`
 llvm_struct! {
     struct TypeStore {
-        types: *const TypeValue,
-        length: u32,
-        capacity: u32
+        slots: *const TypeValue,
+        count: u32,
+        capacity: u32,
+        // The id `allocate_id` tries first
+        next_id: u32,
+        lock: u32,
+        // Empty until the first type gets stored
+        index: *const IndexedType,
+        // Including the entries of the types that have been replaced or removed since
+        index_count: u32,
+        index_capacity: u32
     }
 }
 
+/// The states of the slots, a zeroed slot is empty
+const SLOT_EMPTY: u8 = 0;
+const SLOT_OCCUPIED: u8 = 1;
+
+/// Has to be a power of two, and it stays one as the table always doubles
+const INITIAL_CAPACITY: u32 = 16;
+
+const MODULE_NAME: &str = "type_store";
+
 // TODO should we just kill TypeStore and rename this to TypeStore?
 make_module_interface!(@builder(TypeStoreBuilderImpl<'ctx>) struct TypeStoreInterface {
     add: TypeStoreAdd<'ctx>,
-    get: TypeStoreGet<'ctx>
+    get: TypeStoreGet<'ctx>,
+    update: TypeStoreUpdate<'ctx>,
+    remove: TypeStoreRemove<'ctx>,
+    for_each: TypeStoreForEach<'ctx>,
+    allocate_id: TypeStoreAllocateId<'ctx>,
+    types_equal: TypeStoreTypesEqual<'ctx>,
+    intern: TypeStoreIntern<'ctx>,
+    is_assignable: TypeStoreIsAssignable<'ctx>
+} @globals(MODULE_NAME) {
+    type_store: TypeStore
 });
 
+// Some of the functions are built up front, as the others call them. The ones that are called
+// with the store already locked don't lock it themselves
 pub(in crate::codegen) struct TypeStoreBuilderImpl<'ctx> {
     type_store: TypeStoreOpaquePointer<'ctx>,
+    probe: TypeStoreProbe<'ctx>,
+    index_type: TypeStoreIndexType<'ctx>,
+    insert: TypeStoreInsert<'ctx>,
+    next_id: TypeStoreNextId<'ctx>,
+    types_equal: TypeStoreTypesEqual<'ctx>,
+    find_equal: TypeStoreFindEqual<'ctx>,
+}
+
+impl<'ctx> TypeStoreBuilderImpl<'ctx> {
+    fn new(module_builder: &ModuleBuilder<'ctx>, type_store: TypeStoreOpaquePointer<'ctx>) -> Self {
+        let probe = make_probe(module_builder);
+        let hash_type = make_hash_type(module_builder);
+        let index_type = make_index_type(module_builder, type_store, hash_type);
+        let types_equal = make_types_equal(module_builder);
+
+        Self {
+            type_store,
+            probe,
+            index_type,
+            insert: make_insert(module_builder, type_store, (probe, index_type)),
+            next_id: make_next_id(module_builder, type_store, probe),
+            types_equal,
+            find_equal: make_find_equal(
+                module_builder,
+                type_store,
+                (probe, hash_type, types_equal),
+            ),
+        }
+    }
 }
 
 impl<'ctx> TypeStoreInterfaceBuilder<'ctx, '_> for TypeStoreBuilderImpl<'ctx> {
@@ -57,7 +154,7 @@ impl<'ctx> TypeStoreInterfaceBuilder<'ctx, '_> for TypeStoreBuilderImpl<'ctx> {
         // TODO remove this argument completely
         _context: &'ctx Context,
     ) -> TypeStoreAdd<'ctx> {
-        make_add(builder, self.type_store)
+        make_add(builder, self.type_store, self.insert)
     }
 
     fn get(
@@ -66,37 +163,101 @@ impl<'ctx> TypeStoreInterfaceBuilder<'ctx, '_> for TypeStoreBuilderImpl<'ctx> {
         // TODO remove this argument completely
         _context: &'ctx Context,
     ) -> TypeStoreGet<'ctx> {
-        make_get(builder, self.type_store)
+        make_get(builder, self.type_store, self.probe)
     }
-}
 
-pub(in crate::codegen) fn register(context: &Context) -> Module<'_> {
-    // TODO this should be initialized at a higher level
-    let module_builder_provider = module::register(context);
+    fn update(
+        &self,
+        builder: &mut ModuleBuilder<'ctx>,
+        _context: &'ctx Context,
+    ) -> TypeStoreUpdate<'ctx> {
+        make_update(builder, self.type_store, (self.probe, self.index_type))
+    }
 
-    // TODO we likely want to do some name mangling and have a naming convention and shit for the
-    // builtin modules
-    let mut module_builder = module_builder_provider.make_builder("type_store");
+    fn remove(
+        &self,
+        builder: &mut ModuleBuilder<'ctx>,
+        _context: &'ctx Context,
+    ) -> TypeStoreRemove<'ctx> {
+        make_remove(builder, self.type_store, self.probe)
+    }
+
+    fn for_each(
+        &self,
+        builder: &mut ModuleBuilder<'ctx>,
+        _context: &'ctx Context,
+    ) -> TypeStoreForEach<'ctx> {
+        make_for_each(builder, self.type_store)
+    }
+
+    fn allocate_id(
+        &self,
+        builder: &mut ModuleBuilder<'ctx>,
+        _context: &'ctx Context,
+    ) -> TypeStoreAllocateId<'ctx> {
+        make_allocate_id(builder, self.type_store, self.next_id)
+    }
+
+    fn types_equal(
+        &self,
+        _builder: &mut ModuleBuilder<'ctx>,
+        _context: &'ctx Context,
+    ) -> TypeStoreTypesEqual<'ctx> {
+        self.types_equal
+    }
+
+    fn intern(
+        &self,
+        builder: &mut ModuleBuilder<'ctx>,
+        _context: &'ctx Context,
+    ) -> TypeStoreIntern<'ctx> {
+        make_intern(
+            builder,
+            self.type_store,
+            (self.next_id, self.insert, self.find_equal),
+        )
+    }
+
+    fn is_assignable(
+        &self,
+        builder: &mut ModuleBuilder<'ctx>,
+        _context: &'ctx Context,
+    ) -> TypeStoreIsAssignable<'ctx> {
+        make_is_assignable(builder, self.type_store, self.probe)
+    }
+
+    fn type_store(&self) -> TypeStoreOpaquePointer<'ctx> {
+        self.type_store
+    }
+}
+
+pub(in crate::codegen) fn register<'ctx>(
+    module_builder_provider: &module::ModuleBuilderProvider<'ctx>,
+    context: &'ctx Context,
+) -> module::ModuleBuilder<'ctx> {
+    // The globals and functions of the module get mangled names, see `mangling`
+    let mut module_builder = module_builder_provider.make_builder(MODULE_NAME);
     let value_store_provider = TypeStoreProvider::new(context);
 
-    // TODO separate add_global (that takes optional intializer, otherwise zeroes) and
-    // add_global_import for importing global from other modules
-    let type_store = module_builder.add_global(value_store_provider.llvm_type(), "type_store");
-    type_store.set_initializer(&value_store_provider.llvm_type().const_zero());
+    // Zeroed, the constructor allocates the storage before anything else runs
+    let type_store =
+        module_builder.add_global(value_store_provider.llvm_type(), "type_store", None);
 
     let type_store_initializer =
         make_type_store_initializer(&module_builder, type_store.as_pointer_value());
 
-    module_builder.add_global_constructor(0, &type_store_initializer, Some(type_store));
+    module_builder.add_global_constructor("type_store", &[], type_store_initializer);
+
+    let type_store_destructor =
+        make_type_store_destructor(&module_builder, type_store.as_pointer_value());
+
+    module_builder.add_global_destructor("type_store", type_store_destructor);
 
-    TypeStoreInterface::register(
-        &TypeStoreBuilderImpl {
-            type_store: TypeStoreProvider::new(context)
-                .opaque_pointer(type_store.as_pointer_value()),
-        },
-        &mut module_builder,
-        context,
+    let type_store_builder = TypeStoreBuilderImpl::new(
+        &module_builder,
+        TypeStoreProvider::new(context).opaque_pointer(type_store.as_pointer_value()),
     );
+    TypeStoreInterface::register(&type_store_builder, &mut module_builder, context);
 
-    module_builder.build()
+    module_builder
 }
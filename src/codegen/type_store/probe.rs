@@ -0,0 +1,107 @@
+use inkwell::{IntPredicate, builder::Builder, context::Context, values::IntValue};
+
+use super::{SLOT_EMPTY, TypeValue, TypeValueProvider};
+use crate::codegen::{ContextErgonomics, module};
+
+make_function_type!(
+    TypeStoreProbe,
+    (slots: *const TypeValue, capacity: u32, id: u32): *const TypeValue
+);
+
+/// Finds the slot of the id in the table: the one holding it if it's there, otherwise the empty
+/// one where it would go. Collisions are resolved by linear probing, so the table must never be
+/// full, or looking up a missing id would never end.
+pub(super) fn make_probe<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+) -> TypeStoreProbe<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreProbe>(|function, context, _module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let loop_block = context.append_basic_block(function, "loop");
+        let check_id_block = context.append_basic_block(function, "check_id");
+        let next_block = context.append_basic_block(function, "next");
+        let found_block = context.append_basic_block(function, "found");
+        builder.position_at_end(entry);
+
+        let slots = function.get_nth_param(0).unwrap().into_pointer_value();
+        let capacity = function.get_nth_param(1).unwrap().into_int_value();
+        let id = function.get_nth_param(2).unwrap().into_int_value();
+
+        // The capacity is always a power of two, so this is the cheap version of the remainder
+        let mask = builder
+            .build_int_sub(capacity, context.const_u32(1), "mask")
+            .unwrap();
+        let start = builder
+            .build_and(build_hash(context, &builder, id), mask, "start")
+            .unwrap();
+        builder.build_unconditional_branch(loop_block).unwrap();
+
+        builder.position_at_end(loop_block);
+        let index = builder.build_phi(context.i32_type(), "index").unwrap();
+        let slot_ptr = unsafe {
+            builder.build_gep(
+                TypeValueProvider::new(context).llvm_type(),
+                slots,
+                &[index.as_basic_value().into_int_value()],
+                "slot",
+            )
+        }
+        .unwrap();
+        let slot = TypeValueProvider::new(context).opaque_pointer(slot_ptr);
+        let is_empty = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                slot.get_state(&builder),
+                context.i8_type().const_int(u64::from(SLOT_EMPTY), false),
+                "is_empty",
+            )
+            .unwrap();
+        builder
+            .build_conditional_branch(is_empty, found_block, check_id_block)
+            .unwrap();
+
+        builder.position_at_end(check_id_block);
+        let is_match = builder
+            .build_int_compare(IntPredicate::EQ, slot.get_id(&builder), id, "is_match")
+            .unwrap();
+        builder
+            .build_conditional_branch(is_match, found_block, next_block)
+            .unwrap();
+
+        builder.position_at_end(next_block);
+        let next_index = builder
+            .build_int_add(
+                index.as_basic_value().into_int_value(),
+                context.const_u32(1),
+                "next_index",
+            )
+            .unwrap();
+        let next_index = builder
+            .build_and(next_index, mask, "wrapped_index")
+            .unwrap();
+        builder.build_unconditional_branch(loop_block).unwrap();
+
+        index.add_incoming(&[(&start, entry), (&next_index, next_block)]);
+
+        builder.position_at_end(found_block);
+        builder.build_return(Some(&slot_ptr)).unwrap();
+    })
+}
+
+/// Spreads the ids over the table, as they're mostly handed out sequentially, and would
+/// otherwise all end up next to each other.
+pub(super) fn build_hash<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    id: IntValue<'ctx>,
+) -> IntValue<'ctx> {
+    // Fibonacci hashing, the multiplier is 2^32 divided by the golden ratio
+    let hash = builder
+        .build_int_mul(id, context.const_u32(0x9E37_79B9), "hash")
+        .unwrap();
+    let high_bits = builder
+        .build_right_shift(hash, context.const_u32(16), false, "high_bits")
+        .unwrap();
+
+    builder.build_xor(hash, high_bits, "mixed_hash").unwrap()
+}
@@ -0,0 +1,201 @@
+use inkwell::{
+    IntPredicate,
+    builder::Builder,
+    context::Context,
+    values::{FunctionValue, IntValue, PointerValue},
+};
+
+use super::{
+    SLOT_EMPTY, SLOT_OCCUPIED, TypeStoreOpaquePointer, TypeValueProvider,
+    lock::{build_lock, build_unlock},
+    probe::{TypeStoreProbe, build_hash},
+};
+use crate::codegen::{ContextErgonomics, context::Function as _, module};
+
+make_function_type!(TypeStoreRemove, (id: u32): u8);
+
+/// Removes the type stored under the id, returning 1, or 0 if there's none.
+///
+/// The probing stops at the first empty slot, so emptying the slot could cut the types that
+/// collided with the removed one off. Instead of leaving a marker behind, the types after it are
+/// shifted back into the hole, as long as that doesn't move them before their own slot.
+pub(super) fn make_remove<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    probe: TypeStoreProbe<'ctx>,
+) -> TypeStoreRemove<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreRemove>(|function, context, module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let found_block = context.append_basic_block(function, "found");
+        let missing_block = context.append_basic_block(function, "missing");
+        builder.position_at_end(entry);
+
+        build_lock(context, module, &builder, type_store);
+        let slot_type = TypeValueProvider::new(context).llvm_type();
+        let slots = type_store.get_slots(&builder);
+        let capacity = type_store.get_capacity(&builder);
+        let mask = builder
+            .build_int_sub(capacity, context.const_u32(1), "mask")
+            .unwrap();
+
+        let slot_ptr = probe.build_call(
+            &builder,
+            (
+                slots,
+                capacity,
+                function.get_first_param().unwrap().into_int_value(),
+            ),
+        );
+        let slot = TypeValueProvider::new(context).opaque_pointer(slot_ptr);
+        let is_occupied = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                slot.get_state(&builder),
+                context.i8_type().const_int(u64::from(SLOT_OCCUPIED), false),
+                "is_occupied",
+            )
+            .unwrap();
+        builder
+            .build_conditional_branch(is_occupied, found_block, missing_block)
+            .unwrap();
+
+        builder.position_at_end(found_block);
+        let count = builder
+            .build_int_sub(
+                type_store.get_count(&builder),
+                context.const_u32(1),
+                "count",
+            )
+            .unwrap();
+        builder
+            .build_store(type_store.get_count_ptr(&builder), count)
+            .unwrap();
+        let removed_index = builder
+            .build_ptr_diff(slot_type, slot_ptr, slots, "removed_index")
+            .unwrap();
+        let removed_index = builder
+            .build_int_truncate(removed_index, context.i32_type(), "removed_index")
+            .unwrap();
+        build_close_hole(context, &builder, function, slots, mask, removed_index);
+        build_unlock(context, &builder, type_store);
+        builder
+            .build_return(Some(&context.i8_type().const_int(1, false)))
+            .unwrap();
+
+        builder.position_at_end(missing_block);
+        build_unlock(context, &builder, type_store);
+        builder
+            .build_return(Some(&context.i8_type().const_zero()))
+            .unwrap();
+    })
+}
+
+/// Shifts the types following the removed one back, until the probing reaches an empty slot,
+/// and empties the slot that's left over.
+fn build_close_hole<'ctx>(
+    context: &'ctx Context,
+    builder: &Builder<'ctx>,
+    function: FunctionValue<'ctx>,
+    slots: PointerValue<'ctx>,
+    mask: IntValue<'ctx>,
+    removed_index: IntValue<'ctx>,
+) {
+    let slot_type = TypeValueProvider::new(context).llvm_type();
+    let shift_block = context.append_basic_block(function, "shift");
+    let check_block = context.append_basic_block(function, "shift_check");
+    let move_block = context.append_basic_block(function, "shift_move");
+    let done_block = context.append_basic_block(function, "shifted");
+    let found_block = builder.get_insert_block().unwrap();
+
+    builder.build_unconditional_branch(shift_block).unwrap();
+
+    // The hole is where the removed type was, or the last type that got shifted back
+    builder.position_at_end(shift_block);
+    let hole = builder.build_phi(context.i32_type(), "hole").unwrap();
+    let index = builder.build_phi(context.i32_type(), "index").unwrap();
+    let hole_value = hole.as_basic_value().into_int_value();
+    let next_index = builder
+        .build_int_add(
+            index.as_basic_value().into_int_value(),
+            context.const_u32(1),
+            "next_index",
+        )
+        .unwrap();
+    let next_index = builder
+        .build_and(next_index, mask, "wrapped_index")
+        .unwrap();
+    let next_ptr = unsafe { builder.build_gep(slot_type, slots, &[next_index], "next") }.unwrap();
+    let next = TypeValueProvider::new(context).opaque_pointer(next_ptr);
+    // There's always an empty slot, as the store never gets full
+    let is_empty = builder
+        .build_int_compare(
+            IntPredicate::EQ,
+            next.get_state(builder),
+            context.i8_type().const_int(u64::from(SLOT_EMPTY), false),
+            "is_empty",
+        )
+        .unwrap();
+    builder
+        .build_conditional_branch(is_empty, done_block, check_block)
+        .unwrap();
+
+    // The type can fill the hole if the hole is no further back than its own slot, the
+    // distances are counted backwards from where it is, wrapping around the end
+    builder.position_at_end(check_block);
+    let own_index = builder
+        .build_and(
+            build_hash(context, builder, next.get_id(builder)),
+            mask,
+            "own_index",
+        )
+        .unwrap();
+    let probe_length = builder
+        .build_int_sub(next_index, own_index, "probe_length")
+        .unwrap();
+    let probe_length = builder
+        .build_and(probe_length, mask, "probe_length")
+        .unwrap();
+    let shift_length = builder
+        .build_int_sub(next_index, hole_value, "shift_length")
+        .unwrap();
+    let shift_length = builder
+        .build_and(shift_length, mask, "shift_length")
+        .unwrap();
+    let can_move = builder
+        .build_int_compare(IntPredicate::ULE, shift_length, probe_length, "can_move")
+        .unwrap();
+    builder
+        .build_conditional_branch(can_move, move_block, shift_block)
+        .unwrap();
+
+    builder.position_at_end(move_block);
+    let hole_ptr =
+        unsafe { builder.build_gep(slot_type, slots, &[hole_value], "hole_slot") }.unwrap();
+    let moved = builder.build_load(slot_type, next_ptr, "moved").unwrap();
+    builder.build_store(hole_ptr, moved).unwrap();
+    builder.build_unconditional_branch(shift_block).unwrap();
+
+    hole.add_incoming(&[
+        (&removed_index, found_block),
+        (&hole_value, check_block),
+        (&next_index, move_block),
+    ]);
+    index.add_incoming(&[
+        (&removed_index, found_block),
+        (&next_index, check_block),
+        (&next_index, move_block),
+    ]);
+
+    builder.position_at_end(done_block);
+    let hole_ptr =
+        unsafe { builder.build_gep(slot_type, slots, &[hole_value], "hole_slot") }.unwrap();
+    builder
+        .build_store(
+            TypeValueProvider::new(context)
+                .opaque_pointer(hole_ptr)
+                .get_state_ptr(builder),
+            context.i8_type().const_int(u64::from(SLOT_EMPTY), false),
+        )
+        .unwrap();
+}
@@ -0,0 +1,74 @@
+use inkwell::IntPredicate;
+
+use super::{
+    SLOT_OCCUPIED, TypeStoreOpaquePointer, TypeValueProvider,
+    index::TypeStoreIndexType,
+    lock::{build_lock, build_unlock},
+    probe::TypeStoreProbe,
+};
+use crate::{
+    bytecode::Value,
+    codegen::{
+        context::{Function as _, Procedure as _},
+        module,
+    },
+};
+
+make_function_type!(TypeStoreUpdate, (id: u32, value: *const Value): u8);
+
+/// Replaces the type stored under the id, returning 1, or 0 if there's none (in which case
+/// nothing gets added). The replaced type is left as it is, for whoever still uses it. The type
+/// isn't interned, so if there's an equal one stored already, `intern` can return either id.
+pub(super) fn make_update<'ctx>(
+    module_builder: &module::ModuleBuilder<'ctx>,
+    type_store: TypeStoreOpaquePointer<'ctx>,
+    (probe, index_type): (TypeStoreProbe<'ctx>, TypeStoreIndexType<'ctx>),
+) -> TypeStoreUpdate<'ctx> {
+    module_builder.build_function::<_, _, TypeStoreUpdate>(|function, context, module| {
+        let builder = context.create_builder();
+        let entry = context.append_basic_block(function, "entry");
+        let found_block = context.append_basic_block(function, "found");
+        let missing_block = context.append_basic_block(function, "missing");
+        builder.position_at_end(entry);
+
+        build_lock(context, module, &builder, type_store);
+        let slot_ptr = probe.build_call(
+            &builder,
+            (
+                type_store.get_slots(&builder),
+                type_store.get_capacity(&builder),
+                function.get_first_param().unwrap().into_int_value(),
+            ),
+        );
+        let slot = TypeValueProvider::new(context).opaque_pointer(slot_ptr);
+        let is_occupied = builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                slot.get_state(&builder),
+                context.i8_type().const_int(u64::from(SLOT_OCCUPIED), false),
+                "is_occupied",
+            )
+            .unwrap();
+        builder
+            .build_conditional_branch(is_occupied, found_block, missing_block)
+            .unwrap();
+
+        builder.position_at_end(found_block);
+        let id = function.get_first_param().unwrap().into_int_value();
+        let value = function.get_nth_param(1).unwrap().into_pointer_value();
+        builder
+            .build_store(slot.get_type_ptr(&builder), value)
+            .unwrap();
+        index_type.build_call(&builder, (id, value));
+        build_unlock(context, &builder, type_store);
+        builder
+            .build_return(Some(&context.i8_type().const_int(1, false)))
+            .unwrap();
+
+        builder.position_at_end(missing_block);
+        build_unlock(context, &builder, type_store);
+        builder
+            .build_return(Some(&context.i8_type().const_zero()))
+            .unwrap();
+    })
+}
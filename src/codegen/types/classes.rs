@@ -1,15 +1,36 @@
+/// The class of a value, which the types can restrict the values they accept to.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 // The value of 0 means no class
-pub(in crate::codegen) struct ClassId(u16);
+pub struct ClassId(u16);
 
 impl ClassId {
-    #[allow(unused)]
-    pub(in crate::codegen) const fn as_u16(self) -> u16 {
+    /// The class with the id, or no class for 0.
+    #[must_use]
+    pub const fn new(id: u16) -> Self {
+        Self(id)
+    }
+
+    #[must_use]
+    pub const fn as_u16(self) -> u16 {
         self.0
     }
 
-    pub(crate) const fn none() -> Self {
+    #[must_use]
+    pub const fn none() -> Self {
         Self(0)
     }
+
+    #[must_use]
+    pub const fn is_none(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether a value of this class can be used where one of the class `to` is expected, which
+    /// is the case if they're the same, or `to` is none.
+    // TODO there's no inheritance yet, once there is the class has to be checked against the bases
+    #[must_use]
+    pub const fn is_accepted_by(self, to: Self) -> bool {
+        self.0 == to.0 || to.is_none()
+    }
 }
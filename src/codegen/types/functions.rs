@@ -11,7 +11,6 @@ use crate::{
             representations::{LlvmRepresentation, OperandValue},
         },
     },
-    llvm_struct,
 };
 
 llvm_struct! {
@@ -10,7 +10,6 @@ use crate::{
         },
         types::classes::ClassId,
     },
-    llvm_struct,
 };
 
 llvm_struct! {
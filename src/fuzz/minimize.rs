@@ -0,0 +1,156 @@
+use std::mem::discriminant;
+
+use lilith::{
+    bytecode::{ByteCode, ConstValue, Expression, Value},
+    interpreter,
+};
+
+use super::{Problem, check};
+
+/// Greedily applies the first reduction that keeps the program failing in the same way, until
+/// there are none left.
+pub(super) fn minimize(mut bytecode: ByteCode, mut problem: Problem) -> (ByteCode, Problem) {
+    // A reduction can easily make the program invalid (e.g. by removing an assignment), and an
+    // invalid program failing is not interesting, unless that's what the problem was to begin with
+    let keep_valid = !matches!(problem, Problem::Rejected(_));
+
+    'reduce: loop {
+        for candidate in reductions(&bytecode) {
+            if keep_valid && interpreter::interpret(&candidate).is_err() {
+                continue;
+            }
+
+            if let Err(candidate_problem) = check(&candidate)
+                && discriminant(&candidate_problem) == discriminant(&problem)
+            {
+                bytecode = candidate;
+                problem = candidate_problem;
+
+                continue 'reduce;
+            }
+        }
+
+        return (bytecode, problem);
+    }
+}
+
+/// All the programs that are one step smaller than this one, roughly ordered from the ones that
+/// remove the most code.
+fn reductions(bytecode: &ByteCode) -> Vec<ByteCode> {
+    let mut candidates = vec![];
+
+    for index in 0..bytecode.functions.len() {
+        let mut candidate = bytecode.clone();
+        candidate.functions.remove(index);
+        candidates.push(candidate);
+    }
+
+    for index in 0..bytecode.functions.len() {
+        for instruction in 0..bytecode.functions[index].instructions.len() {
+            let mut candidate = bytecode.clone();
+            candidate.functions[index].instructions.remove(instruction);
+            candidates.push(candidate);
+        }
+    }
+
+    for instruction in 0..bytecode.instructions.len() {
+        let mut candidate = bytecode.clone();
+        candidate.instructions.remove(instruction);
+        candidates.push(candidate);
+    }
+
+    let mut index = 0;
+    while let Some(value) = nth_value(&mut bytecode.clone(), index).cloned() {
+        for replacement in simplifications(&value) {
+            let mut candidate = bytecode.clone();
+            *nth_value(&mut candidate, index).unwrap() = replacement;
+            candidates.push(candidate);
+        }
+
+        index += 1;
+    }
+
+    // Removing the last instruction of a body leaves it empty, which is never what we want
+    candidates.retain(|candidate| {
+        !candidate.instructions.is_empty()
+            && candidate
+                .functions
+                .iter()
+                .all(|function| !function.instructions.is_empty())
+    });
+
+    candidates
+}
+
+/// The values that could replace this one: a zero literal, or any of the values it's computed
+/// from.
+fn simplifications(value: &Value) -> Vec<Value> {
+    let mut replacements = vec![];
+
+    if !matches!(value, Value::Literal(ConstValue::U64(0))) {
+        replacements.push(Value::Literal(ConstValue::U64(0)));
+    }
+
+    if let Value::Computed(expression) = value {
+        match &**expression {
+            Expression::Assignment(_, value) => replacements.push(value.clone()),
+            Expression::Add(left, right) => replacements.extend([left.clone(), right.clone()]),
+            Expression::Call(_, arguments) => replacements.extend(arguments.iter().cloned()),
+        }
+    }
+
+    replacements
+}
+
+/// Finds the value with the given index, counting all the values in the program, the outer ones
+/// before the ones nested in them.
+fn nth_value(bytecode: &mut ByteCode, mut index: usize) -> Option<&mut Value> {
+    let instructions = bytecode
+        .functions
+        .iter_mut()
+        .flat_map(|function| function.instructions.iter_mut())
+        .chain(bytecode.instructions.iter_mut());
+
+    for instruction in instructions {
+        match nth_value_in_expression(instruction, index) {
+            Ok(value) => return Some(value),
+            Err(count) => index -= count,
+        }
+    }
+
+    None
+}
+
+/// Returns either the value, or the number of values in the expression if there are not enough.
+fn nth_value_in_expression(
+    expression: &mut Expression,
+    mut index: usize,
+) -> Result<&mut Value, usize> {
+    let values: Vec<&mut Value> = match expression {
+        Expression::Assignment(_, value) => vec![value],
+        Expression::Add(left, right) => vec![left, right],
+        Expression::Call(_, arguments) => arguments.iter_mut().collect(),
+    };
+
+    let mut count = 0;
+    for value in values {
+        if index == 0 {
+            return Ok(value);
+        }
+
+        index -= 1;
+        count += 1;
+
+        if let Value::Computed(expression) = value {
+            match nth_value_in_expression(expression, index) {
+                Ok(value) => return Ok(value),
+                Err(nested) => {
+                    index -= nested;
+                    count += nested;
+                }
+            }
+        }
+    }
+
+    Err(count)
+}
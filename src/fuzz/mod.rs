@@ -0,0 +1,142 @@
+mod minimize;
+
+use std::{
+    fmt::Display,
+    panic::{AssertUnwindSafe, catch_unwind},
+};
+
+use lilith::{
+    CodeGen, CodeGenError, CompilationMode, Context, Session,
+    bytecode::{self, ByteCode, GeneratorOptions, Rng, ValidationError},
+    interpreter::{self, Comparison, Divergence, InterpreterError},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct FuzzOptions {
+    /// The seed of the first case, every following case uses the next seed, so any single case
+    /// can be rerun on its own by passing its seed with one iteration
+    pub seed: u64,
+    pub iterations: u64,
+    pub generator: GeneratorOptions,
+}
+
+/// What went wrong with a program that's valid, and so should've compiled and run without issues.
+#[derive(Debug)]
+pub enum Problem {
+    Panic(String),
+    Compilation(CodeGenError),
+    /// The generator produced a program that doesn't pass the validation
+    Rejected(ValidationError),
+    Interpreter(InterpreterError),
+    Divergence {
+        compilation_mode: CompilationMode,
+        divergence: Divergence,
+    },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Panic(message) => write!(f, "the compiler panicked: {message}"),
+            Self::Compilation(error) => write!(f, "failed to compile: {error}"),
+            Self::Rejected(error) => write!(f, "the generated program is not valid: {error}"),
+            Self::Interpreter(error) => write!(f, "{error}"),
+            Self::Divergence {
+                compilation_mode,
+                divergence,
+            } => write!(
+                f,
+                "results diverged in {compilation_mode:?} mode: {divergence}"
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Failure {
+    pub seed: u64,
+    pub problem: Problem,
+    /// The smallest program found that still fails in the same way as the generated one
+    pub minimized: ByteCode,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "case with seed {} failed: {}\nminimized program:\n{}",
+            self.seed, self.problem, self.minimized
+        )
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Generates the programs and checks each of them, stopping at the first failure.
+pub fn run(options: &FuzzOptions) -> Result<(), Box<Failure>> {
+    // The panics are reported as failures, so there's no need for the default hook to also print
+    // them (and it would print them again for every attempt of the minimization)
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+
+    let result = (0..options.iterations).try_for_each(|iteration| {
+        let seed = options.seed.wrapping_add(iteration);
+        let bytecode = bytecode::generate(&mut Rng::new(seed), &options.generator);
+
+        check(&bytecode).map_err(|problem| {
+            let (minimized, problem) = minimize::minimize(bytecode, problem);
+
+            Box::new(Failure {
+                seed,
+                problem,
+                minimized,
+            })
+        })
+    });
+
+    std::panic::set_hook(hook);
+
+    result
+}
+
+/// Compiles the program ahead of time (which verifies all the modules), and then runs it in the
+/// JIT in all the compilation modes, comparing the results with the reference interpreter.
+pub fn check(bytecode: &ByteCode) -> Result<(), Problem> {
+    catch_unwind(AssertUnwindSafe(|| check_unguarded(bytecode))).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic payload".to_string());
+
+        Err(Problem::Panic(message))
+    })
+}
+
+fn check_unguarded(bytecode: &ByteCode) -> Result<(), Problem> {
+    let context = Context::create();
+
+    CodeGen::new(&context)
+        .compile(bytecode.clone())
+        .map_err(Problem::Compilation)?;
+
+    for compilation_mode in [CompilationMode::Eager, CompilationMode::Lazy] {
+        let mut session = Session::new(&context)
+            .map_err(Problem::Compilation)?
+            .with_compilation_mode(compilation_mode);
+
+        match interpreter::compare(&mut session, bytecode.clone()) {
+            Comparison::Agreed(_) => {}
+            Comparison::Rejected(error) => return Err(Problem::Rejected(error)),
+            Comparison::Skipped(error) => return Err(Problem::Interpreter(error)),
+            Comparison::Diverged(divergence) => {
+                return Err(Problem::Divergence {
+                    compilation_mode,
+                    divergence,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
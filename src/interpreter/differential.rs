@@ -0,0 +1,62 @@
+use std::fmt::Display;
+
+use super::{InterpreterError, interpret};
+use crate::{
+    bytecode::{ByteCode, ConstValue, ValidationError},
+    codegen::{CodeGenError, Session},
+};
+
+pub enum Comparison {
+    /// Both the interpreter and the JIT computed the same value
+    Agreed(u64),
+    /// Both the interpreter and the JIT rejected the program for the same reason
+    Rejected(ValidationError),
+    /// The program could not be run in the JIT, because it would crash it
+    Skipped(InterpreterError),
+    Diverged(Divergence),
+}
+
+#[derive(Debug)]
+pub struct Divergence {
+    pub interpreter: Result<ConstValue, InterpreterError>,
+    pub jit: Result<u64, CodeGenError>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the interpreter ")?;
+        match &self.interpreter {
+            Ok(value) => write!(f, "returned {value}")?,
+            Err(error) => write!(f, "failed with \"{error}\"")?,
+        }
+
+        write!(f, ", but the JIT ")?;
+        match &self.jit {
+            Ok(value) => write!(f, "returned {value}"),
+            Err(error) => write!(f, "failed with \"{error}\""),
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// Runs the bytecode both in the reference interpreter and in the session, and compares the
+/// results.
+pub fn compare(session: &mut Session<'_>, bytecode: ByteCode) -> Comparison {
+    let interpreter = interpret(&bytecode);
+    if let Err(error @ InterpreterError::CallDepthExceeded(_)) = interpreter {
+        return Comparison::Skipped(error);
+    }
+
+    match (interpreter, session.execute(bytecode)) {
+        (Ok(ConstValue::U64(expected)), Ok(actual)) if expected == actual => {
+            Comparison::Agreed(actual)
+        }
+        (Err(InterpreterError::Invalid(expected)), Err(CodeGenError::Invalid(actual)))
+            if expected == actual =>
+        {
+            Comparison::Rejected(actual)
+        }
+        (interpreter, jit) => Comparison::Diverged(Divergence { interpreter, jit }),
+    }
+}
@@ -0,0 +1,231 @@
+mod differential;
+
+use std::{collections::HashMap, fmt::Display};
+
+pub use differential::{Comparison, Divergence, compare};
+
+use crate::bytecode::{
+    ByteCode, ConstValue, Expression, FunctionDefinition, Identifier, Import, ValidationError,
+    Value, import_arities, validate_functions_with_externals, validate_modules,
+};
+
+// There's no way to branch in the bytecode, so a call chain this deep is a recursion that would
+// never return (and overflow the stack of the generated code)
+const MAX_CALL_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterpreterError {
+    Invalid(ValidationError),
+    CallDepthExceeded(String),
+}
+
+impl Display for InterpreterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(error) => write!(f, "{error}"),
+            Self::CallDepthExceeded(function) => write!(
+                f,
+                "call to `{function}` exceeds the maximum call depth of {MAX_CALL_DEPTH}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InterpreterError {}
+
+impl From<ValidationError> for InterpreterError {
+    fn from(value: ValidationError) -> Self {
+        Self::Invalid(value)
+    }
+}
+
+/// A straightforward tree-walking interpreter, meant to be the reference for what the generated
+/// code computes.
+///
+/// It follows the same rules as the code generator (including the order in which
+/// errors are found), so any difference in the results points at a bug in one of them.
+pub struct Interpreter<'bytecode> {
+    // The functions callable from each of the modules (with `None` being the code outside of
+    // them), along with the module that defines them
+    namespaces: HashMap<Option<&'bytecode str>, Namespace<'bytecode>>,
+    // The module of the code that's being evaluated
+    module: Option<&'bytecode str>,
+    depth: usize,
+}
+
+type Namespace<'bytecode> =
+    HashMap<&'bytecode str, (Option<&'bytecode str>, &'bytecode FunctionDefinition)>;
+
+impl<'bytecode> Interpreter<'bytecode> {
+    /// # Errors
+    ///
+    /// If the modules don't fit together, or the functions are not valid, even the ones that
+    /// never get called.
+    pub fn new(bytecode: &'bytecode ByteCode) -> Result<Self, InterpreterError> {
+        // The code generator compiles all the functions before running anything, so they have to
+        // be rejected here as well, even if they're never called. The modules are compiled first,
+        // in order, and the rest of the functions after them
+        validate_modules(bytecode)?;
+        for module in &bytecode.modules {
+            validate_functions_with_externals(&module.functions, &import_arities(&module.imports))?;
+        }
+        validate_functions_with_externals(&bytecode.functions, &import_arities(&bytecode.imports))?;
+
+        let defined = |module: Option<&'bytecode str>,
+                       functions: &'bytecode [FunctionDefinition]| {
+            functions
+                .iter()
+                .map(move |function| (function.name.as_str(), (module, function)))
+        };
+        // The imports are validated, so they all name one of these
+        let exported: HashMap<_, _> = bytecode
+            .modules
+            .iter()
+            .flat_map(|module| {
+                defined(Some(module.name.as_str()), &module.functions)
+                    .map(move |(function, target)| ((module.name.as_str(), function), target))
+            })
+            .collect();
+        let imported = |imports: &'bytecode [Import]| {
+            imports.iter().filter_map(|import| {
+                let target = exported.get(&(import.module.as_str(), import.function.as_str()))?;
+
+                Some((import.function.as_str(), *target))
+            })
+        };
+
+        let mut namespaces: HashMap<_, Namespace> = bytecode
+            .modules
+            .iter()
+            .map(|module| {
+                let name = Some(module.name.as_str());
+                let namespace = defined(name, &module.functions)
+                    .chain(imported(&module.imports))
+                    .collect();
+
+                (name, namespace)
+            })
+            .collect();
+        namespaces.insert(
+            None,
+            defined(None, &bytecode.functions)
+                .chain(imported(&bytecode.imports))
+                .collect(),
+        );
+
+        Ok(Self {
+            namespaces,
+            module: None,
+            depth: 0,
+        })
+    }
+
+    /// Runs the instructions with an empty scope and returns the result of the last one.
+    ///
+    /// # Errors
+    ///
+    /// If the instructions are not valid, or the calls nest too deep.
+    pub fn run(
+        &mut self,
+        instructions: &[Expression],
+    ) -> Result<Option<ConstValue>, InterpreterError> {
+        self.evaluate_instructions(instructions, &mut HashMap::new())
+    }
+
+    fn evaluate_instructions(
+        &mut self,
+        instructions: &[Expression],
+        scope: &mut HashMap<Identifier, ConstValue>,
+    ) -> Result<Option<ConstValue>, InterpreterError> {
+        let mut result = None;
+        for instruction in instructions {
+            result = Some(self.evaluate_expression(instruction, scope)?);
+        }
+
+        Ok(result)
+    }
+
+    fn evaluate_expression(
+        &mut self,
+        expression: &Expression,
+        scope: &mut HashMap<Identifier, ConstValue>,
+    ) -> Result<ConstValue, InterpreterError> {
+        match expression {
+            Expression::Assignment(binding, value) => {
+                let value = self.evaluate_value(value, scope)?;
+                scope.insert(*binding, value);
+
+                Ok(value)
+            }
+            Expression::Add(left, right) => {
+                let ConstValue::U64(left) = self.evaluate_value(left, scope)?;
+                let ConstValue::U64(right) = self.evaluate_value(right, scope)?;
+
+                // The generated code uses a plain LLVM add, which wraps around on overflow
+                Ok(ConstValue::U64(left.wrapping_add(right)))
+            }
+            Expression::Call(name, arguments) => {
+                let Some(&(module, function)) = self.namespaces[&self.module].get(name.as_str())
+                else {
+                    return Err(ValidationError::UndefinedFunction(name.clone()).into());
+                };
+
+                if function.arguments.len() != arguments.len() {
+                    return Err(ValidationError::ArityMismatch {
+                        function: name.clone(),
+                        expected: function.arguments.len(),
+                        actual: arguments.len(),
+                    }
+                    .into());
+                }
+
+                let mut function_scope = HashMap::new();
+                for (argument, value) in function.arguments.iter().zip(arguments) {
+                    let value = self.evaluate_value(value, scope)?;
+                    function_scope.insert(*argument, value);
+                }
+
+                if self.depth == MAX_CALL_DEPTH {
+                    return Err(InterpreterError::CallDepthExceeded(name.clone()));
+                }
+
+                self.depth += 1;
+                let caller = std::mem::replace(&mut self.module, module);
+                let result =
+                    self.evaluate_instructions(&function.instructions, &mut function_scope);
+                self.module = caller;
+                self.depth -= 1;
+
+                Ok(result?.expect("functions with empty bodies are rejected by the validation"))
+            }
+        }
+    }
+
+    fn evaluate_value(
+        &mut self,
+        value: &Value,
+        scope: &mut HashMap<Identifier, ConstValue>,
+    ) -> Result<ConstValue, InterpreterError> {
+        match value {
+            Value::Literal(value) => Ok(*value),
+            Value::Local(identifier) => scope
+                .get(identifier)
+                .copied()
+                .ok_or_else(|| ValidationError::UndefinedLocal(*identifier).into()),
+            Value::Computed(expression) => self.evaluate_expression(expression, scope),
+        }
+    }
+}
+
+/// Runs the bytecode as a standalone program and returns its result.
+///
+/// # Errors
+///
+/// If the bytecode is not valid, including when it has no instructions, or the calls nest too
+/// deep.
+pub fn interpret(bytecode: &ByteCode) -> Result<ConstValue, InterpreterError> {
+    // The functions are checked first, as the code generator compiles them before the program
+    Interpreter::new(bytecode)?
+        .run(&bytecode.instructions)?
+        .ok_or_else(|| ValidationError::EmptyProgram.into())
+}
@@ -0,0 +1,29 @@
+//! Compiles lilith bytecode to native code with LLVM, either ahead of time into object files, or
+//! just in time to run it in the current process.
+//!
+//! Programs are embedded through a [`Session`], which keeps the runtime (like the type store)
+//! alive across any number of executed bytecode units. The host can register its own functions
+//! for the bytecode to call with [`Session::register_function`], and call the bytecode functions
+//! back with [`Session::call`]. The arguments and results of both are converted from and into
+//! the runtime values automatically, for any type implementing [`HostValue`].
+//!
+//! The bytecode itself is either parsed from its textual form with [`bytecode::parse`], or
+//! constructed directly from the types in [`bytecode`].
+#![deny(clippy::all, clippy::pedantic, clippy::nursery, warnings)]
+pub mod bytecode;
+#[macro_use]
+mod codegen;
+pub mod interpreter;
+
+// Only for the command line driver, which needs the LLVM modules themselves, the embedders use
+// a `Session`
+#[doc(hidden)]
+pub use codegen::CodeGen;
+pub use codegen::{
+    BytecodeArguments, CallError, ClassId, CodeGenError, CompilationMode, DumpedArgument,
+    DumpedKind, DumpedType, HostFunction, HostValue, IntoHostFunction, RuntimeError, Session,
+    StoredType, TypeReference, TypeStoreDump, TypeStoreHandle, demangle, demangle_symbols,
+    write_object_file,
+};
+/// The sessions and code generators borrow the LLVM context, which has to be created first.
+pub use inkwell::context::Context;
@@ -1,16 +1,26 @@
 #![deny(clippy::all, clippy::pedantic, clippy::nursery, warnings)]
-mod bytecode;
-#[macro_use]
-mod codegen;
-use bytecode::ByteCode;
-use codegen::CodeGen;
-use inkwell::context::Context;
+mod cli;
+mod fuzz;
+use std::process::ExitCode;
 
-fn main() {
-    let context = Context::create();
-    let bytecode = ByteCode::new();
-    let codegen = CodeGen::new(&context);
-    let result = codegen.execute(bytecode);
+use cli::Command;
 
-    println!("result: {result}");
+fn main() -> ExitCode {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+
+    let command = match Command::parse(&arguments) {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("error: {error}\n\n{}", cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match cli::run(command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
 }
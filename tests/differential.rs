@@ -0,0 +1,57 @@
+use lilith::{
+    CompilationMode, Context, Session,
+    bytecode::{ByteCode, ValidationError, parse},
+    interpreter::{Comparison, compare},
+};
+
+fn compare_in_all_modes(bytecode: &ByteCode) -> Vec<Comparison> {
+    let context = Context::create();
+
+    [CompilationMode::Eager, CompilationMode::Lazy]
+        .into_iter()
+        .map(|compilation_mode| {
+            let mut session = Session::new(&context)
+                .unwrap()
+                .with_compilation_mode(compilation_mode);
+
+            compare(&mut session, bytecode.clone())
+        })
+        .collect()
+}
+
+#[test]
+fn samples_agree() {
+    let samples = [
+        (include_str!("../samples/basic.lil"), 111),
+        (include_str!("../samples/functions.lil"), 82),
+        (include_str!("../samples/modules.lil"), 45),
+    ];
+
+    for (source, expected) in samples {
+        for comparison in compare_in_all_modes(&parse(source).unwrap()) {
+            assert!(
+                matches!(comparison, Comparison::Agreed(actual) if actual == expected),
+                "{source}"
+            );
+        }
+    }
+}
+
+#[test]
+fn empty_program_is_rejected() {
+    for comparison in compare_in_all_modes(&parse("").unwrap()) {
+        assert!(matches!(
+            comparison,
+            Comparison::Rejected(ValidationError::EmptyProgram)
+        ));
+    }
+
+    // Defining functions doesn't make for a result either
+    let only_functions = parse("(fn double ($1) (add $1 $1))").unwrap();
+    for comparison in compare_in_all_modes(&only_functions) {
+        assert!(matches!(
+            comparison,
+            Comparison::Rejected(ValidationError::EmptyProgram)
+        ));
+    }
+}
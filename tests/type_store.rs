@@ -0,0 +1,768 @@
+use lilith::{
+    ClassId, CodeGenError, Context, DumpedKind, Session, StoredType,
+    bytecode::{Identifier, TypeId, TypeTag, parse},
+};
+
+fn type_id(id: u32) -> TypeId {
+    TypeId::new(id).unwrap()
+}
+
+fn signature(arity: u32) -> StoredType {
+    StoredType::FunctionSignature {
+        class_id: ClassId::none(),
+        arguments: (0..arity)
+            .map(|argument| (Identifier::new(argument), TypeTag::U64.into()))
+            .collect(),
+        return_type: TypeTag::U64.into(),
+    }
+}
+
+#[test]
+fn finds_every_added_type() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+
+    // Enough of them for the store to grow a couple of times
+    let ids = (2000..3000).chain((0..100).map(|id| 100_000 + id * 4096));
+    for id in ids.clone() {
+        session.add_type(type_id(id), signature(id % 5)).unwrap();
+    }
+
+    for id in ids {
+        assert_eq!(
+            session.get_type(type_id(id)).unwrap(),
+            Some(signature(id % 5)),
+            "type {id}"
+        );
+    }
+}
+
+#[test]
+fn missing_types_are_absent() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+
+    for id in 2000..2100 {
+        session.add_type(type_id(id), signature(1)).unwrap();
+    }
+
+    for id in [1999, 2100, 5000, u32::MAX] {
+        assert_eq!(session.get_type(type_id(id)).unwrap(), None, "type {id}");
+    }
+}
+
+#[test]
+fn duplicates_keep_the_first_type() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+    let id = type_id(2000);
+
+    session.add_type(id, signature(1)).unwrap();
+    let duplicate = session.add_type(id, StoredType::primitive(TypeTag::U64));
+
+    assert!(matches!(duplicate, Err(CodeGenError::DuplicateType(duplicate)) if duplicate == id));
+    assert_eq!(session.get_type(id).unwrap(), Some(signature(1)));
+}
+
+#[test]
+fn executed_functions_stay_callable_among_many_types() {
+    let context = Context::create();
+    let mut session = Session::new(&context).unwrap();
+
+    for id in 5000..6000 {
+        session.add_type(type_id(id), signature(0)).unwrap();
+    }
+
+    session
+        .execute(parse("(fn triple ($1) (add (add $1 $1) $1))\n(call triple 1)").unwrap())
+        .unwrap();
+
+    assert_eq!(session.call::<_, u64>("triple", (14_u64,)).unwrap(), 42);
+}
+
+#[test]
+fn removed_types_are_gone_and_the_rest_stay() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+
+    for id in 2000..3000 {
+        session.add_type(type_id(id), signature(id % 5)).unwrap();
+    }
+    // Every other one, so that the collisions get shifted around
+    for id in (2000..3000).step_by(2) {
+        session.remove_type(type_id(id)).unwrap();
+    }
+
+    for id in 2000..3000 {
+        let expected = (id % 2 == 1).then(|| signature(id % 5));
+        assert_eq!(
+            session.get_type(type_id(id)).unwrap(),
+            expected,
+            "type {id}"
+        );
+    }
+
+    let missing = type_id(2000);
+    assert!(matches!(
+        session.remove_type(missing),
+        Err(CodeGenError::UndefinedType(id)) if id == missing
+    ));
+
+    // The removed ids are free to be used again
+    session.add_type(missing, signature(3)).unwrap();
+    assert_eq!(session.get_type(missing).unwrap(), Some(signature(3)));
+}
+
+#[test]
+fn updates_only_replace_existing_types() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+    let id = type_id(2000);
+
+    assert!(matches!(
+        session.update_type(id, signature(1)),
+        Err(CodeGenError::UndefinedType(missing)) if missing == id
+    ));
+    assert_eq!(session.get_type(id).unwrap(), None);
+
+    session.add_type(id, signature(1)).unwrap();
+    session.update_type(id, signature(2)).unwrap();
+    assert_eq!(session.get_type(id).unwrap(), Some(signature(2)));
+}
+
+#[test]
+fn lists_all_the_types() {
+    let context = Context::create();
+    let mut session = Session::new(&context).unwrap();
+
+    for id in (2000..2100).rev() {
+        session.add_type(type_id(id), signature(id % 5)).unwrap();
+    }
+    session.remove_type(type_id(2050)).unwrap();
+    session
+        .execute(parse("(fn pair ($1 $2) (add $1 $2))\n(call pair 1 2)").unwrap())
+        .unwrap();
+
+    let types = session.types().unwrap();
+    let added: Vec<_> = types
+        .iter()
+        .filter(|(id, _)| id.as_u32() >= 2000)
+        .cloned()
+        .collect();
+    let expected: Vec<_> = (2000..2100)
+        .filter(|id| *id != 2050)
+        .map(|id| (type_id(id), signature(id % 5)))
+        .collect();
+    assert_eq!(added, expected);
+
+    // The signature of the executed function is in there as well
+    let pair = StoredType::FunctionSignature {
+        class_id: ClassId::none(),
+        arguments: vec![
+            (Identifier::new(1), TypeTag::U64.into()),
+            (Identifier::new(2), TypeTag::U64.into()),
+        ],
+        return_type: TypeTag::U64.into(),
+    };
+    assert!(
+        types
+            .iter()
+            .any(|(id, r#type)| id.as_u32() < 2000 && *r#type == pair)
+    );
+}
+
+#[test]
+fn ids_of_the_tags_are_reserved() {
+    assert_eq!(TypeId::new(0), None);
+    assert_eq!(TypeId::new(TypeId::RESERVED - 1), None);
+    assert_eq!(
+        TypeId::new(TypeId::RESERVED).map(TypeId::as_u32),
+        Some(TypeId::RESERVED)
+    );
+
+    assert_eq!(format!("{:?}", TypeId::from(TypeTag::U64)), "TypeId(U64)");
+    assert_eq!(format!("{:?}", type_id(1024)), "TypeId(1024)");
+}
+
+#[test]
+fn the_store_rejects_the_ids_of_the_tags() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+    let id = TypeId::from(TypeTag::U64);
+
+    assert!(matches!(
+        session.add_type(id, signature(1)),
+        Err(CodeGenError::ReservedType(reserved)) if reserved == id
+    ));
+    assert!(matches!(
+        session.update_type(id, signature(1)),
+        Err(CodeGenError::ReservedType(reserved)) if reserved == id
+    ));
+    assert!(matches!(
+        session.remove_type(id),
+        Err(CodeGenError::ReservedType(reserved)) if reserved == id
+    ));
+    // The id still stands for the primitive
+    assert!(session.is_assignable(id, id).unwrap());
+    assert!(!session.is_assignable(id, type_id(1024)).unwrap());
+    assert_eq!(session.types().unwrap(), vec![]);
+}
+
+#[test]
+fn allocated_ids_are_fresh() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+
+    // Right where the allocation starts, so it has to skip them
+    for id in TypeId::RESERVED..TypeId::RESERVED + 10 {
+        session.add_type(type_id(id), signature(0)).unwrap();
+    }
+
+    let mut allocated = vec![];
+    for _ in 0..100 {
+        let id = session.allocate_type_id().unwrap();
+        assert!(id.as_u32() >= TypeId::RESERVED + 10, "{id:?}");
+        assert_eq!(session.get_type(id).unwrap(), None, "{id:?}");
+        assert!(!allocated.contains(&id), "{id:?}");
+
+        session.add_type(id, signature(1)).unwrap();
+        allocated.push(id);
+    }
+}
+
+#[test]
+fn equal_signatures_are_interned_once() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+
+    let pair = session.intern_type(signature(2)).unwrap();
+    assert_eq!(session.intern_type(signature(2)).unwrap(), pair);
+    assert_eq!(session.get_type(pair).unwrap(), Some(signature(2)));
+
+    // Anything that's different makes for another type
+    let renamed = StoredType::FunctionSignature {
+        class_id: ClassId::none(),
+        arguments: vec![
+            (Identifier::new(0), TypeTag::U64.into()),
+            (Identifier::new(5), TypeTag::U64.into()),
+        ],
+        return_type: TypeTag::U64.into(),
+    };
+    let returning_signature = StoredType::FunctionSignature {
+        class_id: ClassId::none(),
+        arguments: vec![
+            (Identifier::new(0), TypeTag::U64.into()),
+            (Identifier::new(1), TypeTag::U64.into()),
+        ],
+        return_type: TypeTag::FunctionSignature.into(),
+    };
+    let others = [
+        session.intern_type(signature(1)).unwrap(),
+        session.intern_type(renamed).unwrap(),
+        session.intern_type(returning_signature).unwrap(),
+        session
+            .intern_type(StoredType::primitive(TypeTag::U64))
+            .unwrap(),
+    ];
+    for (index, other) in others.iter().enumerate() {
+        assert_ne!(*other, pair);
+        assert!(!others[..index].contains(other), "{other:?}");
+    }
+
+    // The types added by the host are found as well
+    session.add_type(type_id(5000), signature(4)).unwrap();
+    assert_eq!(session.intern_type(signature(4)).unwrap(), type_id(5000));
+}
+
+#[test]
+fn interned_types_are_found_after_the_index_is_rebuilt() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+
+    // Enough of them for the index to be rebuilt a couple of times
+    let ids: Vec<_> = (0..200)
+        .map(|arity| session.intern_type(signature(arity)).unwrap())
+        .collect();
+    for (arity, id) in (0..).zip(&ids) {
+        assert_eq!(session.intern_type(signature(arity)).unwrap(), *id);
+    }
+
+    // Replaced and removed types aren't found by what they used to be
+    session.update_type(ids[3], signature(1000)).unwrap();
+    session.remove_type(ids[4]).unwrap();
+    assert_eq!(session.intern_type(signature(1000)).unwrap(), ids[3]);
+    for arity in [3, 4] {
+        let id = session.intern_type(signature(arity)).unwrap();
+        assert!(!ids.contains(&id), "{id:?}");
+    }
+}
+
+#[test]
+fn functions_with_the_same_arguments_share_their_signature() {
+    let context = Context::create();
+    let mut session = Session::new(&context).unwrap();
+
+    session
+        .execute(
+            parse(
+                "(fn first ($1 $2) (add $1 1))\n(fn second ($1 $2) (add $2 1))\n(call first 1 2)",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    session
+        .execute(parse("(fn third ($1 $2) (add $1 $2))\n(call third 1 2)").unwrap())
+        .unwrap();
+
+    let signature = StoredType::FunctionSignature {
+        class_id: ClassId::none(),
+        arguments: vec![
+            (Identifier::new(1), TypeTag::U64.into()),
+            (Identifier::new(2), TypeTag::U64.into()),
+        ],
+        return_type: TypeTag::U64.into(),
+    };
+    let types = session.types().unwrap();
+    assert_eq!(
+        types
+            .iter()
+            .filter(|(_, r#type)| *r#type == signature)
+            .count(),
+        1
+    );
+
+    // And the functions still get called through it
+    for (function, result) in [("first", 4), ("second", 5), ("third", 7)] {
+        assert_eq!(
+            session.call::<_, u64>(function, (3_u64, 4_u64)).unwrap(),
+            result
+        );
+    }
+}
+
+#[test]
+fn assignability_matches_between_the_runtime_and_the_host() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+
+    let unary = type_id(3000);
+    let renamed_unary = type_id(3001);
+    let binary = type_id(3002);
+    let takes_unary = type_id(3003);
+    let takes_renamed_unary = type_id(3004);
+    let returns_unary = type_id(3005);
+    let returns_binary = type_id(3006);
+    let u64_type = type_id(3007);
+    let takes_signature = type_id(3008);
+    let missing = type_id(3009);
+
+    let function =
+        |arguments: &[(u32, TypeId)], return_type: TypeId| StoredType::FunctionSignature {
+            class_id: ClassId::none(),
+            arguments: arguments
+                .iter()
+                .map(|(name, type_id)| (Identifier::new(*name), *type_id))
+                .collect(),
+            return_type,
+        };
+    let types = [
+        (unary, signature(1)),
+        (
+            renamed_unary,
+            function(&[(7, TypeTag::U64.into())], TypeTag::U64.into()),
+        ),
+        (binary, signature(2)),
+        (takes_unary, function(&[(0, unary)], TypeTag::U64.into())),
+        (
+            takes_renamed_unary,
+            function(&[(0, renamed_unary)], TypeTag::U64.into()),
+        ),
+        (returns_unary, function(&[], unary)),
+        (returns_binary, function(&[], binary)),
+        (u64_type, StoredType::primitive(TypeTag::U64)),
+        (
+            takes_signature,
+            function(
+                &[(0, TypeTag::FunctionSignature.into())],
+                TypeTag::U64.into(),
+            ),
+        ),
+    ];
+    for (id, r#type) in types.clone() {
+        session.add_type(id, r#type).unwrap();
+    }
+    let lookup = |id: TypeId| {
+        types
+            .iter()
+            .find(|(type_id, _)| *type_id == id)
+            .map(|(_, r#type)| r#type.clone())
+    };
+
+    let expected = [
+        (unary, unary, true),
+        // The names of the arguments don't matter
+        (unary, renamed_unary, true),
+        (renamed_unary, unary, true),
+        (takes_unary, takes_renamed_unary, true),
+        (unary, binary, false),
+        (binary, unary, false),
+        (returns_unary, returns_binary, false),
+        (takes_unary, returns_unary, false),
+        // A primitive is the same wherever it's stored
+        (TypeTag::U64.into(), u64_type, true),
+        (u64_type, TypeTag::U64.into(), true),
+        (TypeTag::U64.into(), unary, false),
+        (
+            TypeTag::U64.into(),
+            TypeTag::FunctionSignature.into(),
+            false,
+        ),
+        // The id of the tag isn't any signature in particular
+        (TypeTag::FunctionSignature.into(), unary, false),
+        (unary, TypeTag::FunctionSignature.into(), false),
+        (takes_signature, takes_unary, false),
+        (takes_unary, takes_signature, false),
+        (missing, missing, true),
+        (missing, unary, false),
+        (unary, missing, false),
+    ];
+    for (from, to, assignable) in expected {
+        assert_eq!(
+            session.is_assignable(from, to).unwrap(),
+            assignable,
+            "{from:?} to {to:?}"
+        );
+        assert_eq!(
+            StoredType::is_assignable(from, to, &lookup),
+            assignable,
+            "{from:?} to {to:?}"
+        );
+    }
+
+    // And both agree on everything else as well
+    let ids: Vec<TypeId> = types
+        .iter()
+        .map(|(id, _)| *id)
+        .chain([
+            TypeTag::U64.into(),
+            TypeTag::FunctionSignature.into(),
+            missing,
+        ])
+        .collect();
+    for from in &ids {
+        for to in &ids {
+            assert_eq!(
+                session.is_assignable(*from, *to).unwrap(),
+                StoredType::is_assignable(*from, *to, &lookup),
+                "{from:?} to {to:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn signatures_referring_to_each_other_are_checked() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+
+    let function = |arguments: &[TypeId]| StoredType::FunctionSignature {
+        class_id: ClassId::none(),
+        arguments: (0..)
+            .zip(arguments)
+            .map(|(name, type_id)| (Identifier::new(name), *type_id))
+            .collect(),
+        return_type: TypeTag::U64.into(),
+    };
+    let [first, second, third, fourth, fifth, sixth] =
+        std::array::from_fn(|_| session.allocate_type_id().unwrap());
+    // Each pair takes the other one as its argument, the last one with an extra argument
+    let types = [
+        (first, function(&[second])),
+        (second, function(&[first])),
+        (third, function(&[fourth])),
+        (fourth, function(&[third])),
+        (fifth, function(&[sixth])),
+        (sixth, function(&[fifth, TypeTag::U64.into()])),
+    ];
+    for (id, r#type) in types.clone() {
+        session.add_type(id, r#type).unwrap();
+    }
+    let lookup = |id: TypeId| {
+        types
+            .iter()
+            .find(|(type_id, _)| *type_id == id)
+            .map(|(_, r#type)| r#type.clone())
+    };
+
+    for (from, to, assignable) in [
+        (first, third, true),
+        (third, first, true),
+        (second, fourth, true),
+        (first, fifth, false),
+        (fifth, first, false),
+    ] {
+        assert_eq!(
+            session.is_assignable(from, to).unwrap(),
+            assignable,
+            "{from:?} to {to:?}"
+        );
+        assert_eq!(
+            StoredType::is_assignable(from, to, &lookup),
+            assignable,
+            "{from:?} to {to:?}"
+        );
+    }
+}
+
+#[test]
+fn classes_are_checked_on_the_runtime_and_the_host() {
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+
+    let class = |class_id| StoredType::Primitive {
+        tag: TypeTag::U64,
+        class_id: ClassId::new(class_id),
+    };
+    let signature = |class_id| StoredType::FunctionSignature {
+        class_id: ClassId::new(class_id),
+        arguments: vec![],
+        return_type: TypeTag::U64.into(),
+    };
+    let types = [
+        (type_id(3000), class(1)),
+        (type_id(3001), class(2)),
+        (type_id(3002), signature(1)),
+        (type_id(3003), signature(0)),
+    ];
+    for (id, r#type) in types.clone() {
+        session.add_type(id, r#type).unwrap();
+    }
+    let lookup = |id: TypeId| {
+        types
+            .iter()
+            .find(|(type_id, _)| *type_id == id)
+            .map(|(_, r#type)| r#type.clone())
+    };
+
+    for (from, to, assignable) in [
+        // Anything of the class goes where there's no class expected, but not the other way
+        (type_id(3000), TypeTag::U64.into(), true),
+        (TypeTag::U64.into(), type_id(3000), false),
+        (type_id(3000), type_id(3001), false),
+        (type_id(3002), type_id(3003), true),
+        (type_id(3003), type_id(3002), false),
+    ] {
+        assert_eq!(
+            session.is_assignable(from, to).unwrap(),
+            assignable,
+            "{from:?} to {to:?}"
+        );
+        assert_eq!(
+            StoredType::is_assignable(from, to, &lookup),
+            assignable,
+            "{from:?} to {to:?}"
+        );
+    }
+    assert_eq!(session.get_type(type_id(3002)).unwrap(), Some(signature(1)));
+}
+
+#[test]
+fn survives_concurrent_use() {
+    const THREADS: u32 = 8;
+    const TYPES: u32 = 500;
+
+    let context = Context::create();
+    let session = Session::new(&context).unwrap();
+    let type_store = session.type_store().unwrap();
+
+    let (interned, allocated): (Vec<_>, Vec<_>) = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                scope.spawn(move || {
+                    // Each thread has its own ids, enough of them to make the store grow while
+                    // the others use it
+                    let ids: Vec<_> = (0..TYPES)
+                        .map(|index| type_id(100_000 + thread * TYPES + index))
+                        .collect();
+                    let mut interned = vec![];
+                    let mut allocated = vec![];
+
+                    for (index, id) in (0..).zip(&ids) {
+                        type_store.add_type(*id, signature(index % 4)).unwrap();
+                        interned.push(type_store.intern_type(signature(4 + index % 4)));
+                        allocated.push(type_store.allocate_type_id());
+
+                        assert_eq!(
+                            type_store.get_type(*id).unwrap(),
+                            Some(signature(index % 4))
+                        );
+                        assert!(type_store.is_assignable(*id, *id));
+                    }
+
+                    // Every other one is removed and the rest replaced, so the types get shifted
+                    // around as well
+                    for (index, id) in (0..).zip(&ids) {
+                        if index % 2 == 0 {
+                            type_store.remove_type(*id).unwrap();
+                        } else {
+                            type_store.update_type(*id, signature(8)).unwrap();
+                        }
+                    }
+
+                    (interned, allocated)
+                })
+            })
+            .collect();
+
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .unzip()
+    });
+
+    // The threads interned the same signatures, so they all got the same ids for them
+    let interned_ids: std::collections::HashSet<_> = interned.iter().flatten().collect();
+    assert_eq!(interned_ids.len(), 4);
+    for (index, id) in (0..).zip(&interned[0]) {
+        assert_eq!(
+            type_store.get_type(*id).unwrap(),
+            Some(signature(4 + index % 4))
+        );
+    }
+
+    // But none of the allocated ids was handed out twice
+    let allocated: Vec<_> = allocated.into_iter().flatten().collect();
+    let allocated_ids: std::collections::HashSet<_> = allocated.iter().collect();
+    assert_eq!(allocated_ids.len(), allocated.len());
+
+    for index in 0..THREADS * TYPES {
+        let id = type_id(100_000 + index);
+        let expected = (index % 2 == 1).then(|| signature(8));
+        assert_eq!(type_store.get_type(id).unwrap(), expected, "type {id:?}");
+    }
+    assert_eq!(
+        session.types().unwrap().len(),
+        interned_ids.len() + (THREADS * TYPES / 2) as usize
+    );
+}
+
+#[test]
+fn dumps_the_types_with_their_names() {
+    let context = Context::create();
+    let mut session = Session::new(&context).unwrap();
+
+    session
+        .execute(parse("(fn pair ($1 $2) (add $1 $2))\n(call pair 1 2)").unwrap())
+        .unwrap();
+    let pair = session.intern_type(signature(2)).unwrap();
+    // Refers to the other one, and to a type that isn't there
+    session
+        .add_type(
+            type_id(9000),
+            StoredType::FunctionSignature {
+                class_id: ClassId::none(),
+                arguments: vec![
+                    (Identifier::new(3), pair),
+                    (Identifier::new(4), type_id(9999)),
+                ],
+                return_type: TypeTag::U64.into(),
+            },
+        )
+        .unwrap();
+    // And to itself
+    session
+        .add_type(
+            type_id(9001),
+            StoredType::FunctionSignature {
+                class_id: ClassId::none(),
+                arguments: vec![(Identifier::new(1), type_id(9001))],
+                return_type: TypeTag::U64.into(),
+            },
+        )
+        .unwrap();
+    // With a class of its own
+    session
+        .add_type(
+            type_id(9002),
+            StoredType::Primitive {
+                tag: TypeTag::U64,
+                class_id: ClassId::new(7),
+            },
+        )
+        .unwrap();
+
+    let dump = session.dump_types().unwrap();
+    let names: Vec<_> = dump
+        .types
+        .iter()
+        .map(|r#type| r#type.name.as_str())
+        .collect();
+    assert!(names.contains(&"fn($1: u64, $2: u64) -> u64"), "{names:?}");
+    assert!(names.contains(&"fn($0: u64, $1: u64) -> u64"), "{names:?}");
+    assert!(
+        names.contains(&"fn($3: fn($0: u64, $1: u64) -> u64, $4: #9999) -> u64"),
+        "{names:?}"
+    );
+    assert!(names.contains(&"fn($1: #9001) -> u64"), "{names:?}");
+
+    let referring = dump
+        .types
+        .iter()
+        .find(|r#type| r#type.id == type_id(9000))
+        .unwrap();
+    let DumpedKind::FunctionSignature {
+        arguments,
+        return_type,
+    } = &referring.kind
+    else {
+        panic!("{referring:?}");
+    };
+    assert_eq!(arguments[0].name, "$3");
+    assert_eq!(arguments[0].r#type.id, pair);
+    assert_eq!(return_type.name, "u64");
+    assert!(referring.class_id.is_none());
+
+    let json = dump.to_json();
+    assert!(json.starts_with("{\"types\":[{\"id\":"), "{json}");
+    assert!(
+        json.contains(
+            "{\"id\":9001,\"name\":\"fn($1: #9001) -> u64\",\"class_id\":0,\
+             \"kind\":\"function_signature\",\
+             \"arguments\":[{\"name\":\"$1\",\"type\":{\"id\":9001,\"name\":\"fn($1: #9001) -> u64\"}}],\
+             \"return_type\":{\"id\":16,\"name\":\"u64\"}}"
+        ),
+        "{json}"
+    );
+    assert!(
+        json.contains(
+            "{\"id\":9002,\"name\":\"u64\",\"class_id\":7,\"kind\":\"primitive\",\"tag\":\"u64\"}"
+        ),
+        "{json}"
+    );
+
+    let table = dump.to_string();
+    assert!(table.starts_with("  id  kind"), "{table}");
+    assert!(
+        table
+            .lines()
+            .any(|line| line == "9001  function signature  fn($1: #9001) -> u64"),
+        "{table}"
+    );
+    assert_eq!(table.lines().count(), dump.types.len() + 1);
+}
+
+#[test]
+fn main_is_interned_once_with_its_signature() {
+    let context = Context::create();
+    let mut session = Session::new(&context).unwrap();
+
+    for _ in 0..3 {
+        assert_eq!(session.execute(parse("(add 1 2)").unwrap()).unwrap(), 3);
+    }
+
+    let types = session.types().unwrap();
+    assert_eq!(
+        types.iter().map(|(_, r#type)| r#type).collect::<Vec<_>>(),
+        [&signature(0)]
+    );
+}